@@ -0,0 +1,4 @@
+-- Objects written before these columns existed have no value for them
+ALTER TABLE objects ADD COLUMN sha1_checksum TEXT NOT NULL DEFAULT '';
+ALTER TABLE objects ADD COLUMN crc32_checksum TEXT NOT NULL DEFAULT '';
+ALTER TABLE objects ADD COLUMN crc32c_checksum TEXT NOT NULL DEFAULT '';
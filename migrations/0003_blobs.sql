@@ -0,0 +1,9 @@
+-- Object bodies are stored once per distinct sha256 and shared by every
+-- object with that content
+CREATE TABLE IF NOT EXISTS blobs (
+    sha256 TEXT PRIMARY KEY,
+    storage_path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    ref_count INTEGER NOT NULL,
+    created_at TEXT NOT NULL
+);
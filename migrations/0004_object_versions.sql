@@ -0,0 +1,55 @@
+-- Versioning state of a bucket: '' until versioning is first configured,
+-- then 'Enabled' or 'Suspended'
+ALTER TABLE buckets ADD COLUMN versioning TEXT NOT NULL DEFAULT '';
+
+-- Objects become object versions. A key can now have many rows, one per
+-- version, so the (bucket_id, key) constraint has to go, which SQLite can
+-- only do by rebuilding the table. Existing objects become the 'null'
+-- version, the one unversioned buckets keep overwriting.
+CREATE TABLE objects_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    bucket_id INTEGER NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    version_id TEXT NOT NULL,
+    is_latest INTEGER NOT NULL,
+    is_delete_marker INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    etag TEXT NOT NULL,
+    md5_checksum TEXT NOT NULL,
+    sha256_checksum TEXT NOT NULL,
+    sha1_checksum TEXT NOT NULL DEFAULT '',
+    crc32_checksum TEXT NOT NULL DEFAULT '',
+    crc32c_checksum TEXT NOT NULL DEFAULT '',
+    storage_path TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    modified_at TEXT NOT NULL,
+    UNIQUE (bucket_id, key, version_id)
+);
+
+INSERT INTO objects_new (id, bucket_id, key, version_id, is_latest, is_delete_marker, size, content_type, etag,
+                         md5_checksum, sha256_checksum, sha1_checksum, crc32_checksum, crc32c_checksum,
+                         storage_path, created_at, modified_at)
+SELECT id, bucket_id, key, 'null', 1, 0, size, content_type, etag,
+       md5_checksum, sha256_checksum, sha1_checksum, crc32_checksum, crc32c_checksum,
+       storage_path, created_at, modified_at
+FROM objects;
+
+-- object_metadata is rebuilt too, dropping objects under it would cascade
+CREATE TABLE object_metadata_new (
+    object_id INTEGER NOT NULL REFERENCES objects_new(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (object_id, key)
+);
+
+INSERT INTO object_metadata_new (object_id, key, value) SELECT object_id, key, value FROM object_metadata;
+
+DROP TABLE object_metadata;
+DROP TABLE objects;
+
+ALTER TABLE objects_new RENAME TO objects;
+ALTER TABLE object_metadata_new RENAME TO object_metadata;
+
+-- Listings only look at the current version of each key
+CREATE INDEX idx_objects_latest ON objects (bucket_id, is_latest, key);
@@ -0,0 +1,13 @@
+-- Lifecycle rules of a bucket as JSON, NULL when it has none
+ALTER TABLE buckets ADD COLUMN lifecycle TEXT;
+
+-- Object tags, set with x-amz-tagging, which lifecycle rules can filter on
+CREATE TABLE object_tags (
+    object_id INTEGER NOT NULL REFERENCES objects(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (object_id, key)
+);
+
+-- The expiration worker looks for objects by age
+CREATE INDEX idx_objects_modified ON objects (bucket_id, modified_at);
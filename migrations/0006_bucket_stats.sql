@@ -0,0 +1,8 @@
+-- Running totals of the object versions a bucket stores, delete markers
+-- aside, kept up to date as objects are written and deleted
+ALTER TABLE buckets ADD COLUMN object_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE buckets ADD COLUMN total_size INTEGER NOT NULL DEFAULT 0;
+
+UPDATE buckets SET
+    object_count = (SELECT COUNT(*) FROM objects WHERE bucket_id = buckets.id AND is_delete_marker = 0),
+    total_size = (SELECT COALESCE(SUM(size), 0) FROM objects WHERE bucket_id = buckets.id AND is_delete_marker = 0);
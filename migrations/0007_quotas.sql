@@ -0,0 +1,17 @@
+-- Canonical id of who created a bucket: the generated id of a user, or `root:`
+-- and the access key id for the root credentials. NULL for buckets created
+-- without authentication. Owner quotas are keyed by the same id.
+ALTER TABLE buckets ADD COLUMN owner TEXT;
+
+-- Limits on what a bucket may hold, NULL for no limit
+ALTER TABLE buckets ADD COLUMN max_objects INTEGER;
+ALTER TABLE buckets ADD COLUMN max_size INTEGER;
+
+-- Limits on what the buckets of one owner may hold together
+CREATE TABLE owner_quotas (
+    owner TEXT PRIMARY KEY,
+    max_objects INTEGER,
+    max_size INTEGER
+);
+
+CREATE INDEX idx_buckets_owner ON buckets (owner);
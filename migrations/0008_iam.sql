@@ -0,0 +1,51 @@
+-- Users with their own access keys, limited by the policies attached to them
+-- and to their groups. The root credentials of the config file are not here.
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    -- what the user owns buckets and is granted ACLs by, generated so that a
+    -- user created later under the same name doesn't inherit them
+    canonical_id TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE group_members (
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX idx_group_members_user ON group_members (user_id);
+
+-- The secret is needed in plaintext to check signatures, so it is encrypted
+-- with the master key rather than hashed: a 12 byte nonce then AES-256-GCM
+-- ciphertext
+CREATE TABLE access_keys (
+    access_key_id TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    secret BLOB NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_access_keys_user ON access_keys (user_id);
+
+-- Inline JSON policy documents, by name
+CREATE TABLE user_policies (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    document TEXT NOT NULL,
+    PRIMARY KEY (user_id, name)
+);
+
+CREATE TABLE group_policies (
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    document TEXT NOT NULL,
+    PRIMARY KEY (group_id, name)
+);
@@ -0,0 +1,2 @@
+-- Bucket policy as the JSON document it was put with
+ALTER TABLE buckets ADD COLUMN policy TEXT;
@@ -0,0 +1,3 @@
+-- Access control lists as JSON, NULL for the default: the owner has full control
+ALTER TABLE buckets ADD COLUMN acl TEXT;
+ALTER TABLE objects ADD COLUMN acl TEXT;
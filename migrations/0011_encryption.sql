@@ -0,0 +1,8 @@
+-- Default encryption of new objects in a bucket, 'AES256' or NULL for none
+ALTER TABLE buckets ADD COLUMN encryption TEXT;
+
+-- How an object body is encrypted, NULL for plaintext, and its data key
+-- sealed with the master key. Encrypted bodies are never shared, they have
+-- no row in blobs.
+ALTER TABLE objects ADD COLUMN encryption TEXT;
+ALTER TABLE objects ADD COLUMN data_key BLOB;
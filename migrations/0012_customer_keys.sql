@@ -0,0 +1,4 @@
+-- For objects encrypted with a key the client holds (SSE-C): a random salt
+-- followed by a hash of the key under it. Reads have to give a key with the
+-- same hash, the key itself is never stored.
+ALTER TABLE objects ADD COLUMN customer_key_hash BLOB;
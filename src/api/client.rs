@@ -0,0 +1,56 @@
+//! Where a request comes from. Behind a reverse proxy the connection is the
+//! proxy's, the client's address and scheme are in the `X-Forwarded-For` and
+//! `X-Forwarded-Proto` it adds. Any client can send those headers too, so
+//! they are only believed on connections from a configured trusted proxy.
+
+use std::net::IpAddr;
+
+use axum::http::HeaderMap;
+
+use crate::auth::IpNetwork;
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct Client {
+    pub ip: Option<IpAddr>,
+    /// Whether the client used HTTPS, only ever through a trusted proxy as
+    /// the server itself speaks plain HTTP
+    pub secure_transport: bool,
+}
+
+
+/// The client of a request on a connection from `peer`
+pub fn client(peer: Option<IpAddr>, headers: &HeaderMap, trusted_proxies: &[IpNetwork]) -> Client {
+    let trusted = |ip: IpAddr| trusted_proxies.iter().any(|network| network.contains(ip));
+
+    if !peer.is_some_and(trusted) {
+        return Client { ip: peer, secure_transport: false };
+    }
+
+    // every proxy appends the address it got the request from, the last one
+    // that isn't a trusted proxy is the client. What comes before it is
+    // whatever the client sent.
+    let forwarded: Vec<IpAddr> = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|ip| ip.trim().parse().ok())
+        .collect();
+
+    let ip = forwarded.iter().rev().find(|ip| !trusted(**ip)).or(forwarded.first()).copied().or(peer);
+
+    // the scheme is set by the proxy in front of us, the last value
+    let secure_transport = headers
+        .get_all(FORWARDED_PROTO_HEADER)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .next_back()
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+    Client { ip, secure_transport }
+}
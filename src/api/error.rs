@@ -0,0 +1,390 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::{AuthError, ChunkError, StorageError};
+
+use super::types::{ErrorResponse, Xml};
+
+
+pub type ApiResult<T> = std::result::Result<T, S3Error>;
+
+
+/// An S3 error: HTTP status plus the `Code` and `Message` of the XML body.
+///
+/// `IntoResponse` only attaches it to an empty response; the request context
+/// middleware renders the body once it knows the resource and request id.
+#[derive(Debug, Clone)]
+pub struct S3Error {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+
+impl S3Error {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    pub fn internal() -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            "We encountered an internal error. Please try again.",
+        )
+    }
+
+    pub fn not_implemented() -> Self {
+        Self::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "A header or query you provided implies functionality that is not implemented.",
+        )
+    }
+
+    pub fn access_denied() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied")
+    }
+
+    pub fn malformed_xml() -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "MalformedXML",
+            "The XML you provided was not well-formed or did not validate against our published schema.",
+        )
+    }
+
+    /// Full `<Error>` response for this error
+    pub fn render(&self, resource: &str, request_id: &str) -> Response {
+        let body = ErrorResponse {
+            code: self.code.to_string(),
+            message: self.message.clone(),
+            resource: resource.to_string(),
+            request_id: request_id.to_string(),
+        };
+
+        (self.status, Xml(body)).into_response()
+    }
+}
+
+
+impl From<&StorageError> for S3Error {
+    fn from(err: &StorageError) -> Self {
+        match err {
+            StorageError::BucketNotFound(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchBucket",
+                "The specified bucket does not exist",
+            ),
+            StorageError::BucketAlreadyExists(_) => Self::new(
+                StatusCode::CONFLICT,
+                "BucketAlreadyExists",
+                "The requested bucket name is not available. Please select a different name and try again.",
+            ),
+            StorageError::BucketAlreadyOwnedByYou(_) => Self::new(
+                StatusCode::CONFLICT,
+                "BucketAlreadyOwnedByYou",
+                "Your previous request to create the named bucket succeeded and you already own it.",
+            ),
+            StorageError::BucketNotEmpty(_) => Self::new(
+                StatusCode::CONFLICT,
+                "BucketNotEmpty",
+                "The bucket you tried to delete is not empty",
+            ),
+            StorageError::ObjectNotFound(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchKey",
+                "The specified key does not exist.",
+            ),
+            StorageError::VersionNotFound(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchVersion",
+                "The specified version does not exist.",
+            ),
+            StorageError::DeleteMarker { requested: false, .. } => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchKey",
+                "The specified key does not exist.",
+            ),
+            StorageError::DeleteMarker { requested: true, .. } => Self::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "MethodNotAllowed",
+                "The specified method is not allowed against this resource.",
+            ),
+            StorageError::NoLifecycleConfiguration(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchLifecycleConfiguration",
+                "The lifecycle configuration does not exist",
+            ),
+            StorageError::NoBucketPolicy(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchBucketPolicy",
+                "The bucket policy does not exist",
+            ),
+            StorageError::NoEncryptionConfiguration(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "ServerSideEncryptionConfigurationNotFoundError",
+                "The server side encryption configuration was not found",
+            ),
+            StorageError::NoMasterKey => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                "Server-side encryption needs the server to be configured with a master key",
+            ),
+            StorageError::CustomerKeyRequired => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                "The object was stored using a form of Server Side Encryption. \
+                 The correct parameters must be provided to retrieve the object.",
+            ),
+            StorageError::CustomerKeyNotApplicable => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                "The encryption parameters are not applicable to this object.",
+            ),
+            StorageError::CustomerKeyMismatch => Self::new(
+                StatusCode::FORBIDDEN,
+                "AccessDenied",
+                "The customer-provided encryption key does not match the key the object was encrypted with",
+            ),
+            StorageError::QuotaExceeded(msg) => Self::new(
+                StatusCode::FORBIDDEN,
+                "QuotaExceeded",
+                msg,
+            ),
+            StorageError::NoSuchEntity(name) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchEntity",
+                format!("The {name} cannot be found."),
+            ),
+            StorageError::EntityAlreadyExists(name) => Self::new(
+                StatusCode::CONFLICT,
+                "EntityAlreadyExists",
+                format!("The {name} already exists."),
+            ),
+            StorageError::InvalidBucketName(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidBucketName",
+                format!("The specified bucket is not valid. {msg}"),
+            ),
+            StorageError::InvalidObjectKey(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                msg.clone(),
+            ),
+            StorageError::ChecksumMismatch { .. } => Self::new(
+                StatusCode::BAD_REQUEST,
+                "BadDigest",
+                "The Content-MD5 or checksum value that you specified did not match what the server received.",
+            ),
+            StorageError::InvalidRange(_) => Self::new(
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "InvalidRange",
+                "The requested range is not satisfiable",
+            ),
+            StorageError::InvalidArgument(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                msg.clone(),
+            ),
+            StorageError::UploadNotFound(_) => Self::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchUpload",
+                "The specified multipart upload does not exist. The upload ID might be invalid, or the multipart upload might have been aborted or completed.",
+            ),
+            StorageError::InvalidPart(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidPart",
+                format!("One or more of the specified parts could not be found or did not match. {msg}"),
+            ),
+            StorageError::InvalidPartOrder => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidPartOrder",
+                "The list of parts was not in ascending order. The parts list must be specified in order by part number.",
+            ),
+            StorageError::PartTooSmall(_) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "EntityTooSmall",
+                "Your proposed upload is smaller than the minimum allowed object size.",
+            ),
+            // the body of an upload may turn out to be malformed while it is read
+            StorageError::IoError(e) => match e.get_ref().and_then(|e| e.downcast_ref::<ChunkError>()) {
+                Some(e) => Self::from(e),
+                None => {
+                    tracing::error!("request failed: {}", err);
+                    Self::internal()
+                }
+            },
+            StorageError::SerializationError(_)
+            | StorageError::DatabaseError(_) => {
+                tracing::error!("request failed: {}", err);
+                Self::internal()
+            }
+        }
+    }
+}
+
+
+impl From<StorageError> for S3Error {
+    fn from(err: StorageError) -> Self {
+        Self::from(&err)
+    }
+}
+
+
+impl From<&ChunkError> for S3Error {
+    fn from(err: &ChunkError) -> Self {
+        match err {
+            ChunkError::SignatureMismatch => AuthError::SignatureMismatch.into(),
+            ChunkError::Malformed(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                msg.clone(),
+            ),
+            ChunkError::IncompleteBody => Self::new(
+                StatusCode::BAD_REQUEST,
+                "IncompleteBody",
+                "You did not provide the number of bytes specified by the Content-Length HTTP header.",
+            ),
+            ChunkError::ChecksumMismatch(name) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "BadDigest",
+                format!("The {name} you specified did not match the calculated checksum."),
+            ),
+        }
+    }
+}
+
+
+impl From<AuthError> for S3Error {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::MissingAuthentication => Self::new(
+                StatusCode::FORBIDDEN,
+                "AccessDenied",
+                "Access Denied",
+            ),
+            AuthError::MalformedAuthorization(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "AuthorizationHeaderMalformed",
+                msg,
+            ),
+            AuthError::InvalidAccessKeyId(_) => Self::new(
+                StatusCode::FORBIDDEN,
+                "InvalidAccessKeyId",
+                "The AWS access key Id you provided does not exist in our records.",
+            ),
+            AuthError::SignatureMismatch => Self::new(
+                StatusCode::FORBIDDEN,
+                "SignatureDoesNotMatch",
+                "The request signature we calculated does not match the signature you provided. Check your key and signing method.",
+            ),
+            AuthError::RequestTimeTooSkewed(_) => Self::new(
+                StatusCode::FORBIDDEN,
+                "RequestTimeTooSkewed",
+                "The difference between the request time and the server's time is too large.",
+            ),
+            AuthError::RequestExpired => Self::new(
+                StatusCode::FORBIDDEN,
+                "AccessDenied",
+                "Request has expired",
+            ),
+            AuthError::UnsupportedPayload(value) => Self::new(
+                StatusCode::NOT_IMPLEMENTED,
+                "NotImplemented",
+                format!("{value} payload signing is not supported"),
+            ),
+            AuthError::ContentSha256Mismatch => Self::new(
+                StatusCode::BAD_REQUEST,
+                "XAmzContentSHA256Mismatch",
+                "The provided 'x-amz-content-sha256' header does not match what was computed.",
+            ),
+            AuthError::MalformedPolicy(msg) => Self::new(
+                StatusCode::BAD_REQUEST,
+                "MalformedPolicyDocument",
+                format!("The policy document is not valid: {msg}"),
+            ),
+        }
+    }
+}
+
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        let mut response = self.status.into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+
+impl IntoResponse for StorageError {
+    fn into_response(self) -> Response {
+        S3Error::from(&self).into_response()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn maps_storage_errors_to_s3_errors() {
+        let name = || "name".to_string();
+
+        let cases = [
+            (StorageError::BucketNotFound(name()), StatusCode::NOT_FOUND, "NoSuchBucket"),
+            (StorageError::BucketAlreadyExists(name()), StatusCode::CONFLICT, "BucketAlreadyExists"),
+            (StorageError::BucketAlreadyOwnedByYou(name()), StatusCode::CONFLICT, "BucketAlreadyOwnedByYou"),
+            (StorageError::BucketNotEmpty(name()), StatusCode::CONFLICT, "BucketNotEmpty"),
+            (StorageError::ObjectNotFound(name()), StatusCode::NOT_FOUND, "NoSuchKey"),
+            (StorageError::VersionNotFound(name()), StatusCode::NOT_FOUND, "NoSuchVersion"),
+            (StorageError::DeleteMarker { version_id: name(), requested: false }, StatusCode::NOT_FOUND, "NoSuchKey"),
+            (StorageError::DeleteMarker { version_id: name(), requested: true }, StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed"),
+            (StorageError::NoLifecycleConfiguration(name()), StatusCode::NOT_FOUND, "NoSuchLifecycleConfiguration"),
+            (StorageError::NoBucketPolicy(name()), StatusCode::NOT_FOUND, "NoSuchBucketPolicy"),
+            (StorageError::NoEncryptionConfiguration(name()), StatusCode::NOT_FOUND, "ServerSideEncryptionConfigurationNotFoundError"),
+            (StorageError::NoMasterKey, StatusCode::BAD_REQUEST, "InvalidRequest"),
+            (StorageError::CustomerKeyRequired, StatusCode::BAD_REQUEST, "InvalidRequest"),
+            (StorageError::CustomerKeyNotApplicable, StatusCode::BAD_REQUEST, "InvalidRequest"),
+            (StorageError::CustomerKeyMismatch, StatusCode::FORBIDDEN, "AccessDenied"),
+            (StorageError::QuotaExceeded(name()), StatusCode::FORBIDDEN, "QuotaExceeded"),
+            (StorageError::NoSuchEntity(name()), StatusCode::NOT_FOUND, "NoSuchEntity"),
+            (StorageError::EntityAlreadyExists(name()), StatusCode::CONFLICT, "EntityAlreadyExists"),
+            (StorageError::InvalidBucketName(name()), StatusCode::BAD_REQUEST, "InvalidBucketName"),
+            (StorageError::InvalidObjectKey(name()), StatusCode::BAD_REQUEST, "InvalidArgument"),
+            (StorageError::ChecksumMismatch { expected: name(), actual: name() }, StatusCode::BAD_REQUEST, "BadDigest"),
+            (StorageError::InvalidRange(1), StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange"),
+            (StorageError::InvalidArgument(name()), StatusCode::BAD_REQUEST, "InvalidArgument"),
+            (StorageError::UploadNotFound(name()), StatusCode::NOT_FOUND, "NoSuchUpload"),
+            (StorageError::InvalidPart(name()), StatusCode::BAD_REQUEST, "InvalidPart"),
+            (StorageError::InvalidPartOrder, StatusCode::BAD_REQUEST, "InvalidPartOrder"),
+            (StorageError::PartTooSmall(1), StatusCode::BAD_REQUEST, "EntityTooSmall"),
+            (io::Error::other(ChunkError::IncompleteBody).into(), StatusCode::BAD_REQUEST, "IncompleteBody"),
+            (io::Error::other("disk failed").into(), StatusCode::INTERNAL_SERVER_ERROR, "InternalError"),
+        ];
+
+        for (err, status, code) in cases {
+            let s3 = S3Error::from(&err);
+            assert_eq!((s3.status, s3.code), (status, code), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_the_code_into_the_error_document() {
+        let response = S3Error::from(StorageError::BucketNotFound("bucket".to_string())).render("/bucket", "ID");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("<Code>NoSuchBucket</Code>"), "{body}");
+        assert!(body.contains("<Resource>/bucket</Resource>"), "{body}");
+        assert!(body.contains("<RequestId>ID</RequestId>"), "{body}");
+    }
+}
@@ -0,0 +1,455 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    api::{
+        AppState,
+        error::{ApiResult, S3Error},
+        types::{AccessControlList, AccessControlPolicy, GrantEntry, GranteeEntry, OWNER_ID, Owner, S3_XMLNS, Xml},
+    },
+    auth::Principal,
+    storage::{Acl, CannedAcl, Grant, Grantee, Permission},
+};
+
+/// Canned ACL of a request, e.g. `x-amz-acl: public-read`
+const ACL_HEADER: &str = "x-amz-acl";
+
+/// Headers granting a permission, e.g. `x-amz-grant-read: id="alice", uri="..."`
+const GRANT_HEADERS: [(&str, Permission); 5] = [
+    ("x-amz-grant-read", Permission::Read),
+    ("x-amz-grant-write", Permission::Write),
+    ("x-amz-grant-read-acp", Permission::ReadAcp),
+    ("x-amz-grant-write-acp", Permission::WriteAcp),
+    ("x-amz-grant-full-control", Permission::FullControl),
+];
+
+/// The groups ACLs can grant to
+const ALL_USERS_URI: &str = "http://acs.amazonaws.com/groups/global/AllUsers";
+const AUTHENTICATED_USERS_URI: &str = "http://acs.amazonaws.com/groups/global/AuthenticatedUsers";
+
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// PutObjectAcl bodies are streamed in like object bodies, this bounds how much is read
+const MAX_ACL_SIZE: usize = 64 * 1024;
+
+
+/// An ACL as a request gives it in headers, either canned or as grants
+enum HeaderAcl {
+    Canned(CannedAcl),
+    Grants(Vec<Grant>),
+}
+
+
+/// GET /{bucket}?acl - GetBucketAcl
+pub async fn get_bucket_acl(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    Ok(acl_response(state.storage.get_bucket_acl(bucket).await?))
+}
+
+
+/// PUT /{bucket}?acl - PutBucketAcl, with ACL headers or an
+/// `AccessControlPolicy` body. The owner stays the same.
+pub async fn put_bucket_acl(state: &AppState, bucket: &str, headers: &HeaderMap, body: Bytes) -> ApiResult<Response> {
+    let owner = state.storage.get_bucket_acl(bucket).await?.owner;
+    let acl = requested_acl(headers, &body, &owner, None)?;
+
+    state.storage.put_bucket_acl(bucket, &acl).await?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+
+/// GET /{bucket}/{*key}?acl - GetObjectAcl, of an older version with `versionId`
+pub async fn get_object_acl(state: &AppState, bucket: &str, key: &str, version_id: Option<&str>) -> ApiResult<Response> {
+    Ok(acl_response(state.storage.get_object_acl(bucket, key, version_id).await?))
+}
+
+
+/// PUT /{bucket}/{*key}?acl - PutObjectAcl, like PutBucketAcl
+pub async fn put_object_acl(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+    headers: &HeaderMap,
+    body: Body,
+) -> ApiResult<Response> {
+    let body = axum::body::to_bytes(body, MAX_ACL_SIZE).await.map_err(|_| malformed_acl())?;
+
+    let owner = state.storage.get_object_acl(bucket, key, version_id).await?.owner;
+    let bucket_owner = state.storage.get_bucket_acl(bucket).await?.owner;
+    let acl = requested_acl(headers, &body, &owner, Some(&bucket_owner))?;
+
+    state.storage.put_object_acl(bucket, key, version_id, &acl).await?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+
+/// ACL of a bucket being created, owned by whoever creates it
+pub fn new_bucket_acl(headers: &HeaderMap, principal: Option<&Principal>) -> ApiResult<Acl> {
+    let owner = principal.map_or(OWNER_ID, |p| p.canonical_id.as_str());
+
+    Ok(match header_acl(headers)? {
+        Some(requested) => requested.into_acl(owner, None),
+        None => Acl::private(owner),
+    })
+}
+
+
+/// ACL of an object being written, owned by whoever writes it. `None` leaves
+/// an anonymous write without ACL headers to the default, the bucket owner
+/// having full control.
+pub async fn new_object_acl(
+    state: &AppState,
+    bucket: &str,
+    headers: &HeaderMap,
+    principal: Option<&Principal>,
+) -> ApiResult<Option<Acl>> {
+    let requested = header_acl(headers)?;
+
+    if requested.is_none() && principal.is_none() {
+        return Ok(None);
+    }
+
+    let needs_bucket_owner =
+        principal.is_none() || matches!(requested, Some(HeaderAcl::Canned(CannedAcl::BucketOwnerFullControl)));
+
+    let bucket_owner = match needs_bucket_owner {
+        true => Some(state.storage.get_bucket_acl(bucket).await?.owner),
+        false => None,
+    };
+
+    let owner = principal.map(|p| p.canonical_id.as_str()).or(bucket_owner.as_deref()).unwrap_or(OWNER_ID);
+
+    Ok(Some(match requested {
+        Some(requested) => requested.into_acl(owner, bucket_owner.as_deref()),
+        None => Acl::private(owner),
+    }))
+}
+
+
+impl HeaderAcl {
+    fn into_acl(self, owner: &str, bucket_owner: Option<&str>) -> Acl {
+        match self {
+            HeaderAcl::Canned(canned) => Acl::canned(canned, owner, bucket_owner),
+            HeaderAcl::Grants(grants) => Acl { owner: owner.to_string(), grants },
+        }
+    }
+}
+
+
+/// The ACL a PutBucketAcl or PutObjectAcl asks for, in headers or the body
+fn requested_acl(headers: &HeaderMap, body: &[u8], owner: &str, bucket_owner: Option<&str>) -> ApiResult<Acl> {
+    match (header_acl(headers)?, body.iter().all(u8::is_ascii_whitespace)) {
+        (Some(requested), true) => Ok(requested.into_acl(owner, bucket_owner)),
+        (Some(_), false) => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "UnexpectedContent",
+            "This request does not support content when ACL headers are given",
+        )),
+        (None, false) => Ok(Acl { owner: owner.to_string(), grants: body_grants(body)? }),
+        (None, true) => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "MissingSecurityHeader",
+            "Your request was missing a required header: x-amz-acl, x-amz-grant-* or an AccessControlPolicy body",
+        )),
+    }
+}
+
+
+/// `x-amz-acl` or `x-amz-grant-*` headers, which don't go together
+fn header_acl(headers: &HeaderMap) -> ApiResult<Option<HeaderAcl>> {
+    let header = |name: &str| -> ApiResult<Option<&str>> {
+        headers
+            .get(name)
+            .map(|v| v.to_str().map_err(|_| invalid_argument(format!("Invalid {name} header"))))
+            .transpose()
+    };
+
+    let mut grants = Vec::new();
+
+    for (name, permission) in GRANT_HEADERS {
+        if let Some(value) = header(name)? {
+            for grantee in value.split(',').map(str::trim).filter(|g| !g.is_empty()) {
+                grants.push(Grant { grantee: header_grantee(grantee)?, permission });
+            }
+        }
+    }
+
+    let canned = header(ACL_HEADER)?;
+    let any_grant_header = GRANT_HEADERS.iter().any(|(name, _)| headers.contains_key(*name));
+
+    match canned {
+        Some(_) if any_grant_header => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Specifying both Canned ACLs and Header Grants is not allowed",
+        )),
+        Some(canned) => CannedAcl::parse(canned)
+            .map(|canned| Some(HeaderAcl::Canned(canned)))
+            .ok_or_else(|| invalid_argument(format!("Invalid canned ACL {canned}"))),
+        None if any_grant_header => Ok(Some(HeaderAcl::Grants(grants))),
+        None => Ok(None),
+    }
+}
+
+
+/// One grantee of a grant header, `id="..."`, `uri="..."` or `emailAddress="..."`
+fn header_grantee(grantee: &str) -> ApiResult<Grantee> {
+    let invalid = || invalid_argument(format!("Invalid grantee {grantee}"));
+
+    let (kind, value) = grantee.split_once('=').ok_or_else(invalid)?;
+    let value = value.trim().trim_matches('"');
+
+    match kind.trim() {
+        "id" if !value.is_empty() => Ok(Grantee::User(value.to_string())),
+        "uri" => group_grantee(value),
+        "emailAddress" => Err(unresolvable_email()),
+        _ => Err(invalid()),
+    }
+}
+
+
+fn body_grants(body: &[u8]) -> ApiResult<Vec<Grant>> {
+    let policy: AccessControlPolicy = std::str::from_utf8(body)
+        .ok()
+        .and_then(|xml| quick_xml::de::from_str(xml).ok())
+        .ok_or_else(malformed_acl)?;
+
+    policy
+        .access_control_list
+        .grants
+        .into_iter()
+        .map(|entry| {
+            let permission = Permission::parse(&entry.permission).ok_or_else(malformed_acl)?;
+
+            let grantee = match (entry.grantee.kind.as_str(), entry.grantee.id, entry.grantee.uri) {
+                ("CanonicalUser" | "", Some(id), _) if !id.is_empty() => Grantee::User(id),
+                ("Group" | "", _, Some(uri)) => group_grantee(&uri)?,
+                ("AmazonCustomerByEmail", _, _) => return Err(unresolvable_email()),
+                _ => return Err(malformed_acl()),
+            };
+
+            Ok(Grant { grantee, permission })
+        })
+        .collect()
+}
+
+
+fn group_grantee(uri: &str) -> ApiResult<Grantee> {
+    match uri {
+        ALL_USERS_URI => Ok(Grantee::AllUsers),
+        AUTHENTICATED_USERS_URI => Ok(Grantee::AuthenticatedUsers),
+        _ => Err(invalid_argument(format!("Invalid group uri {uri}"))),
+    }
+}
+
+
+fn acl_response(acl: Acl) -> Response {
+    let grants = acl
+        .grants
+        .into_iter()
+        .map(|grant| {
+            let (kind, id, uri) = match grant.grantee {
+                Grantee::User(id) => ("CanonicalUser", Some(id), None),
+                Grantee::AllUsers => ("Group", None, Some(ALL_USERS_URI)),
+                Grantee::AuthenticatedUsers => ("Group", None, Some(AUTHENTICATED_USERS_URI)),
+            };
+
+            GrantEntry {
+                grantee: GranteeEntry {
+                    xmlns_xsi: Some(XSI_NAMESPACE.to_string()),
+                    kind: kind.to_string(),
+                    display_name: id.clone(),
+                    id,
+                    uri: uri.map(str::to_string),
+                    email_address: None,
+                },
+                permission: grant.permission.as_str().to_string(),
+            }
+        })
+        .collect();
+
+    Xml(AccessControlPolicy {
+        xmlns: Some(S3_XMLNS.to_string()),
+        owner: Some(Owner { id: acl.owner.clone(), display_name: acl.owner }),
+        access_control_list: AccessControlList { grants },
+    })
+    .into_response()
+}
+
+
+fn invalid_argument(message: String) -> S3Error {
+    S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+}
+
+
+fn malformed_acl() -> S3Error {
+    S3Error::new(
+        StatusCode::BAD_REQUEST,
+        "MalformedACLError",
+        "The XML you provided was not well-formed or did not validate against our published schema",
+    )
+}
+
+
+fn unresolvable_email() -> S3Error {
+    S3Error::new(
+        StatusCode::BAD_REQUEST,
+        "UnresolvableGrantByEmailAddress",
+        "Grants by email address are not supported, grant by id",
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (HeaderName::from_static(name), HeaderValue::from_str(value).unwrap())).collect()
+    }
+
+    fn acl_from(pairs: &[(&'static str, &str)]) -> ApiResult<Acl> {
+        requested_acl(&headers(pairs), b"", "alice", None)
+    }
+
+    fn code(result: ApiResult<Acl>) -> &'static str {
+        result.unwrap_err().code
+    }
+
+    fn grant(grantee: Grantee, permission: Permission) -> Grant {
+        Grant { grantee, permission }
+    }
+
+    #[test]
+    fn reads_canned_acls() {
+        assert_eq!(acl_from(&[("x-amz-acl", "private")]).unwrap(), Acl::private("alice"));
+        assert_eq!(
+            acl_from(&[("x-amz-acl", "public-read")]).unwrap(),
+            Acl::canned(CannedAcl::PublicRead, "alice", None),
+        );
+
+        let acl = requested_acl(&headers(&[("x-amz-acl", "bucket-owner-full-control")]), b"", "bob", Some("alice")).unwrap();
+        assert!(acl.allows(Permission::FullControl, Some("alice")));
+
+        assert_eq!(code(acl_from(&[("x-amz-acl", "public")])), "InvalidArgument");
+    }
+
+    #[test]
+    fn reads_grant_headers() {
+        let acl = acl_from(&[
+            ("x-amz-grant-read", r#"id="bob", uri="http://acs.amazonaws.com/groups/global/AllUsers""#),
+            ("x-amz-grant-write-acp", "id=carol"),
+            ("x-amz-grant-full-control", r#" uri="http://acs.amazonaws.com/groups/global/AuthenticatedUsers" "#),
+        ])
+        .unwrap();
+
+        assert_eq!(acl.owner, "alice");
+        assert_eq!(acl.grants, [
+            grant(Grantee::User("bob".to_string()), Permission::Read),
+            grant(Grantee::AllUsers, Permission::Read),
+            grant(Grantee::User("carol".to_string()), Permission::WriteAcp),
+            grant(Grantee::AuthenticatedUsers, Permission::FullControl),
+        ]);
+    }
+
+    #[test]
+    fn rejects_malformed_grant_headers() {
+        for value in [
+            "bob",
+            r#"id="""#,
+            r#"name="bob""#,
+            r#"uri="http://acs.amazonaws.com/groups/s3/LogDelivery""#,
+            r#"id="bob", carol"#,
+        ] {
+            assert_eq!(code(acl_from(&[("x-amz-grant-read", value)])), "InvalidArgument", "{value}");
+        }
+
+        assert_eq!(
+            code(acl_from(&[("x-amz-grant-read", r#"emailAddress="bob@example.com""#)])),
+            "UnresolvableGrantByEmailAddress",
+        );
+
+        // canned and granted at once
+        assert_eq!(code(acl_from(&[("x-amz-acl", "private"), ("x-amz-grant-read", "id=bob")])), "InvalidRequest");
+    }
+
+    #[test]
+    fn needs_headers_or_a_body_but_not_both() {
+        assert_eq!(code(acl_from(&[])), "MissingSecurityHeader");
+        assert_eq!(code(requested_acl(&headers(&[]), b" \n", "alice", None)), "MissingSecurityHeader");
+
+        let body = br#"<AccessControlPolicy><AccessControlList/></AccessControlPolicy>"#;
+        assert_eq!(code(requested_acl(&headers(&[("x-amz-acl", "private")]), body, "alice", None)), "UnexpectedContent");
+    }
+
+    #[test]
+    fn reads_access_control_policies() {
+        let body = format!(
+            r#"<AccessControlPolicy xmlns="{S3_XMLNS}">
+                 <Owner><ID>mallory</ID></Owner>
+                 <AccessControlList>
+                   <Grant>
+                     <Grantee xmlns:xsi="{XSI_NAMESPACE}" xsi:type="CanonicalUser"><ID>bob</ID></Grantee>
+                     <Permission>WRITE</Permission>
+                   </Grant>
+                   <Grant>
+                     <Grantee xmlns:xsi="{XSI_NAMESPACE}" xsi:type="Group"><URI>{ALL_USERS_URI}</URI></Grantee>
+                     <Permission>READ</Permission>
+                   </Grant>
+                 </AccessControlList>
+               </AccessControlPolicy>"#
+        );
+
+        let acl = requested_acl(&headers(&[]), body.as_bytes(), "alice", None).unwrap();
+
+        // the owner in the body doesn't change it
+        assert_eq!(acl.owner, "alice");
+        assert_eq!(acl.grants, [
+            grant(Grantee::User("bob".to_string()), Permission::Write),
+            grant(Grantee::AllUsers, Permission::Read),
+        ]);
+    }
+
+    #[test]
+    fn rejects_malformed_access_control_policies() {
+        let policy = |grant: &str| {
+            format!("<AccessControlPolicy><AccessControlList><Grant>{grant}</Grant></AccessControlList></AccessControlPolicy>")
+        };
+
+        for body in [
+            "not xml".to_string(),
+            "<AccessControlPolicy/>".to_string(),
+            policy("<Grantee><ID>bob</ID></Grantee><Permission>EVERYTHING</Permission>"),
+            policy("<Grantee><ID></ID></Grantee><Permission>READ</Permission>"),
+            policy("<Permission>READ</Permission>"),
+        ] {
+            assert_eq!(code(requested_acl(&headers(&[]), body.as_bytes(), "alice", None)), "MalformedACLError", "{body}");
+        }
+
+        let body = policy(
+            r#"<Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="AmazonCustomerByEmail">
+                 <EmailAddress>bob@example.com</EmailAddress>
+               </Grantee><Permission>READ</Permission>"#,
+        );
+        assert_eq!(code(requested_acl(&headers(&[]), body.as_bytes(), "alice", None)), "UnresolvableGrantByEmailAddress");
+
+        let body = policy("<Grantee><URI>http://example.com/everyone</URI></Grantee><Permission>READ</Permission>");
+        assert_eq!(code(requested_acl(&headers(&[]), body.as_bytes(), "alice", None)), "InvalidArgument");
+    }
+
+    #[test]
+    fn new_buckets_belong_to_their_creator() {
+        assert_eq!(new_bucket_acl(&headers(&[]), None).unwrap(), Acl::private(OWNER_ID));
+
+        let acl = new_bucket_acl(&headers(&[("x-amz-acl", "public-read")]), None).unwrap();
+        assert!(acl.allows(Permission::Read, None));
+        assert!(!acl.allows(Permission::Write, None));
+    }
+}
@@ -0,0 +1,207 @@
+use std::net::SocketAddr;
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, Method, StatusCode, header},
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{AppState, client, error::{ApiResult, S3Error}},
+    auth::{self, Credential, sigv4},
+    storage::{BucketInfo, OwnerUsage, Quota},
+};
+
+/// Presigned URLs are valid for an hour unless asked otherwise
+const DEFAULT_PRESIGN_EXPIRY_SECS: i64 = 60 * 60;
+
+
+#[derive(Debug, Deserialize)]
+pub struct PresignParams {
+    pub bucket: String,
+    pub key: String,
+    pub method: Option<String>,
+    pub expires: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresignResponse {
+    pub url: String,
+    pub method: String,
+    pub expires_at: String,
+}
+
+
+/// GET /.admin/presign?bucket=..&key=..&method=GET|PUT&expires=SECS
+///
+/// Mints a presigned URL signed with the caller's own access key, so a backend
+/// can hand short lived upload/download URLs to browsers without sharing secrets.
+/// The URL is `https` when the request came through a trusted proxy over HTTPS.
+pub async fn presign(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    credential: Option<Extension<Credential>>,
+    headers: HeaderMap,
+    Query(params): Query<PresignParams>,
+) -> ApiResult<Json<PresignResponse>> {
+    let Some(Extension(credential)) = credential else {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Presigned URLs need the server to be configured with access keys",
+        ));
+    };
+
+    let method = match params.method.as_deref().unwrap_or("GET").to_ascii_uppercase().as_str() {
+        "GET" => Method::GET,
+        "PUT" => Method::PUT,
+        other => return Err(invalid_argument(format!("Cannot presign {other} requests, use GET or PUT"))),
+    };
+
+    let expires = params.expires.unwrap_or(DEFAULT_PRESIGN_EXPIRY_SECS);
+
+    if !(1..=sigv4::MAX_PRESIGN_EXPIRY_SECS).contains(&expires) {
+        return Err(invalid_argument(format!(
+            "expires must be between 1 and {} seconds",
+            sigv4::MAX_PRESIGN_EXPIRY_SECS
+        )));
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| invalid_argument("Missing Host header".to_string()))?;
+    let peer = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+    let scheme = match client::client(peer, &headers, &state.trusted_proxies).secure_transport {
+        true => "https",
+        false => "http",
+    };
+
+    let now = Utc::now();
+    let url = auth::presign_url(
+        &credential,
+        &method,
+        &format!("{scheme}://{host}"),
+        &params.bucket,
+        &params.key,
+        expires,
+        now,
+    )?;
+
+    Ok(Json(PresignResponse {
+        url,
+        method: method.to_string(),
+        expires_at: (now + Duration::seconds(expires)).to_rfc3339(),
+    }))
+}
+
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub buckets: Vec<BucketStats>,
+    pub object_count: usize,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketStats {
+    pub name: String,
+    pub owner: Option<String>,
+    pub created_at: String,
+    pub object_count: usize,
+    pub total_size: u64,
+    pub quota: Quota,
+}
+
+impl From<BucketInfo> for BucketStats {
+    fn from(info: BucketInfo) -> Self {
+        Self {
+            name: info.name,
+            owner: info.owner,
+            created_at: info.created_at.to_rfc3339(),
+            object_count: info.object_count,
+            total_size: info.total_size,
+            quota: info.quota,
+        }
+    }
+}
+
+
+/// GET /.admin/stats
+///
+/// Object count and size of every bucket plus the totals across all of them.
+/// Every stored version counts, so these are what the data takes up on disk
+/// before deduplication.
+pub async fn stats(State(state): State<AppState>) -> ApiResult<Json<StatsResponse>> {
+    let buckets: Vec<BucketStats> = state.storage.list_buckets().await?.into_iter().map(BucketStats::from).collect();
+
+    Ok(Json(StatsResponse {
+        object_count: buckets.iter().map(|b| b.object_count).sum(),
+        total_size: buckets.iter().map(|b| b.total_size).sum(),
+        buckets,
+    }))
+}
+
+
+/// GET /.admin/stats/{bucket} - object count and size of one bucket
+pub async fn bucket_stats(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> ApiResult<Json<BucketStats>> {
+    Ok(Json(state.storage.get_bucket_info(&bucket).await?.into()))
+}
+
+
+/// PUT /.admin/quotas/buckets/{bucket} with `{"max_objects": N, "max_size": BYTES}`
+///
+/// Replaces the quota of a bucket, a missing or null limit removes it. Puts
+/// and multipart completions that would take the bucket over it fail with
+/// QuotaExceeded.
+pub async fn put_bucket_quota(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    body: Bytes,
+) -> ApiResult<Json<BucketStats>> {
+    state.storage.put_bucket_quota(&bucket, &parse_quota(&body)?).await?;
+
+    Ok(Json(state.storage.get_bucket_info(&bucket).await?.into()))
+}
+
+
+/// GET /.admin/quotas/owners/{owner} - what the buckets of an owner hold
+/// together, and their quota. The owner is a canonical id: the `canonical_id`
+/// of a user, or `root:` and the access key id for the root credentials.
+pub async fn get_owner_quota(
+    State(state): State<AppState>,
+    Path(owner): Path<String>,
+) -> ApiResult<Json<OwnerUsage>> {
+    Ok(Json(state.storage.get_owner_usage(&owner).await?))
+}
+
+
+/// PUT /.admin/quotas/owners/{owner} with `{"max_objects": N, "max_size": BYTES}`
+///
+/// Replaces the quota shared by all buckets of an owner, which applies on
+/// top of the quota of each bucket.
+pub async fn put_owner_quota(
+    State(state): State<AppState>,
+    Path(owner): Path<String>,
+    body: Bytes,
+) -> ApiResult<Json<OwnerUsage>> {
+    state.storage.put_owner_quota(&owner, &parse_quota(&body)?).await?;
+
+    Ok(Json(state.storage.get_owner_usage(&owner).await?))
+}
+
+
+fn parse_quota(body: &[u8]) -> ApiResult<Quota> {
+    serde_json::from_slice(body).map_err(|e| invalid_argument(format!("Invalid quota: {e}")))
+}
+
+
+fn invalid_argument(message: String) -> S3Error {
+    S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+}
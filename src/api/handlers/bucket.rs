@@ -0,0 +1,363 @@
+use axum::{
+    Extension,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    api::{
+        AppState,
+        error::{ApiResult, S3Error},
+        types::{
+            BucketEntry, BucketQuery, Buckets, CommonPrefix, DeleteMarkerEntry, ListAllMyBucketsResult, ListBucketResult,
+            ListVersionsResult, ObjectEntry, ObjectVersionEntry, Owner, S3_XMLNS, STORAGE_CLASS, VersionEntry,
+            VersioningConfiguration, VersioningConfigurationResult, Xml, s3_timestamp,
+        },
+    },
+    auth::{Principal, sigv4},
+    storage::{BucketVersioning, ListObjectsOptions, ListVersionsOptions},
+};
+
+use super::{acl, encryption, lifecycle, multipart, object::parse_max_keys, policy};
+
+
+/// GET / - ListBuckets, the buckets the caller owns. The root credentials see
+/// every bucket.
+pub async fn list_buckets(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+) -> ApiResult<impl IntoResponse> {
+    let principal = principal.as_ref().map(|Extension(p)| p);
+    let buckets = state.storage.list_buckets().await?;
+
+    let owned_by = |owner: &Option<String>| match principal {
+        Some(p) if p.user.is_some() => owner.as_deref() == Some(p.canonical_id.as_str()),
+        _ => true,
+    };
+
+    Ok(Xml(ListAllMyBucketsResult {
+        xmlns: S3_XMLNS,
+        owner: principal.map_or_else(Owner::default, |p| Owner {
+            id: p.canonical_id.clone(),
+            display_name: p.user.clone().unwrap_or_else(|| p.access_key_id.clone()),
+        }),
+        buckets: Buckets {
+            bucket: buckets
+                .into_iter()
+                .filter(|b| owned_by(&b.owner))
+                .map(|b| BucketEntry {
+                    creation_date: s3_timestamp(&b.created_at),
+                    name: b.name,
+                })
+                .collect(),
+        },
+    }))
+}
+
+
+/// GET /{bucket} - ListObjectsV2 with `?list-type=2`, ListMultipartUploads with `?uploads`,
+/// GetBucketVersioning with `?versioning`, ListObjectVersions with `?versions`,
+/// GetBucketLifecycleConfiguration with `?lifecycle`, GetBucketPolicy with `?policy`,
+/// GetBucketAcl with `?acl`, GetBucketEncryption with `?encryption`
+pub async fn get_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    Query(query): Query<BucketQuery>,
+) -> ApiResult<Response> {
+    if query.uploads.is_some() {
+        return multipart::list_multipart_uploads(&state, &bucket, &query).await;
+    }
+
+    if query.versioning.is_some() {
+        return get_bucket_versioning(&state, &bucket).await;
+    }
+
+    if query.versions.is_some() {
+        return list_object_versions(&state, &bucket, &query).await;
+    }
+
+    if query.lifecycle.is_some() {
+        return lifecycle::get_bucket_lifecycle(&state, &bucket).await;
+    }
+
+    if query.policy.is_some() {
+        return policy::get_bucket_policy(&state, &bucket).await;
+    }
+
+    if query.acl.is_some() {
+        return acl::get_bucket_acl(&state, &bucket).await;
+    }
+
+    if query.encryption.is_some() {
+        return encryption::get_bucket_encryption(&state, &bucket).await;
+    }
+
+    if query.list_type.as_deref() == Some("2") {
+        return list_objects_v2(&state, &bucket, &query).await;
+    }
+
+    Err(S3Error::not_implemented())
+}
+
+
+/// GET /{bucket}?list-type=2 - ListObjectsV2
+///
+/// Continuation tokens are the hex encoded key the next page starts at.
+async fn list_objects_v2(state: &AppState, bucket: &str, query: &BucketQuery) -> ApiResult<Response> {
+    let max_keys = parse_max_keys(query.max_keys.as_deref(), "max-keys")?;
+
+    let url_encode = parse_encoding_type(query)?;
+
+    // a continuation token takes precedence over start-after
+    let from = match (&query.continuation_token, &query.start_after) {
+        (Some(token), _) => Some(
+            hex::decode(token)
+                .ok()
+                .and_then(|from| String::from_utf8(from).ok())
+                .ok_or_else(|| S3Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidArgument",
+                    "The continuation token provided is incorrect",
+                ))?,
+        ),
+        // keys can't contain NUL, so this is the smallest string after start-after
+        (None, Some(start_after)) => Some(format!("{start_after}\0")),
+        (None, None) => None,
+    };
+
+    let options = ListObjectsOptions {
+        prefix: query.prefix.clone().unwrap_or_default(),
+        delimiter: query.delimiter.clone(),
+        from,
+        max_keys,
+        fetch_owner: query.fetch_owner.as_deref() == Some("true"),
+    };
+
+    let listing = state.storage.list_objects(bucket, &options).await?;
+    let encode = |value: &str| encode_value(value, url_encode);
+
+    Ok(Xml(ListBucketResult {
+        xmlns: S3_XMLNS,
+        name: bucket.to_string(),
+        prefix: encode(&options.prefix),
+        delimiter: options.delimiter.as_deref().map(encode),
+        max_keys,
+        key_count: listing.objects.len() + listing.common_prefixes.len(),
+        is_truncated: listing.next.is_some(),
+        continuation_token: query.continuation_token.clone(),
+        next_continuation_token: listing.next.map(hex::encode),
+        start_after: query.start_after.as_deref().map(encode),
+        encoding_type: url_encode.then_some("url"),
+        contents: listing
+            .objects
+            .into_iter()
+            .map(|o| ObjectEntry {
+                key: encode(&o.key),
+                last_modified: s3_timestamp(&o.modified_at),
+                etag: format!("\"{}\"", o.etag),
+                size: o.size,
+                storage_class: STORAGE_CLASS,
+                owner: o.owner.map(|owner| Owner { id: owner.clone(), display_name: owner }),
+            })
+            .collect(),
+        common_prefixes: listing
+            .common_prefixes
+            .iter()
+            .map(|p| CommonPrefix { prefix: encode(p) })
+            .collect(),
+    }).into_response())
+}
+
+
+/// GET /{bucket}?versions - ListObjectVersions
+async fn list_object_versions(state: &AppState, bucket: &str, query: &BucketQuery) -> ApiResult<Response> {
+    let max_keys = parse_max_keys(query.max_keys.as_deref(), "max-keys")?;
+    let url_encode = parse_encoding_type(query)?;
+
+    let options = ListVersionsOptions {
+        prefix: query.prefix.clone().unwrap_or_default(),
+        delimiter: query.delimiter.clone(),
+        key_marker: query.key_marker.clone(),
+        version_id_marker: query.version_id_marker.clone(),
+        max_keys,
+    };
+
+    let listing = state.storage.list_object_versions(bucket, &options).await?;
+    let encode = |value: &str| encode_value(value, url_encode);
+    let (next_key_marker, next_version_id_marker) = match listing.next {
+        Some((key, version_id)) => (Some(encode(&key)), Some(version_id).filter(|v| !v.is_empty())),
+        None => (None, None),
+    };
+
+    Ok(Xml(ListVersionsResult {
+        xmlns: S3_XMLNS,
+        name: bucket.to_string(),
+        prefix: encode(&options.prefix),
+        key_marker: options.key_marker.as_deref().map(encode).unwrap_or_default(),
+        version_id_marker: options.version_id_marker.unwrap_or_default(),
+        is_truncated: next_key_marker.is_some(),
+        next_key_marker,
+        next_version_id_marker,
+        max_keys,
+        delimiter: options.delimiter.as_deref().map(encode),
+        encoding_type: url_encode.then_some("url"),
+        entries: listing
+            .versions
+            .into_iter()
+            .map(|v| {
+                if v.is_delete_marker {
+                    VersionEntry::DeleteMarker(DeleteMarkerEntry {
+                        key: encode(&v.key),
+                        version_id: v.version_id,
+                        is_latest: v.is_latest,
+                        last_modified: s3_timestamp(&v.modified_at),
+                        owner: Owner { id: v.owner.clone(), display_name: v.owner },
+                    })
+                } else {
+                    VersionEntry::Version(ObjectVersionEntry {
+                        key: encode(&v.key),
+                        version_id: v.version_id,
+                        is_latest: v.is_latest,
+                        last_modified: s3_timestamp(&v.modified_at),
+                        etag: format!("\"{}\"", v.etag),
+                        size: v.size,
+                        storage_class: STORAGE_CLASS,
+                        owner: Owner { id: v.owner.clone(), display_name: v.owner },
+                    })
+                }
+            })
+            .collect(),
+        common_prefixes: listing
+            .common_prefixes
+            .iter()
+            .map(|p| CommonPrefix { prefix: encode(p) })
+            .collect(),
+    }).into_response())
+}
+
+
+/// `encoding-type` of a listing, `url` being the only one there is
+fn parse_encoding_type(query: &BucketQuery) -> ApiResult<bool> {
+    match query.encoding_type.as_deref() {
+        None => Ok(false),
+        Some("url") => Ok(true),
+        Some(_) => Err(S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", "Invalid Encoding Method specified in Request")),
+    }
+}
+
+fn encode_value(value: &str, url_encode: bool) -> String {
+    if url_encode { sigv4::uri_encode(value, false) } else { value.to_string() }
+}
+
+
+/// GET /{bucket}?versioning - GetBucketVersioning
+async fn get_bucket_versioning(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    let versioning = state.storage.get_bucket_versioning(bucket).await?;
+
+    Ok(Xml(VersioningConfigurationResult {
+        xmlns: S3_XMLNS,
+        status: Some(versioning.as_str()).filter(|s| !s.is_empty()),
+    }).into_response())
+}
+
+
+/// PUT /{bucket} - CreateBucket, PutBucketVersioning with `?versioning`,
+/// PutBucketLifecycleConfiguration with `?lifecycle`, PutBucketPolicy with `?policy`,
+/// PutBucketAcl with `?acl`, PutBucketEncryption with `?encryption`
+///
+/// Any CreateBucketConfiguration body is accepted and ignored, we only have one region.
+/// `x-amz-acl` or `x-amz-grant-*` headers set the ACL of the new bucket.
+pub async fn put_bucket(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path(bucket): Path<String>,
+    Query(query): Query<BucketQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<Response> {
+    if query.versioning.is_some() {
+        return put_bucket_versioning(&state, &bucket, body).await;
+    }
+
+    if query.lifecycle.is_some() {
+        return lifecycle::put_bucket_lifecycle(&state, &bucket, body).await;
+    }
+
+    if query.policy.is_some() {
+        return policy::put_bucket_policy(&state, &bucket, body).await;
+    }
+
+    if query.acl.is_some() {
+        return acl::put_bucket_acl(&state, &bucket, &headers, body).await;
+    }
+
+    if query.encryption.is_some() {
+        return encryption::put_bucket_encryption(&state, &bucket, body).await;
+    }
+
+    let principal = principal.as_ref().map(|Extension(p)| p);
+    let acl = acl::new_bucket_acl(&headers, principal)?;
+    let owner = principal.map(|p| p.canonical_id.as_str());
+
+    state.storage.create_bucket(&bucket, owner, &acl).await?;
+
+    Ok([(header::LOCATION, format!("/{bucket}"))].into_response())
+}
+
+
+/// PUT /{bucket}?versioning - PutBucketVersioning. MFA delete is not supported
+/// and ignored.
+async fn put_bucket_versioning(state: &AppState, bucket: &str, body: Bytes) -> ApiResult<Response> {
+    let versioning = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|xml| quick_xml::de::from_str::<VersioningConfiguration>(xml).ok())
+        .and_then(|config| match config.status.as_deref() {
+            Some("Enabled") => Some(BucketVersioning::Enabled),
+            Some("Suspended") => Some(BucketVersioning::Suspended),
+            _ => None,
+        })
+        .ok_or_else(S3Error::malformed_xml)?;
+
+    state.storage.put_bucket_versioning(bucket, versioning).await?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+
+/// HEAD /{bucket} - HeadBucket
+pub async fn head_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    state.storage.head_bucket(&bucket).await?;
+
+    Ok(StatusCode::OK)
+}
+
+
+/// DELETE /{bucket} - DeleteBucket, the bucket has to be empty,
+/// DeleteBucketLifecycle with `?lifecycle`, DeleteBucketPolicy with `?policy`
+/// or DeleteBucketEncryption with `?encryption`
+pub async fn delete_bucket(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    Query(query): Query<BucketQuery>,
+) -> ApiResult<Response> {
+    if query.lifecycle.is_some() {
+        return lifecycle::delete_bucket_lifecycle(&state, &bucket).await;
+    }
+
+    if query.policy.is_some() {
+        return policy::delete_bucket_policy(&state, &bucket).await;
+    }
+
+    if query.encryption.is_some() {
+        return encryption::delete_bucket_encryption(&state, &bucket).await;
+    }
+
+    state.storage.delete_bucket(&bucket, false).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
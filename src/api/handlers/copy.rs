@@ -0,0 +1,190 @@
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use percent_encoding::percent_decode_str;
+
+use crate::{
+    api::{
+        AppState,
+        error::{ApiResult, S3Error},
+        types::{CopyObjectResult, S3_XMLNS, Xml, s3_timestamp},
+    },
+    auth::{COPY_SOURCE_HEADER, Principal, sigv4},
+    storage::{NULL_VERSION_ID, ObjectMetadata, PutObjectOptions},
+};
+
+use super::{acl, encryption};
+use super::object::{request_metadata, request_tags, version_headers};
+
+/// Whether the copy takes the source's metadata and tags, `COPY` by default, or the request's, `REPLACE`
+const METADATA_DIRECTIVE_HEADER: &str = "x-amz-metadata-directive";
+const TAGGING_DIRECTIVE_HEADER: &str = "x-amz-tagging-directive";
+
+/// Version of the source a copy read
+const COPY_SOURCE_VERSION_ID_HEADER: &str = "x-amz-copy-source-version-id";
+
+/// Conditions on the source, the copy fails with `412 Precondition Failed` unless they hold
+const COPY_SOURCE_IF_MATCH: &str = "x-amz-copy-source-if-match";
+const COPY_SOURCE_IF_NONE_MATCH: &str = "x-amz-copy-source-if-none-match";
+const COPY_SOURCE_IF_MODIFIED_SINCE: &str = "x-amz-copy-source-if-modified-since";
+const COPY_SOURCE_IF_UNMODIFIED_SINCE: &str = "x-amz-copy-source-if-unmodified-since";
+
+
+/// The object `x-amz-copy-source` names, `bucket/key` URL encoded with an
+/// optional `?versionId=`
+struct CopySource {
+    bucket: String,
+    key: String,
+    version_id: Option<String>,
+}
+
+
+/// PUT /{bucket}/{*key} with `x-amz-copy-source` - CopyObject
+///
+/// The source is streamed into a new object, decrypted with the key in the
+/// `x-amz-copy-source-server-side-encryption-customer-*` headers if it is
+/// encrypted with one. The copy is encrypted as its own encryption headers or
+/// the bucket default say. Metadata and tags are the source's unless the
+/// directives ask to `REPLACE` them, the ACL is always the request's.
+pub async fn copy_object(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    principal: Option<&Principal>,
+) -> ApiResult<Response> {
+    let source = copy_source(headers)?;
+    let copy_metadata = copies(headers, METADATA_DIRECTIVE_HEADER)?;
+    let copy_tags = copies(headers, TAGGING_DIRECTIVE_HEADER)?;
+    let source_customer_key = encryption::request_copy_source_customer_key(headers)?;
+    let (encryption, customer_key) = encryption::request_write_encryption(headers)?;
+
+    let to_itself = source.bucket == bucket && source.key == key && source.version_id.is_none();
+
+    if to_itself && copy_metadata && encryption.is_none() && customer_key.is_none() {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "This copy request is illegal because it is trying to copy an object to itself without changing the \
+             object's metadata, storage class, website redirect location or encryption attributes.",
+        ));
+    }
+
+    let acl = acl::new_object_acl(state, bucket, headers, principal).await?;
+
+    let content = state
+        .storage
+        .get_object(&source.bucket, &source.key, source.version_id.as_deref(), None, source_customer_key.as_ref())
+        .await?;
+
+    check_conditions(headers, &content.metadata)?;
+
+    let (content_type, custom_metadata) = match copy_metadata {
+        true => (Some(content.metadata.content_type.clone()), content.metadata.custom_metadata.clone()),
+        false => request_metadata(headers)?,
+    };
+
+    let tags = match copy_tags {
+        true => content.metadata.tags.clone(),
+        false => request_tags(headers)?,
+    };
+
+    let options = PutObjectOptions {
+        content_type,
+        custom_metadata,
+        tags,
+        expected_checksums: Vec::new(),
+        acl,
+        encryption,
+        customer_key: customer_key.clone(),
+        content_length: Some(content.metadata.size),
+    };
+
+    let metadata = state.storage.put_object(bucket, key, content.reader, options).await?;
+
+    let mut response_headers = version_headers(&metadata.version_id);
+
+    if content.metadata.version_id != NULL_VERSION_ID
+        && let Ok(version_id) = HeaderValue::from_str(&content.metadata.version_id)
+    {
+        response_headers.insert(COPY_SOURCE_VERSION_ID_HEADER, version_id);
+    }
+
+    response_headers.extend(encryption::encryption_headers(metadata.encryption, customer_key.as_ref()));
+
+    Ok((response_headers, Xml(CopyObjectResult {
+        xmlns: S3_XMLNS,
+        etag: format!("\"{}\"", metadata.etag),
+        last_modified: s3_timestamp(&metadata.modified_at),
+    })).into_response())
+}
+
+
+fn copy_source(headers: &HeaderMap) -> ApiResult<CopySource> {
+    let invalid = || {
+        S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "Copy Source must mention the source bucket and key: sourcebucket/sourcekey",
+        )
+    };
+
+    let value = headers.get(COPY_SOURCE_HEADER).and_then(|v| v.to_str().ok()).ok_or_else(invalid)?;
+    let (path, query) = value.split_once('?').unwrap_or((value, ""));
+    let path = percent_decode_str(path).decode_utf8().map_err(|_| invalid())?;
+
+    let (bucket, key) = path
+        .trim_start_matches('/')
+        .split_once('/')
+        .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+        .ok_or_else(invalid)?;
+
+    let version_id = sigv4::query_pairs(query).into_iter().find_map(|(k, v)| (k == "versionId").then_some(v));
+
+    Ok(CopySource { bucket: bucket.to_string(), key: key.to_string(), version_id })
+}
+
+
+/// Whether a directive header asks to copy from the source, the default, or replace
+fn copies(headers: &HeaderMap, name: &str) -> ApiResult<bool> {
+    match headers.get(name).map(|v| v.to_str().unwrap_or_default()) {
+        None | Some("COPY") => Ok(true),
+        Some("REPLACE") => Ok(false),
+        Some(value) => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            format!("Unknown {name} value {value}"),
+        )),
+    }
+}
+
+
+/// The `x-amz-copy-source-if-*` conditions, checked against the source like
+/// the `If-*` headers of a GET
+fn check_conditions(headers: &HeaderMap, source: &ObjectMetadata) -> ApiResult<()> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let date = |name| header(name).and_then(|v| httpdate::parse_http_date(v).ok()).map(DateTime::<Utc>::from);
+
+    let etag_matches = |value: &str| {
+        value.split(',').map(|tag| tag.trim().trim_matches('"')).any(|tag| tag == "*" || tag == source.etag)
+    };
+
+    // object times have sub-second precision, the dates compared with don't
+    let modified = source.modified_at.timestamp();
+
+    let holds = header(COPY_SOURCE_IF_MATCH).is_none_or(etag_matches)
+        && !header(COPY_SOURCE_IF_NONE_MATCH).is_some_and(etag_matches)
+        && date(COPY_SOURCE_IF_MODIFIED_SINCE).is_none_or(|since| modified > since.timestamp())
+        && date(COPY_SOURCE_IF_UNMODIFIED_SINCE).is_none_or(|since| modified <= since.timestamp());
+
+    match holds {
+        true => Ok(()),
+        false => Err(S3Error::new(
+            StatusCode::PRECONDITION_FAILED,
+            "PreconditionFailed",
+            "At least one of the pre-conditions you specified did not hold",
+        )),
+    }
+}
@@ -0,0 +1,312 @@
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+use crate::{
+    api::{
+        AppState,
+        error::{ApiResult, S3Error},
+        types::{ApplyServerSideEncryptionByDefault, S3_XMLNS, ServerSideEncryptionConfiguration, ServerSideEncryptionRule, Xml},
+    },
+    storage::{CustomerKey, ServerSideEncryption},
+};
+
+/// How a request asks for its object to be encrypted, and how responses say it is
+const ENCRYPTION_HEADER: &str = "x-amz-server-side-encryption";
+
+/// Encryption with keys from a KMS, which we don't have
+const KMS_ALGORITHMS: [&str; 2] = ["aws:kms", "aws:kms:dsse"];
+
+/// Headers of SSE-C, encryption with a key the client gives with every
+/// request. The key is base64 encoded and checked against its base64 md5.
+const CUSTOMER_ALGORITHM_HEADER: &str = "x-amz-server-side-encryption-customer-algorithm";
+const CUSTOMER_KEY_HEADER: &str = "x-amz-server-side-encryption-customer-key";
+const CUSTOMER_KEY_MD5_HEADER: &str = "x-amz-server-side-encryption-customer-key-md5";
+
+/// The same for the key the source of a copy is encrypted with
+const COPY_SOURCE_CUSTOMER_HEADERS: [&str; 3] = [
+    "x-amz-copy-source-server-side-encryption-customer-algorithm",
+    "x-amz-copy-source-server-side-encryption-customer-key",
+    "x-amz-copy-source-server-side-encryption-customer-key-md5",
+];
+
+/// The only algorithm SSE-C supports
+const CUSTOMER_ALGORITHM: &str = "AES256";
+
+
+/// GET /{bucket}?encryption - GetBucketEncryption
+pub async fn get_bucket_encryption(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    let encryption = state.storage.get_bucket_encryption(bucket).await?;
+
+    Ok(Xml(ServerSideEncryptionConfiguration {
+        xmlns: Some(S3_XMLNS.to_string()),
+        rules: vec![ServerSideEncryptionRule {
+            apply_server_side_encryption_by_default: Some(ApplyServerSideEncryptionByDefault {
+                sse_algorithm: encryption.as_str().to_string(),
+                kms_master_key_id: None,
+            }),
+            bucket_key_enabled: Some(false),
+        }],
+    }).into_response())
+}
+
+
+/// PUT /{bucket}?encryption - PutBucketEncryption
+///
+/// Only `AES256` is supported, with a single rule. Bucket keys are a KMS
+/// feature and ignored.
+pub async fn put_bucket_encryption(state: &AppState, bucket: &str, body: Bytes) -> ApiResult<Response> {
+    let config: ServerSideEncryptionConfiguration = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|xml| quick_xml::de::from_str(xml).ok())
+        .ok_or_else(S3Error::malformed_xml)?;
+
+    let [rule] = config.rules.as_slice() else {
+        return Err(S3Error::malformed_xml());
+    };
+
+    let default = rule.apply_server_side_encryption_by_default.as_ref().ok_or_else(S3Error::malformed_xml)?;
+
+    state.storage.put_bucket_encryption(bucket, parse_algorithm(&default.sse_algorithm)?).await?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+
+/// DELETE /{bucket}?encryption - DeleteBucketEncryption
+pub async fn delete_bucket_encryption(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    state.storage.delete_bucket_encryption(bucket).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+
+/// The encryption `x-amz-server-side-encryption` asks for, `None` to leave it
+/// to the bucket default
+fn request_encryption(headers: &HeaderMap) -> ApiResult<Option<ServerSideEncryption>> {
+    headers
+        .get(ENCRYPTION_HEADER)
+        .map(|value| parse_algorithm(value.to_str().unwrap_or_default()))
+        .transpose()
+}
+
+
+/// The encryption a write asks for: `x-amz-server-side-encryption`, or a
+/// customer key, which don't go together
+pub(super) fn request_write_encryption(
+    headers: &HeaderMap,
+) -> ApiResult<(Option<ServerSideEncryption>, Option<CustomerKey>)> {
+    match (request_encryption(headers)?, request_customer_key(headers)?) {
+        (Some(_), Some(_)) => Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "Server Side Encryption with Customer provided key is incompatible with the encryption method specified",
+        )),
+        requested => Ok(requested),
+    }
+}
+
+
+/// The key given in the `x-amz-server-side-encryption-customer-*` headers,
+/// `None` when there are none. The md5 has to match the key.
+pub(super) fn request_customer_key(headers: &HeaderMap) -> ApiResult<Option<CustomerKey>> {
+    customer_key(headers, [CUSTOMER_ALGORITHM_HEADER, CUSTOMER_KEY_HEADER, CUSTOMER_KEY_MD5_HEADER])
+}
+
+
+/// The key of the source of a copy, in the `x-amz-copy-source-server-side-encryption-customer-*` headers
+pub(super) fn request_copy_source_customer_key(headers: &HeaderMap) -> ApiResult<Option<CustomerKey>> {
+    customer_key(headers, COPY_SOURCE_CUSTOMER_HEADERS)
+}
+
+
+/// The key in the algorithm, key and key md5 headers `names`
+fn customer_key(headers: &HeaderMap, names: [&str; 3]) -> ApiResult<Option<CustomerKey>> {
+    let header = |name| headers.get(name).map(|v| v.to_str().unwrap_or_default());
+
+    let [algorithm, key, md5] = names.map(header);
+
+    if algorithm.is_none() && key.is_none() && md5.is_none() {
+        return Ok(None);
+    }
+
+    match algorithm {
+        Some(CUSTOMER_ALGORITHM) => {}
+        Some(_) => {
+            return Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidEncryptionAlgorithmError",
+                "The encryption request you specified is not valid. The valid value is AES256.",
+            ));
+        }
+        None => {
+            return Err(invalid_argument(
+                "Requests specifying Server Side Encryption with Customer provided keys must provide a valid \
+                 encryption algorithm.",
+            ));
+        }
+    }
+
+    let key = key
+        .and_then(|key| BASE64.decode(key).ok())
+        .and_then(CustomerKey::new)
+        .ok_or_else(|| invalid_argument("The secret key was invalid for the specified algorithm."))?;
+
+    match md5 {
+        Some(md5) if md5 == key.md5() => Ok(Some(key)),
+        Some(_) => Err(invalid_argument("The calculated MD5 hash of the key did not match the hash that was provided.")),
+        None => Err(invalid_argument(
+            "Requests specifying Server Side Encryption with Customer provided keys must provide the client \
+             calculated MD5 of the secret key.",
+        )),
+    }
+}
+
+
+/// `x-amz-server-side-encryption` for an encrypted object, or for one
+/// encrypted with `customer_key` the algorithm and md5 of the key
+pub(super) fn encryption_headers(
+    encryption: Option<ServerSideEncryption>,
+    customer_key: Option<&CustomerKey>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    match encryption {
+        Some(ServerSideEncryption::CustomerKey) => {
+            headers.insert(CUSTOMER_ALGORITHM_HEADER, HeaderValue::from_static(CUSTOMER_ALGORITHM));
+
+            if let Some(md5) = customer_key.and_then(|key| HeaderValue::from_str(&key.md5()).ok()) {
+                headers.insert(CUSTOMER_KEY_MD5_HEADER, md5);
+            }
+        }
+        Some(encryption) => {
+            headers.insert(ENCRYPTION_HEADER, HeaderValue::from_static(encryption.as_str()));
+        }
+        None => {}
+    }
+
+    headers
+}
+
+
+/// `AES256`, the one algorithm `x-amz-server-side-encryption` and bucket
+/// encryption configurations can ask for
+fn parse_algorithm(value: &str) -> ApiResult<ServerSideEncryption> {
+    if KMS_ALGORITHMS.contains(&value) {
+        return Err(S3Error::not_implemented());
+    }
+
+    match ServerSideEncryption::parse(value) {
+        Some(ServerSideEncryption::Aes256) => Ok(ServerSideEncryption::Aes256),
+        _ => Err(invalid_argument("The encryption method specified is not supported")),
+    }
+}
+
+
+fn invalid_argument(message: &str) -> S3Error {
+    S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderName;
+
+    use crate::error::StorageError;
+
+    use super::*;
+
+    fn key_headers(key: &[u8], md5: &str) -> HeaderMap {
+        [
+            (CUSTOMER_ALGORITHM_HEADER, CUSTOMER_ALGORITHM.to_string()),
+            (CUSTOMER_KEY_HEADER, BASE64.encode(key)),
+            (CUSTOMER_KEY_MD5_HEADER, md5.to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (HeaderName::from_static(name), HeaderValue::from_str(&value).unwrap()))
+        .collect()
+    }
+
+    fn status_and_code(err: S3Error) -> (StatusCode, &'static str) {
+        (err.status, err.code)
+    }
+
+    #[test]
+    fn reads_customer_keys() {
+        let key = CustomerKey::new(vec![7; 32]).unwrap();
+
+        let read = request_customer_key(&key_headers(&[7; 32], &key.md5())).unwrap().unwrap();
+        assert_eq!(read.md5(), key.md5());
+
+        assert!(request_customer_key(&HeaderMap::new()).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_bad_customer_key_headers() {
+        let md5 = CustomerKey::new(vec![7; 32]).unwrap().md5();
+        let bad_request = (StatusCode::BAD_REQUEST, "InvalidArgument");
+
+        // an md5 of another key, and one that isn't base64 md5 at all
+        let other_md5 = CustomerKey::new(vec![8; 32]).unwrap().md5();
+        assert_eq!(status_and_code(request_customer_key(&key_headers(&[7; 32], &other_md5)).unwrap_err()), bad_request);
+        assert_eq!(status_and_code(request_customer_key(&key_headers(&[7; 32], "nope")).unwrap_err()), bad_request);
+
+        // keys have to be 256 bits
+        let mut headers = key_headers(&[7; 32], &md5);
+        headers.insert(CUSTOMER_KEY_HEADER, HeaderValue::from_str(&BASE64.encode([7; 16])).unwrap());
+        assert_eq!(status_and_code(request_customer_key(&headers).unwrap_err()), bad_request);
+
+        let mut headers = key_headers(&[7; 32], &md5);
+        headers.remove(CUSTOMER_KEY_MD5_HEADER);
+        assert_eq!(status_and_code(request_customer_key(&headers).unwrap_err()), bad_request);
+
+        let mut headers = key_headers(&[7; 32], &md5);
+        headers.insert(CUSTOMER_ALGORITHM_HEADER, HeaderValue::from_static("AES128"));
+        assert_eq!(
+            status_and_code(request_customer_key(&headers).unwrap_err()),
+            (StatusCode::BAD_REQUEST, "InvalidEncryptionAlgorithmError"),
+        );
+
+        // the copy source headers are checked the same way
+        let copy_headers: HeaderMap = key_headers(&[7; 32], &other_md5)
+            .into_iter()
+            .zip(COPY_SOURCE_CUSTOMER_HEADERS)
+            .map(|((_, value), name)| (HeaderName::from_static(name), value))
+            .collect();
+        assert_eq!(status_and_code(request_copy_source_customer_key(&copy_headers).unwrap_err()), bad_request);
+    }
+
+    #[test]
+    fn maps_customer_key_errors_to_statuses() {
+        assert_eq!(
+            status_and_code(StorageError::CustomerKeyMismatch.into()),
+            (StatusCode::FORBIDDEN, "AccessDenied"),
+        );
+        assert_eq!(
+            status_and_code(StorageError::CustomerKeyRequired.into()),
+            (StatusCode::BAD_REQUEST, "InvalidRequest"),
+        );
+        assert_eq!(
+            status_and_code(StorageError::CustomerKeyNotApplicable.into()),
+            (StatusCode::BAD_REQUEST, "InvalidRequest"),
+        );
+    }
+
+    #[test]
+    fn customer_keys_and_server_side_encryption_dont_go_together() {
+        let md5 = CustomerKey::new(vec![7; 32]).unwrap().md5();
+        let mut headers = key_headers(&[7; 32], &md5);
+
+        let (encryption, key) = request_write_encryption(&headers).unwrap();
+        assert!(encryption.is_none() && key.is_some());
+
+        headers.insert(ENCRYPTION_HEADER, HeaderValue::from_static("AES256"));
+        assert_eq!(
+            status_and_code(request_write_encryption(&headers).unwrap_err()),
+            (StatusCode::BAD_REQUEST, "InvalidArgument"),
+        );
+    }
+}
@@ -1,5 +1,2 @@
-use axum::Json;
-
 pub async fn health_check() {
-    ()
 }
@@ -0,0 +1,236 @@
+//! Admin API managing users, groups, their access keys and the policies
+//! limiting what they may do. Only the root credentials can call it.
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{
+    api::{AppState, error::{ApiResult, S3Error}},
+    auth::{Credential, PolicyDocument},
+    storage::{GroupInfo, UserInfo},
+};
+
+
+#[derive(Debug, Serialize)]
+pub struct UsersResponse {
+    pub users: Vec<UserInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupsResponse {
+    pub groups: Vec<GroupInfo>,
+}
+
+/// A new access key, the only time its secret is shown
+#[derive(Debug, Serialize)]
+pub struct AccessKeyResponse {
+    pub user: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+
+/// GET /.admin/users
+pub async fn list_users(State(state): State<AppState>) -> ApiResult<Json<UsersResponse>> {
+    Ok(Json(UsersResponse { users: state.storage.list_users().await? }))
+}
+
+
+/// PUT /.admin/users/{user} - create a user, without access keys or policies
+pub async fn put_user(State(state): State<AppState>, Path(user): Path<String>) -> ApiResult<Json<UserInfo>> {
+    Ok(Json(state.storage.create_user(&user).await?))
+}
+
+
+/// GET /.admin/users/{user} - a user with its groups, access key ids and policy names
+pub async fn get_user(State(state): State<AppState>, Path(user): Path<String>) -> ApiResult<Json<UserInfo>> {
+    Ok(Json(state.storage.get_user(&user).await?))
+}
+
+
+/// DELETE /.admin/users/{user} - delete a user and its access keys
+pub async fn delete_user(State(state): State<AppState>, Path(user): Path<String>) -> ApiResult<StatusCode> {
+    state.storage.delete_user(&user).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// POST /.admin/users/{user}/access-keys
+///
+/// Generates an access key for a user. The secret is in the response and
+/// nowhere else readable: the database only keeps it encrypted with the
+/// master key, since checking signatures needs it in plaintext.
+pub async fn create_access_key(
+    State(state): State<AppState>,
+    Path(user): Path<String>,
+) -> ApiResult<Json<AccessKeyResponse>> {
+    let Some(master_key) = &state.master_key else {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            "Access keys for users need the server to be configured with a master key",
+        ));
+    };
+
+    let credential = Credential::generate();
+
+    state
+        .storage
+        .create_access_key(&user, &credential.access_key_id, &master_key.seal(credential.secret_access_key.as_bytes()))
+        .await?;
+
+    Ok(Json(AccessKeyResponse {
+        user,
+        access_key_id: credential.access_key_id,
+        secret_access_key: credential.secret_access_key,
+    }))
+}
+
+
+/// DELETE /.admin/access-keys/{access_key_id}
+pub async fn delete_access_key(
+    State(state): State<AppState>,
+    Path(access_key_id): Path<String>,
+) -> ApiResult<StatusCode> {
+    state.storage.delete_access_key(&access_key_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// PUT /.admin/users/{user}/policies/{policy} with a policy document, e.g.
+/// `{"Statement": {"Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::builds/*"}}`
+pub async fn put_user_policy(
+    State(state): State<AppState>,
+    Path((user, policy)): Path<(String, String)>,
+    body: Bytes,
+) -> ApiResult<StatusCode> {
+    state.storage.put_user_policy(&user, &policy, &parse_policy(&body)?).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// GET /.admin/users/{user}/policies/{policy} - the document as it was put
+pub async fn get_user_policy(
+    State(state): State<AppState>,
+    Path((user, policy)): Path<(String, String)>,
+) -> ApiResult<Response> {
+    Ok(policy_response(state.storage.get_user_policy(&user, &policy).await?))
+}
+
+
+/// DELETE /.admin/users/{user}/policies/{policy}
+pub async fn delete_user_policy(
+    State(state): State<AppState>,
+    Path((user, policy)): Path<(String, String)>,
+) -> ApiResult<StatusCode> {
+    state.storage.delete_user_policy(&user, &policy).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// GET /.admin/groups
+pub async fn list_groups(State(state): State<AppState>) -> ApiResult<Json<GroupsResponse>> {
+    Ok(Json(GroupsResponse { groups: state.storage.list_groups().await? }))
+}
+
+
+/// PUT /.admin/groups/{group} - create an empty group
+pub async fn put_group(State(state): State<AppState>, Path(group): Path<String>) -> ApiResult<Json<GroupInfo>> {
+    Ok(Json(state.storage.create_group(&group).await?))
+}
+
+
+/// GET /.admin/groups/{group} - a group with its members and policy names
+pub async fn get_group(State(state): State<AppState>, Path(group): Path<String>) -> ApiResult<Json<GroupInfo>> {
+    Ok(Json(state.storage.get_group(&group).await?))
+}
+
+
+/// DELETE /.admin/groups/{group} - delete a group, its members lose its policies
+pub async fn delete_group(State(state): State<AppState>, Path(group): Path<String>) -> ApiResult<StatusCode> {
+    state.storage.delete_group(&group).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// PUT /.admin/groups/{group}/members/{user}
+pub async fn put_group_member(
+    State(state): State<AppState>,
+    Path((group, user)): Path<(String, String)>,
+) -> ApiResult<StatusCode> {
+    state.storage.add_group_member(&group, &user).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// DELETE /.admin/groups/{group}/members/{user}
+pub async fn delete_group_member(
+    State(state): State<AppState>,
+    Path((group, user)): Path<(String, String)>,
+) -> ApiResult<StatusCode> {
+    state.storage.remove_group_member(&group, &user).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// PUT /.admin/groups/{group}/policies/{policy} with a policy document that
+/// applies to every member
+pub async fn put_group_policy(
+    State(state): State<AppState>,
+    Path((group, policy)): Path<(String, String)>,
+    body: Bytes,
+) -> ApiResult<StatusCode> {
+    state.storage.put_group_policy(&group, &policy, &parse_policy(&body)?).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// GET /.admin/groups/{group}/policies/{policy}
+pub async fn get_group_policy(
+    State(state): State<AppState>,
+    Path((group, policy)): Path<(String, String)>,
+) -> ApiResult<Response> {
+    Ok(policy_response(state.storage.get_group_policy(&group, &policy).await?))
+}
+
+
+/// DELETE /.admin/groups/{group}/policies/{policy}
+pub async fn delete_group_policy(
+    State(state): State<AppState>,
+    Path((group, policy)): Path<(String, String)>,
+) -> ApiResult<StatusCode> {
+    state.storage.delete_group_policy(&group, &policy).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+
+/// Check a policy document parses and return it as text to be stored
+fn parse_policy(body: &[u8]) -> ApiResult<String> {
+    let document = std::str::from_utf8(body).map_err(|_| {
+        S3Error::new(StatusCode::BAD_REQUEST, "MalformedPolicyDocument", "The policy document must be UTF-8")
+    })?;
+
+    PolicyDocument::parse(document)?;
+
+    Ok(document.to_string())
+}
+
+
+fn policy_response(document: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], document).into_response()
+}
@@ -0,0 +1,142 @@
+use axum::{
+    body::Bytes,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        AppState,
+        error::{ApiResult, S3Error},
+        types::{
+            AbortIncompleteMultipartUpload, BucketLifecycleConfiguration, LifecycleAnd, LifecycleExpiration,
+            LifecycleFilter, LifecycleRuleEntry, NoncurrentVersionExpiration, S3_XMLNS, TagEntry, Xml,
+        },
+    },
+    storage::{LifecycleConfiguration, LifecycleRule},
+};
+
+
+/// GET /{bucket}?lifecycle - GetBucketLifecycleConfiguration
+pub async fn get_bucket_lifecycle(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    let config = state.storage.get_bucket_lifecycle(bucket).await?;
+
+    Ok(Xml(BucketLifecycleConfiguration {
+        xmlns: Some(S3_XMLNS.to_string()),
+        rules: config.rules.into_iter().map(rule_entry).collect(),
+    }).into_response())
+}
+
+
+/// PUT /{bucket}?lifecycle - PutBucketLifecycleConfiguration
+///
+/// Transitions, expiration dates and object size filters are rejected, we
+/// have a single storage class and only expire by age.
+pub async fn put_bucket_lifecycle(state: &AppState, bucket: &str, body: Bytes) -> ApiResult<Response> {
+    let request: BucketLifecycleConfiguration = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|xml| quick_xml::de::from_str(xml).ok())
+        .ok_or_else(S3Error::malformed_xml)?;
+
+    let config = LifecycleConfiguration {
+        rules: request.rules.into_iter().map(lifecycle_rule).collect::<ApiResult<_>>()?,
+    };
+
+    state.storage.put_bucket_lifecycle(bucket, &config).await?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+
+/// DELETE /{bucket}?lifecycle - DeleteBucketLifecycle
+pub async fn delete_bucket_lifecycle(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    state.storage.delete_bucket_lifecycle(bucket).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+
+fn lifecycle_rule(entry: LifecycleRuleEntry) -> ApiResult<LifecycleRule> {
+    let enabled = match entry.status.as_str() {
+        "Enabled" => true,
+        "Disabled" => false,
+        _ => return Err(S3Error::malformed_xml()),
+    };
+
+    let filter = entry.filter.unwrap_or_default();
+    let and = filter.and.unwrap_or_default();
+    let expiration = entry.expiration.unwrap_or_default();
+
+    if !entry.transitions.is_empty()
+        || !entry.noncurrent_version_transitions.is_empty()
+        || expiration.date.is_some()
+        || filter.object_size_greater_than.is_some()
+        || filter.object_size_less_than.is_some()
+        || and.object_size_greater_than.is_some()
+        || and.object_size_less_than.is_some()
+    {
+        return Err(S3Error::not_implemented());
+    }
+
+    // a filter names either a prefix, a single tag, or several of them joined by And
+    let (prefix, tags) = match (entry.prefix, filter.prefix, filter.tag, and.prefix, and.tags) {
+        (prefix, None, None, None, tags) if tags.is_empty() => (prefix.unwrap_or_default(), Vec::new()),
+        (None, Some(prefix), None, None, tags) if tags.is_empty() => (prefix, Vec::new()),
+        (None, None, Some(tag), None, tags) if tags.is_empty() => (String::new(), vec![tag]),
+        (None, None, None, prefix, tags) => (prefix.unwrap_or_default(), tags),
+        _ => return Err(S3Error::malformed_xml()),
+    };
+
+    let noncurrent = entry.noncurrent_version_expiration;
+
+    Ok(LifecycleRule {
+        // S3 makes up an id for rules that come without one
+        id: entry.id.filter(|id| !id.is_empty()).unwrap_or_else(|| Uuid::new_v4().simple().to_string()),
+        enabled,
+        prefix,
+        tags: tags.into_iter().map(|t| (t.key, t.value)).collect(),
+        expiration_days: expiration.days,
+        expired_object_delete_marker: expiration.expired_object_delete_marker.unwrap_or(false),
+        noncurrent_days: noncurrent.as_ref().map(|n| n.noncurrent_days),
+        newer_noncurrent_versions: noncurrent.and_then(|n| n.newer_noncurrent_versions),
+        abort_incomplete_upload_days: entry.abort_incomplete_multipart_upload.map(|a| a.days_after_initiation),
+    })
+}
+
+
+fn rule_entry(rule: LifecycleRule) -> LifecycleRuleEntry {
+    let mut tags: Vec<TagEntry> = rule.tags.into_iter().map(|(key, value)| TagEntry { key, value }).collect();
+
+    let filter = match tags.len() {
+        0 => LifecycleFilter { prefix: Some(rule.prefix), ..Default::default() },
+        1 if rule.prefix.is_empty() => LifecycleFilter { tag: tags.pop(), ..Default::default() },
+        _ => LifecycleFilter {
+            and: Some(LifecycleAnd { prefix: Some(rule.prefix).filter(|p| !p.is_empty()), tags, ..Default::default() }),
+            ..Default::default()
+        },
+    };
+
+    let expiration = (rule.expiration_days.is_some() || rule.expired_object_delete_marker).then(|| LifecycleExpiration {
+        days: rule.expiration_days,
+        expired_object_delete_marker: rule.expired_object_delete_marker.then_some(true),
+        ..Default::default()
+    });
+
+    LifecycleRuleEntry {
+        id: Some(rule.id),
+        filter: Some(filter),
+        prefix: None,
+        status: if rule.enabled { "Enabled" } else { "Disabled" }.to_string(),
+        expiration,
+        noncurrent_version_expiration: rule.noncurrent_days.map(|noncurrent_days| NoncurrentVersionExpiration {
+            noncurrent_days,
+            newer_noncurrent_versions: rule.newer_noncurrent_versions,
+        }),
+        abort_incomplete_multipart_upload: rule
+            .abort_incomplete_upload_days
+            .map(|days_after_initiation| AbortIncompleteMultipartUpload { days_after_initiation }),
+        transitions: Vec::new(),
+        noncurrent_version_transitions: Vec::new(),
+    }
+}
@@ -1,4 +1,22 @@
 mod health;
+mod admin;
+mod iam;
+mod bucket;
+mod object;
+mod multipart;
+mod lifecycle;
+mod policy;
+mod acl;
+mod encryption;
+mod copy;
 
 
 pub use health::health_check;
+pub use admin::{bucket_stats, get_owner_quota, presign, put_bucket_quota, put_owner_quota, stats};
+pub use iam::{
+    create_access_key, delete_access_key, delete_group, delete_group_member, delete_group_policy, delete_user,
+    delete_user_policy, get_group, get_group_policy, get_user, get_user_policy, list_groups, list_users, put_group,
+    put_group_member, put_group_policy, put_user, put_user_policy,
+};
+pub use bucket::{delete_bucket, get_bucket, head_bucket, list_buckets, put_bucket};
+pub use object::{delete_object, get_object, head_object, post_object, put_object};
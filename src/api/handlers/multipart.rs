@@ -0,0 +1,255 @@
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    api::{
+        AppState,
+        error::{ApiResult, S3Error},
+        types::{
+            BucketQuery, CompleteMultipartUpload, CompleteMultipartUploadResult, InitiateMultipartUploadResult,
+            ListMultipartUploadsResult, ListPartsResult, ObjectQuery, Owner, PartEntry, S3_XMLNS, STORAGE_CLASS,
+            UploadEntry, Xml, s3_timestamp,
+        },
+    },
+    auth::Principal,
+    storage::{CompletedPart, PutObjectOptions, ServerSideEncryption, UploadPartOptions},
+};
+
+use super::{acl, encryption};
+use super::object::{UploadBody, body_reader, map_payload_error, parse_max_keys, request_metadata, request_tags, version_headers};
+
+
+/// POST /{bucket}/{*key}?uploads - CreateMultipartUpload, the ACL and
+/// encryption headers apply to the completed object. A customer key has to
+/// be given again with every part and the completion.
+pub async fn create_multipart_upload(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    principal: Option<&Principal>,
+) -> ApiResult<Response> {
+    let (content_type, custom_metadata) = request_metadata(headers)?;
+    let tags = request_tags(headers)?;
+    let acl = acl::new_object_acl(state, bucket, headers, principal).await?;
+    let (encryption, customer_key) = encryption::request_write_encryption(headers)?;
+
+    let options = PutObjectOptions {
+        content_type,
+        custom_metadata,
+        tags,
+        expected_checksums: Vec::new(),
+        acl,
+        encryption,
+        customer_key: customer_key.clone(),
+        content_length: None,
+    };
+    let upload = state.storage.create_multipart_upload(bucket, key, options).await?;
+
+    Ok((encryption::encryption_headers(upload.encryption, customer_key.as_ref()), Xml(InitiateMultipartUploadResult {
+        xmlns: S3_XMLNS,
+        bucket: upload.bucket,
+        key: upload.key,
+        upload_id: upload.upload_id,
+    })).into_response())
+}
+
+
+/// PUT /{bucket}/{*key}?partNumber=N&uploadId=ID - UploadPart
+pub async fn upload_part(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    query: &ObjectQuery,
+    headers: &HeaderMap,
+    body: UploadBody,
+) -> ApiResult<Response> {
+    let part_number = query
+        .part_number
+        .as_deref()
+        .and_then(|n| n.parse::<u32>().ok())
+        .ok_or_else(|| S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "Part number must be an integer between 1 and 10000, inclusive",
+        ))?;
+
+    let customer_key = encryption::request_customer_key(headers)?;
+    let (reader, expected_checksums) = body_reader(headers, body, state.limits.max_object_size)?;
+    let options = UploadPartOptions { expected_checksums, customer_key: customer_key.clone() };
+
+    let part = state
+        .storage
+        .upload_part(bucket, key, upload_id, part_number, reader, options)
+        .await
+        .map_err(|e| map_payload_error(e, headers))?;
+
+    let encryption = customer_key.as_ref().map(|_| ServerSideEncryption::CustomerKey);
+
+    Ok((
+        encryption::encryption_headers(encryption, customer_key.as_ref()),
+        [(header::ETAG, format!("\"{}\"", part.etag))],
+    ).into_response())
+}
+
+
+/// POST /{bucket}/{*key}?uploadId=ID - CompleteMultipartUpload
+pub async fn complete_multipart_upload(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> ApiResult<Response> {
+    let request: CompleteMultipartUpload = std::str::from_utf8(&body)
+        .ok()
+        .and_then(|xml| quick_xml::de::from_str(xml).ok())
+        .ok_or_else(S3Error::malformed_xml)?;
+
+    let parts: Vec<CompletedPart> = request
+        .parts
+        .into_iter()
+        .map(|p| CompletedPart { part_number: p.part_number, etag: p.etag })
+        .collect();
+
+    let customer_key = encryption::request_customer_key(headers)?;
+    let metadata = state.storage.complete_multipart_upload(bucket, key, upload_id, &parts, customer_key.as_ref()).await?;
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost");
+
+    Ok((
+        version_headers(&metadata.version_id),
+        encryption::encryption_headers(metadata.encryption, customer_key.as_ref()),
+        Xml(CompleteMultipartUploadResult {
+            xmlns: S3_XMLNS,
+            location: format!("http://{host}/{bucket}/{key}"),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            etag: format!("\"{}\"", metadata.etag),
+        }),
+    ).into_response())
+}
+
+
+/// DELETE /{bucket}/{*key}?uploadId=ID - AbortMultipartUpload
+pub async fn abort_multipart_upload(state: &AppState, bucket: &str, key: &str, upload_id: &str) -> ApiResult<Response> {
+    state.storage.abort_multipart_upload(bucket, key, upload_id).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+
+/// GET /{bucket}/{*key}?uploadId=ID - ListParts
+pub async fn list_parts(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    query: &ObjectQuery,
+) -> ApiResult<Response> {
+    let max_parts = parse_max_keys(query.max_parts.as_deref(), "max-parts")?;
+    let marker = match query.part_number_marker.as_deref() {
+        Some(m) => m.parse::<u32>().map_err(|_| S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            "part-number-marker must be an integer",
+        ))?,
+        None => 0,
+    };
+
+    let (upload, parts) = state.storage.list_parts(bucket, key, upload_id).await?;
+
+    let mut remaining = parts.into_iter().filter(|p| p.part_number > marker).peekable();
+    let page: Vec<PartEntry> = remaining
+        .by_ref()
+        .take(max_parts)
+        .map(|p| PartEntry {
+            part_number: p.part_number,
+            last_modified: s3_timestamp(&p.last_modified),
+            etag: format!("\"{}\"", p.etag),
+            size: p.size,
+        })
+        .collect();
+    let is_truncated = remaining.peek().is_some();
+
+    Ok(Xml(ListPartsResult {
+        xmlns: S3_XMLNS,
+        bucket: upload.bucket,
+        key: upload.key,
+        upload_id: upload.upload_id,
+        initiator: Owner::default(),
+        owner: Owner::default(),
+        storage_class: STORAGE_CLASS,
+        part_number_marker: marker,
+        next_part_number_marker: page.last().map(|p| p.part_number).unwrap_or(marker),
+        max_parts,
+        is_truncated,
+        parts: page,
+    }).into_response())
+}
+
+
+/// GET /{bucket}?uploads - ListMultipartUploads
+pub async fn list_multipart_uploads(state: &AppState, bucket: &str, query: &BucketQuery) -> ApiResult<Response> {
+    let max_uploads = parse_max_keys(query.max_uploads.as_deref(), "max-uploads")?;
+    let prefix = query.prefix.clone().unwrap_or_default();
+    let key_marker = query.key_marker.clone().unwrap_or_default();
+    let upload_id_marker = query.upload_id_marker.clone().unwrap_or_default();
+
+    let uploads = state.storage.list_multipart_uploads(bucket).await?;
+
+    // uploads come ordered by key; with an upload id marker resume right after
+    // that upload within the marker key, otherwise after the marker key entirely
+    let mut past_marker = false;
+    let mut remaining = uploads
+        .into_iter()
+        .filter(|u| u.key.starts_with(&prefix))
+        .filter(|u| {
+            if key_marker.is_empty() || u.key > key_marker {
+                true
+            } else if u.key < key_marker || upload_id_marker.is_empty() {
+                false
+            } else if past_marker {
+                true
+            } else {
+                past_marker = u.upload_id == upload_id_marker;
+                false
+            }
+        })
+        .peekable();
+
+    let page: Vec<UploadEntry> = remaining
+        .by_ref()
+        .take(max_uploads)
+        .map(|u| UploadEntry {
+            key: u.key,
+            upload_id: u.upload_id,
+            initiator: Owner::default(),
+            owner: Owner::default(),
+            storage_class: STORAGE_CLASS,
+            initiated: s3_timestamp(&u.initiated),
+        })
+        .collect();
+    let is_truncated = remaining.peek().is_some();
+
+    Ok(Xml(ListMultipartUploadsResult {
+        xmlns: S3_XMLNS,
+        bucket: bucket.to_string(),
+        key_marker,
+        upload_id_marker,
+        next_key_marker: page.last().map(|u| u.key.clone()).unwrap_or_default(),
+        next_upload_id_marker: page.last().map(|u| u.upload_id.clone()).unwrap_or_default(),
+        prefix,
+        max_uploads,
+        is_truncated,
+        uploads: page,
+    }).into_response())
+}
@@ -0,0 +1,786 @@
+use std::collections::HashMap;
+
+use axum::{
+    Extension,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use futures_util::TryStreamExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::{
+    api::{AppState, error::{ApiResult, S3Error}, types::ObjectQuery},
+    auth::{self, COPY_SOURCE_HEADER, Framing, Principal, sigv4::{self, ChunkSigner}},
+    error::{AuthError, StorageError},
+    storage::{
+        ByteRange, ChecksumAlgorithm, Checksums, CustomerKey, NULL_VERSION_ID, ObjectMetadata, ObjectReader, PutObjectOptions,
+        ServerSideEncryption,
+    },
+};
+
+use super::{acl, copy, encryption, multipart};
+
+/// Chunk size objects are streamed back to clients in
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Checksum headers a client can send, with the digest size of each
+const CHECKSUM_HEADERS: [(&str, ChecksumAlgorithm, usize); 4] = [
+    ("x-amz-checksum-crc32", ChecksumAlgorithm::Crc32, 4),
+    ("x-amz-checksum-crc32c", ChecksumAlgorithm::Crc32c, 4),
+    ("x-amz-checksum-sha1", ChecksumAlgorithm::Sha1, 20),
+    ("x-amz-checksum-sha256", ChecksumAlgorithm::Sha256, 32),
+];
+
+/// Checksum header sent after the data of an `aws-chunked` body
+const TRAILER_HEADER: &str = "x-amz-trailer";
+
+/// Length of an `aws-chunked` body without the chunk framing
+const DECODED_CONTENT_LENGTH_HEADER: &str = "x-amz-decoded-content-length";
+
+/// Prefix of headers carrying user-defined object metadata
+const USER_METADATA_PREFIX: &str = "x-amz-meta-";
+
+/// Combined size of all user-defined metadata names and values, same as S3
+const MAX_USER_METADATA_SIZE: usize = 2 * 1024;
+
+/// Tags of an object, given as a URL query string
+const TAGGING_HEADER: &str = "x-amz-tagging";
+
+/// Limits on object tags, same as S3
+const MAX_TAGS: usize = 10;
+const MAX_TAG_KEY_LENGTH: usize = 128;
+const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+/// Version of the object a request wrote, read or deleted
+const VERSION_ID_HEADER: &str = "x-amz-version-id";
+
+/// Set when a request hit or created a delete marker
+const DELETE_MARKER_HEADER: &str = "x-amz-delete-marker";
+
+/// Page size of listings when the client doesn't ask for one, also the maximum
+const MAX_KEYS: usize = 1000;
+
+
+/// PUT /{bucket}/{*key} - PutObject, UploadPart with `?uploadId`, PutObjectAcl with `?acl`,
+/// CopyObject with `x-amz-copy-source`
+///
+/// The body is streamed straight to disk, it is never buffered whole.
+pub async fn put_object(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    chunk_signer: Option<Extension<ChunkSigner>>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> ApiResult<Response> {
+    if query.acl.is_some() {
+        return acl::put_object_acl(&state, &bucket, &key, query.version_id.as_deref(), &headers, body).await;
+    }
+
+    let principal = principal.as_ref().map(|Extension(p)| p);
+    let chunk_signer = chunk_signer.map(|Extension(s)| s);
+    let copy = headers.contains_key(COPY_SOURCE_HEADER);
+
+    if let Some(upload_id) = &query.upload_id {
+        // UploadPartCopy
+        if copy {
+            return Err(S3Error::not_implemented());
+        }
+
+        let body = UploadBody { body, chunk_signer };
+        return multipart::upload_part(&state, &bucket, &key, upload_id, &query, &headers, body).await;
+    }
+
+    if copy {
+        return copy::copy_object(&state, &bucket, &key, &headers, principal).await;
+    }
+
+    let (content_type, custom_metadata) = request_metadata(&headers)?;
+    let tags = request_tags(&headers)?;
+    let acl = acl::new_object_acl(&state, &bucket, &headers, principal).await?;
+    let (encryption, customer_key) = encryption::request_write_encryption(&headers)?;
+    let trailer = trailer_checksum(&headers)?;
+    let (reader, expected_checksums) = body_reader(&headers, UploadBody { body, chunk_signer }, state.limits.max_object_size)?;
+
+    let options = PutObjectOptions {
+        content_type,
+        custom_metadata,
+        tags,
+        expected_checksums,
+        acl,
+        encryption,
+        customer_key: customer_key.clone(),
+        content_length: Some(content_length(&headers)?),
+    };
+
+    let metadata = state
+        .storage
+        .put_object(&bucket, &key, reader, options)
+        .await
+        .map_err(|e| map_payload_error(e, &headers))?;
+
+    // echo the checksums the client sent, like S3 does
+    let mut response_headers = checksum_headers(&metadata.checksums, |name| {
+        headers.contains_key(name) || trailer.is_some_and(|(trailer, _)| trailer == name)
+    });
+    response_headers.insert(header::ETAG, header_value(&format!("\"{}\"", metadata.etag)));
+    response_headers.extend(version_headers(&metadata.version_id));
+    response_headers.extend(encryption::encryption_headers(metadata.encryption, customer_key.as_ref()));
+
+    Ok(response_headers.into_response())
+}
+
+
+/// POST /{bucket}/{*key} - CreateMultipartUpload with `?uploads`,
+/// CompleteMultipartUpload with `?uploadId`
+pub async fn post_object(
+    State(state): State<AppState>,
+    principal: Option<Extension<Principal>>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<Response> {
+    if query.uploads.is_some() {
+        let principal = principal.as_ref().map(|Extension(p)| p);
+        return multipart::create_multipart_upload(&state, &bucket, &key, &headers, principal).await;
+    }
+
+    if let Some(upload_id) = &query.upload_id {
+        return multipart::complete_multipart_upload(&state, &bucket, &key, upload_id, &headers, body).await;
+    }
+
+    Err(S3Error::not_implemented())
+}
+
+
+/// GET /{bucket}/{*key} - GetObject, ListParts with `?uploadId`, GetObjectAcl with `?acl`
+///
+/// Honours a single `Range: bytes=..` with `206 Partial Content` and reads
+/// an older version of the object with `?versionId`. Objects encrypted with
+/// a customer key need the key in the `x-amz-server-side-encryption-customer-*`
+/// headers.
+pub async fn get_object(
+    State(state): State<AppState>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    if query.acl.is_some() {
+        return acl::get_object_acl(&state, &bucket, &key, query.version_id.as_deref()).await;
+    }
+
+    if let Some(upload_id) = &query.upload_id {
+        return multipart::list_parts(&state, &bucket, &key, upload_id, &query).await;
+    }
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_range);
+
+    let customer_key = encryption::request_customer_key(&headers)?;
+
+    let content = match state
+        .storage
+        .get_object(&bucket, &key, query.version_id.as_deref(), range, customer_key.as_ref())
+        .await
+    {
+        Ok(content) => content,
+        Err(e @ StorageError::DeleteMarker { .. }) => return Ok(delete_marker_error(e)),
+        Err(StorageError::InvalidRange(size)) => {
+            return Ok((
+                [(header::CONTENT_RANGE, format!("bytes */{size}"))],
+                S3Error::from(StorageError::InvalidRange(size)),
+            ).into_response());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let body = Body::from_stream(ReaderStream::with_capacity(content.reader, READ_CHUNK_SIZE));
+
+    let mut response_headers = object_headers(&content.metadata, customer_key.as_ref());
+
+    // checksums describe the whole object, they are left out of partial responses
+    if content.range.is_none() {
+        response_headers.extend(object_checksum_headers(&content.metadata, &headers));
+    }
+
+    let status = match content.range {
+        Some((start, end)) => {
+            response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                header_value(&format!("bytes {start}-{end}/{}", content.metadata.size)),
+            );
+            StatusCode::PARTIAL_CONTENT
+        }
+        None => StatusCode::OK,
+    };
+
+    Ok((status, response_headers, body).into_response())
+}
+
+
+/// HEAD /{bucket}/{*key} - HeadObject, with the customer key like GetObject
+pub async fn head_object(
+    State(state): State<AppState>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let customer_key = encryption::request_customer_key(&headers)?;
+
+    let metadata = match state
+        .storage
+        .head_object(&bucket, &key, query.version_id.as_deref(), customer_key.as_ref())
+        .await
+    {
+        Ok(metadata) => metadata,
+        Err(e @ StorageError::DeleteMarker { .. }) => return Ok(delete_marker_error(e)),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut response_headers = object_headers(&metadata, customer_key.as_ref());
+    response_headers.extend(object_checksum_headers(&metadata, &headers));
+
+    Ok(response_headers.into_response())
+}
+
+
+/// DELETE /{bucket}/{*key} - DeleteObject, or AbortMultipartUpload with `?uploadId`
+///
+/// With `?versionId` that version is removed for good, otherwise a versioned
+/// bucket gets a delete marker.
+pub async fn delete_object(
+    State(state): State<AppState>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(query): Query<ObjectQuery>,
+) -> ApiResult<Response> {
+    if let Some(upload_id) = &query.upload_id {
+        return multipart::abort_multipart_upload(&state, &bucket, &key, upload_id).await;
+    }
+
+    let deleted = state.storage.delete_object(&bucket, &key, query.version_id.as_deref()).await?;
+
+    let mut response_headers = HeaderMap::new();
+
+    if let Some(version_id) = &deleted.version_id {
+        response_headers.insert(VERSION_ID_HEADER, header_value(version_id));
+    }
+
+    if deleted.delete_marker {
+        response_headers.insert(DELETE_MARKER_HEADER, HeaderValue::from_static("true"));
+    }
+
+    Ok((StatusCode::NO_CONTENT, response_headers).into_response())
+}
+
+
+/// The error for a delete marker standing in for an object, with headers
+/// telling the client which marker it hit
+fn delete_marker_error(err: StorageError) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(DELETE_MARKER_HEADER, HeaderValue::from_static("true"));
+
+    if let StorageError::DeleteMarker { version_id, .. } = &err {
+        headers.extend(version_headers(version_id));
+    }
+
+    (headers, S3Error::from(err)).into_response()
+}
+
+
+/// `x-amz-version-id` for a version, objects written without versioning don't get one
+pub(super) fn version_headers(version_id: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if version_id != NULL_VERSION_ID {
+        headers.insert(VERSION_ID_HEADER, header_value(version_id));
+    }
+
+    headers
+}
+
+
+/// A single range from a `Range` header. Anything else, multiple ranges and
+/// ones ending before they start included, is ignored and the whole object
+/// served, like S3 does.
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+
+    if spec.contains(',') {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        return end.parse().ok().map(ByteRange::Suffix);
+    }
+
+    let start = start.parse().ok()?;
+    let end = match end {
+        "" => None,
+        end => Some(end.parse().ok().filter(|&end| end >= start)?),
+    };
+
+    Some(ByteRange::FromTo(start, end))
+}
+
+
+/// Headers describing a whole object, shared by GetObject and HeadObject
+fn object_headers(metadata: &ObjectMetadata, customer_key: Option<&CustomerKey>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&metadata.content_type).unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(metadata.size));
+    headers.insert(header::ETAG, header_value(&format!("\"{}\"", metadata.etag)));
+    headers.insert(
+        header::LAST_MODIFIED,
+        header_value(&httpdate::fmt_http_date(metadata.modified_at.into())),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.extend(version_headers(&metadata.version_id));
+    headers.extend(encryption::encryption_headers(metadata.encryption, customer_key));
+
+    if !metadata.tags.is_empty() {
+        headers.insert("x-amz-tagging-count", HeaderValue::from(metadata.tags.len()));
+    }
+
+    for (name, value) in &metadata.custom_metadata {
+        let name = HeaderName::try_from(format!("{USER_METADATA_PREFIX}{name}"));
+
+        // values that can't be sent as a header are left out rather than failing the request
+        if let (Ok(name), Ok(value)) = (name, HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    }
+
+    headers
+}
+
+
+/// Content type and `x-amz-meta-*` values a client sent along with an object
+pub(super) fn request_metadata(headers: &HeaderMap) -> ApiResult<(Option<String>, HashMap<String, String>)> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let mut custom_metadata = HashMap::new();
+    let mut total_size = 0;
+
+    for (name, value) in headers {
+        let Some(name) = name.as_str().strip_prefix(USER_METADATA_PREFIX) else {
+            continue;
+        };
+
+        let value = value.to_str().map_err(|_| S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidArgument",
+            format!("Value of {USER_METADATA_PREFIX}{name} must be ASCII"),
+        ))?;
+
+        total_size += name.len() + value.len();
+        custom_metadata.insert(name.to_string(), value.to_string());
+    }
+
+    if total_size > MAX_USER_METADATA_SIZE {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "MetadataTooLarge",
+            "Your metadata headers exceed the maximum allowed metadata size.",
+        ));
+    }
+
+    Ok((content_type, custom_metadata))
+}
+
+
+/// Tags a client sent along with an object in `x-amz-tagging`
+pub(super) fn request_tags(headers: &HeaderMap) -> ApiResult<HashMap<String, String>> {
+    let Some(value) = headers.get(TAGGING_HEADER) else {
+        return Ok(HashMap::new());
+    };
+
+    let invalid = |message: &str| S3Error::new(StatusCode::BAD_REQUEST, "InvalidTag", message);
+
+    let value = value.to_str().map_err(|_| invalid("The header 'x-amz-tagging' shall be encoded as UTF-8 then URLEncoded URL query parameters without tag name duplicates."))?;
+    let mut tags = HashMap::new();
+
+    for (key, value) in sigv4::query_pairs(value) {
+        if key.is_empty() || key.chars().count() > MAX_TAG_KEY_LENGTH {
+            return Err(invalid("The TagKey you have provided is invalid"));
+        }
+
+        if value.chars().count() > MAX_TAG_VALUE_LENGTH {
+            return Err(invalid("The TagValue you have provided is invalid"));
+        }
+
+        if tags.insert(key, value).is_some() {
+            return Err(invalid("Cannot provide multiple Tags with the same key"));
+        }
+    }
+
+    if tags.len() > MAX_TAGS {
+        return Err(invalid("Object tags cannot be greater than 10"));
+    }
+
+    Ok(tags)
+}
+
+
+fn header_value(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).expect("header values we build are ASCII")
+}
+
+
+/// Declared length of an upload body, which is required, for an `aws-chunked`
+/// body the length of its data
+fn content_length(headers: &HeaderMap) -> ApiResult<u64> {
+    let name = match chunked(headers) {
+        Some(_) => HeaderName::from_static(DECODED_CONTENT_LENGTH_HEADER),
+        None => header::CONTENT_LENGTH,
+    };
+
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| S3Error::new(
+            StatusCode::LENGTH_REQUIRED,
+            "MissingContentLength",
+            "You must provide the Content-Length HTTP header.",
+        ))
+}
+
+
+/// Body of an upload, with what checks its chunks if it is sent as `aws-chunked`
+pub(super) struct UploadBody {
+    pub body: Body,
+    pub chunk_signer: Option<ChunkSigner>,
+}
+
+
+/// Stream over an upload body, after checking its declared length against
+/// `max_size`, together with the digests it has to match: the payload hash it
+/// was signed with, `Content-MD5` and any `x-amz-checksum-*`. The framing of an
+/// `aws-chunked` body is stripped and its chunks checked.
+pub(super) fn body_reader(
+    headers: &HeaderMap,
+    UploadBody { body, chunk_signer }: UploadBody,
+    max_size: u64,
+) -> ApiResult<(ObjectReader, Vec<(ChecksumAlgorithm, String)>)> {
+    let content_length = content_length(headers)?;
+
+    if content_length > max_size {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "EntityTooLarge",
+            "Your proposed upload exceeds the maximum allowed object size.",
+        ));
+    }
+
+    let mut expected = Vec::new();
+
+    // a signed payload hash has to match the body we actually received
+    if let Some(sha256) = signed_payload_hash(headers) {
+        expected.push((ChecksumAlgorithm::Sha256, sha256.to_string()));
+    }
+
+    if let Some(value) = headers.get("content-md5") {
+        let digest = decode_digest(value, 16).ok_or_else(|| S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidDigest",
+            "The Content-MD5 you specified was invalid.",
+        ))?;
+
+        expected.push((ChecksumAlgorithm::Md5, digest));
+    }
+
+    for (name, algorithm, len) in CHECKSUM_HEADERS {
+        if let Some(value) = headers.get(name) {
+            let digest = decode_digest(value, len).ok_or_else(|| S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                format!("Value for {name} header is invalid."),
+            ))?;
+
+            expected.push((algorithm, digest));
+        }
+    }
+
+    let reader = StreamReader::new(body.into_data_stream().map_err(std::io::Error::other));
+
+    let Some(payload) = chunked(headers) else {
+        return Ok((Box::pin(reader), expected));
+    };
+
+    let framing = Framing {
+        signed: payload != sigv4::STREAMING_UNSIGNED_PAYLOAD_TRAILER,
+        signer: chunk_signer,
+        decoded_length: content_length,
+        trailer: trailer_checksum(headers)?.map(|(name, algorithm)| (name.to_string(), algorithm)),
+    };
+
+    Ok((Box::pin(auth::decode_chunked(reader, framing)), expected))
+}
+
+
+/// The `x-amz-content-sha256` of a body sent as `aws-chunked`
+fn chunked(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(sigv4::CONTENT_SHA256_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.starts_with("STREAMING-"))
+}
+
+
+/// The checksum header `x-amz-trailer` announces after an `aws-chunked` body
+fn trailer_checksum(headers: &HeaderMap) -> ApiResult<Option<(&'static str, ChecksumAlgorithm)>> {
+    let Some(value) = headers.get(TRAILER_HEADER) else {
+        return Ok(None);
+    };
+
+    CHECKSUM_HEADERS
+        .into_iter()
+        .find(|(name, ..)| value.as_bytes().eq_ignore_ascii_case(name.as_bytes()))
+        .map(|(name, algorithm, _)| Some((name, algorithm)))
+        .ok_or_else(|| S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "InvalidRequest",
+            format!("Value for {TRAILER_HEADER} header is invalid."),
+        ))
+}
+
+
+/// A mismatch of the signed payload hash is a signing error, any other digest is a BadDigest
+pub(super) fn map_payload_error(err: StorageError, headers: &HeaderMap) -> S3Error {
+    match err {
+        StorageError::ChecksumMismatch { expected, .. }
+            if signed_payload_hash(headers).is_some_and(|sha256| sha256.eq_ignore_ascii_case(&expected)) =>
+        {
+            AuthError::ContentSha256Mismatch.into()
+        }
+        e => S3Error::from(e),
+    }
+}
+
+
+fn signed_payload_hash(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(sigv4::CONTENT_SHA256_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| *v != sigv4::UNSIGNED_PAYLOAD && !v.starts_with("STREAMING-"))
+}
+
+
+/// Base64 digest from a header as hex, if it decodes to `len` bytes
+fn decode_digest(value: &HeaderValue, len: usize) -> Option<String> {
+    BASE64
+        .decode(value.as_bytes())
+        .ok()
+        .filter(|digest| digest.len() == len)
+        .map(hex::encode)
+}
+
+
+/// `x-amz-checksum-*` headers for the algorithms `include` picks
+fn checksum_headers(checksums: &Checksums, include: impl Fn(&str) -> bool) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for (name, algorithm, _) in CHECKSUM_HEADERS {
+        // objects stored before an algorithm was supported have no value for it
+        let Ok(digest) = hex::decode(checksums.get(algorithm)) else { continue };
+
+        if !digest.is_empty() && include(name) {
+            headers.insert(name, header_value(&BASE64.encode(digest)));
+        }
+    }
+
+    headers
+}
+
+
+/// Checksums served with a whole object: the SHA-256 for clients checking
+/// integrity end to end, the other algorithms only when asked for with
+/// `x-amz-checksum-mode: ENABLED` as S3 does. Objects under a customer key
+/// only get them when asked for, they fingerprint the plaintext.
+fn object_checksum_headers(metadata: &ObjectMetadata, request_headers: &HeaderMap) -> HeaderMap {
+    let checksum_mode = request_headers
+        .get("x-amz-checksum-mode")
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"ENABLED"));
+    let customer_key = metadata.encryption == Some(ServerSideEncryption::CustomerKey);
+
+    checksum_headers(&metadata.checksums, |name| {
+        checksum_mode || (name == "x-amz-checksum-sha256" && !customer_key)
+    })
+}
+
+
+/// `max-keys` style page size parameter, capped at `MAX_KEYS`
+pub(super) fn parse_max_keys(value: Option<&str>, name: &str) -> ApiResult<usize> {
+    match value {
+        None => Ok(MAX_KEYS),
+        Some(v) => v
+            .parse::<usize>()
+            .map(|n| n.min(MAX_KEYS))
+            .map_err(|_| S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                format!("{name} must be a non-negative integer"),
+            )),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99"), Some(ByteRange::FromTo(0, Some(99))));
+        assert_eq!(parse_range("bytes=5-5"), Some(ByteRange::FromTo(5, Some(5))));
+        assert_eq!(parse_range("bytes=100-"), Some(ByteRange::FromTo(100, None)));
+        assert_eq!(parse_range("bytes=-20"), Some(ByteRange::Suffix(20)));
+        assert_eq!(parse_range(" bytes= 1 - 2 "), Some(ByteRange::FromTo(1, Some(2))));
+    }
+
+    #[test]
+    fn ignores_what_it_cannot_serve() {
+        // ending before the start, served whole rather than refused
+        assert_eq!(parse_range("bytes=5-2"), None);
+        assert_eq!(parse_range("bytes=0-1,4-5"), None);
+        assert_eq!(parse_range("items=0-1"), None);
+        assert_eq!(parse_range("bytes=a-1"), None);
+        assert_eq!(parse_range("bytes=1-b"), None);
+        assert_eq!(parse_range("bytes=-"), None);
+        assert_eq!(parse_range("bytes=5"), None);
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (HeaderName::from_static(name), header_value(value))).collect()
+    }
+
+    fn expected_digests(pairs: &[(&'static str, &str)]) -> ApiResult<Vec<(ChecksumAlgorithm, String)>> {
+        let mut headers = headers(pairs);
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+        let body = UploadBody { body: Body::empty(), chunk_signer: None };
+
+        body_reader(&headers, body, u64::MAX).map(|(_, expected)| expected)
+    }
+
+    #[test]
+    fn decodes_base64_digests_to_hex() {
+        assert_eq!(decode_digest(&header_value("y/Q5Jg=="), 4).as_deref(), Some("cbf43926"));
+        assert_eq!(
+            decode_digest(&header_value("XUFAKrxLKna5cZ2REBfFkg=="), 16).as_deref(),
+            Some("5d41402abc4b2a76b9719d911017c592"),
+        );
+
+        // wrong length, not base64, hex instead of base64
+        assert_eq!(decode_digest(&header_value("y/Q5Jg=="), 16), None);
+        assert_eq!(decode_digest(&header_value("not base64!"), 4), None);
+        assert_eq!(decode_digest(&header_value("cbf43926"), 4), None);
+    }
+
+    fn metadata(encryption: Option<ServerSideEncryption>) -> ObjectMetadata {
+        ObjectMetadata {
+            key: "key".to_string(),
+            version_id: NULL_VERSION_ID.to_string(),
+            size: 5,
+            content_type: "text/plain".to_string(),
+            etag: "5d41402abc4b2a76b9719d911017c592".to_string(),
+            checksums: Checksums {
+                md5: "5d41402abc4b2a76b9719d911017c592".to_string(),
+                crc32: "cbf43926".to_string(),
+                crc32c: "e3069283".to_string(),
+                // stored before the algorithm was supported
+                sha1: String::new(),
+                sha256: "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225".to_string(),
+            },
+            created_at: chrono::Utc::now(),
+            modified_at: chrono::Utc::now(),
+            custom_metadata: HashMap::new(),
+            tags: HashMap::new(),
+            encryption,
+        }
+    }
+
+    #[test]
+    fn encodes_hex_checksums_as_base64() {
+        let checksums = metadata(None).checksums;
+
+        let all = checksum_headers(&checksums, |_| true);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all["x-amz-checksum-crc32"], "y/Q5Jg==");
+        assert_eq!(all["x-amz-checksum-crc32c"], "4waSgw==");
+        assert_eq!(all["x-amz-checksum-sha256"], "FeKw08M4keuw8e9gnsQZQgwg4yDOlMZfvIwzEkSOsiU=");
+        assert!(!all.contains_key("x-amz-checksum-sha1"));
+
+        // round trip back to the stored digest
+        for (name, algorithm, len) in CHECKSUM_HEADERS {
+            if let Some(value) = all.get(name) {
+                assert_eq!(decode_digest(value, len).as_deref(), Some(checksums.get(algorithm)), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn serves_the_sha256_unless_under_a_customer_key() {
+        let checksum_mode = headers(&[("x-amz-checksum-mode", "enabled")]);
+
+        let plaintext = metadata(None);
+        let served = object_checksum_headers(&plaintext, &HeaderMap::new());
+        assert_eq!(served.keys().collect::<Vec<_>>(), ["x-amz-checksum-sha256"]);
+        assert_eq!(object_checksum_headers(&plaintext, &checksum_mode).len(), 3);
+
+        let sse_c = metadata(Some(ServerSideEncryption::CustomerKey));
+        assert!(object_checksum_headers(&sse_c, &HeaderMap::new()).is_empty());
+        assert_eq!(object_checksum_headers(&sse_c, &checksum_mode).len(), 3);
+
+        let sse_s3 = metadata(Some(ServerSideEncryption::Aes256));
+        assert_eq!(object_checksum_headers(&sse_s3, &HeaderMap::new()).len(), 1);
+    }
+
+    #[test]
+    fn reads_expected_digests_from_headers() {
+        let expected = expected_digests(&[
+            ("content-md5", "XUFAKrxLKna5cZ2REBfFkg=="),
+            ("x-amz-checksum-crc32", "y/Q5Jg=="),
+        ])
+        .unwrap();
+        assert_eq!(expected, [
+            (ChecksumAlgorithm::Md5, "5d41402abc4b2a76b9719d911017c592".to_string()),
+            (ChecksumAlgorithm::Crc32, "cbf43926".to_string()),
+        ]);
+
+        let err = expected_digests(&[("content-md5", "y/Q5Jg==")]).unwrap_err();
+        assert_eq!((err.status, err.code), (StatusCode::BAD_REQUEST, "InvalidDigest"));
+
+        let err = expected_digests(&[("x-amz-checksum-sha256", "y/Q5Jg==")]).unwrap_err();
+        assert_eq!((err.status, err.code), (StatusCode::BAD_REQUEST, "InvalidRequest"));
+    }
+
+    #[test]
+    fn mismatches_are_bad_digests() {
+        let mismatch = || StorageError::ChecksumMismatch { expected: "00".to_string(), actual: "ff".to_string() };
+
+        let err = map_payload_error(mismatch(), &HeaderMap::new());
+        assert_eq!((err.status, err.code), (StatusCode::BAD_REQUEST, "BadDigest"));
+
+        // unless it is the payload hash the request was signed with
+        let err = map_payload_error(mismatch(), &headers(&[("x-amz-content-sha256", "00")]));
+        assert_eq!(err.code, "XAmzContentSHA256Mismatch");
+    }
+}
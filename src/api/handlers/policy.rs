@@ -0,0 +1,58 @@
+use axum::{
+    body::Bytes,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    api::{AppState, error::{ApiResult, S3Error}},
+    auth::PolicyDocument,
+    error::AuthError,
+};
+
+/// Largest bucket policy S3 accepts
+const MAX_POLICY_SIZE: usize = 20 * 1024;
+
+
+/// GET /{bucket}?policy - GetBucketPolicy, the document as it was put
+pub async fn get_bucket_policy(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    let policy = state.storage.get_bucket_policy(bucket).await?;
+
+    Ok(([(header::CONTENT_TYPE, "application/json")], policy).into_response())
+}
+
+
+/// PUT /{bucket}?policy - PutBucketPolicy
+///
+/// Statements name their principals: `"*"` for anyone, anonymous requests
+/// included, or `arn:aws:iam::<account>:user/<name>` for a user. Conditions
+/// on `aws:SourceIp` and `aws:SecureTransport` are supported.
+pub async fn put_bucket_policy(state: &AppState, bucket: &str, body: Bytes) -> ApiResult<Response> {
+    if body.len() > MAX_POLICY_SIZE {
+        return Err(malformed_policy(format!("Policies must be at most {MAX_POLICY_SIZE} bytes")));
+    }
+
+    let policy = std::str::from_utf8(&body).map_err(|_| malformed_policy("Policies must be UTF-8".to_string()))?;
+
+    PolicyDocument::parse_bucket_policy(policy, bucket).map_err(|e| match e {
+        AuthError::MalformedPolicy(message) => malformed_policy(message),
+        e => e.into(),
+    })?;
+
+    state.storage.put_bucket_policy(bucket, policy).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+
+/// DELETE /{bucket}?policy - DeleteBucketPolicy
+pub async fn delete_bucket_policy(state: &AppState, bucket: &str) -> ApiResult<Response> {
+    state.storage.delete_bucket_policy(bucket).await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+
+fn malformed_policy(message: String) -> S3Error {
+    S3Error::new(StatusCode::BAD_REQUEST, "MalformedPolicy", message)
+}
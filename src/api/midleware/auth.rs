@@ -0,0 +1,276 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+
+use crate::{
+    api::{AppState, client::{self, Client}, error::{ApiResult, S3Error}},
+    auth::{self, AccessRequest, COPY_SOURCE_HEADER, Credential, Decision, PolicyDocument, Principal, sigv4::{self, ChunkSigner}},
+    error::AuthError,
+    storage::Permission,
+};
+
+/// The one admin endpoint users may call, for URLs signed with their own key
+const PRESIGN_PATH: &str = "/.admin/presign";
+
+
+/// Rejects requests that are not signed with a known access key, or that
+/// neither the policies of the signing user and of the bucket nor the ACLs of
+/// the bucket and object allow, and records
+/// the signing `Principal` and its `Credential` for the handlers, along with
+/// the `ChunkSigner` checking the chunks of a body streamed as `aws-chunked`. Unsigned
+/// requests get through only where a bucket policy or an ACL allows anyone.
+///
+/// Authentication is skipped entirely when no root credentials are configured,
+/// which the server only starts with while there is no master key and no users.
+pub async fn authenticate(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    if state.credentials.is_empty() {
+        return next.run(req).await;
+    }
+
+    // the address of the connection, X-Forwarded-For only from a trusted proxy as any client can set it
+    let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    let client = client::client(peer, req.headers(), &state.trusted_proxies);
+
+    match authorize(&state, req.method(), req.uri(), req.headers(), client).await {
+        Ok(Some((principal, credential, chunk_signer))) => {
+            req.extensions_mut().insert(principal);
+            req.extensions_mut().insert(credential);
+
+            if let Some(chunk_signer) = chunk_signer {
+                req.extensions_mut().insert(chunk_signer);
+            }
+
+            next.run(req).await
+        }
+        Ok(None) => next.run(req).await,
+        Err(e) => {
+            tracing::debug!("rejected {} {}: {}", req.method(), req.uri().path(), e.message);
+            e.into_response()
+        }
+    }
+}
+
+
+/// Who signed the request and what checks the chunks of its body, `None` for
+/// an anonymous request a bucket policy or ACL allows
+async fn authorize(
+    state: &AppState,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    client: Client,
+) -> ApiResult<Option<(Principal, Credential, Option<ChunkSigner>)>> {
+    let signer = match sigv4::parse(uri, headers, Utc::now()) {
+        Ok(signed) => {
+            let (credential, principal) = lookup(state, &signed.access_key_id).await?;
+
+            sigv4::verify(&signed, &credential, method, uri, headers)?;
+
+            let chunk_signer = sigv4::chunk_signer(&signed, &credential);
+
+            Some((principal, credential, chunk_signer))
+        }
+        Err(AuthError::MissingAuthentication) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let principal = signer.as_ref().map(|(principal, _, _)| principal.clone());
+
+    // the root credentials may do anything, whatever a bucket policy says
+    if principal.as_ref().is_some_and(|p| p.user.is_none()) {
+        return Ok(signer);
+    }
+
+    if uri.path().starts_with("/.admin/") {
+        return match (uri.path(), &principal) {
+            (PRESIGN_PATH, Some(_)) => Ok(signer),
+            (_, None) => Err(AuthError::MissingAuthentication.into()),
+            _ => Err(S3Error::access_denied()),
+        };
+    }
+
+    let Client { ip: source_ip, secure_transport } = client;
+
+    let request = AccessRequest::from_http(method, uri, principal.clone(), source_ip, secure_transport);
+
+    check_policies(state, &request).await?;
+
+    // a copy reads its source, which has to be allowed as well
+    if let Some(source) = headers.get(COPY_SOURCE_HEADER) {
+        let source = source
+            .to_str()
+            .ok()
+            .and_then(|source| AccessRequest::copy_source(source, principal, source_ip, secure_transport))
+            .ok_or_else(|| S3Error::new(StatusCode::BAD_REQUEST, "InvalidArgument", "Invalid copy source"))?;
+
+        check_policies(state, &source).await?;
+    }
+
+    Ok(signer)
+}
+
+
+/// The key pair of an access key id and who it belongs to, a user or the root
+/// credentials of the config
+async fn lookup(state: &AppState, access_key_id: &str) -> ApiResult<(Credential, Principal)> {
+    if let Some(credential) = state.credentials.get(access_key_id) {
+        return Ok((credential.clone(), Principal::root(access_key_id)));
+    }
+
+    let Some((user, canonical_id, sealed)) = state.storage.get_access_key(access_key_id).await? else {
+        return Err(AuthError::InvalidAccessKeyId(access_key_id.to_string()).into());
+    };
+
+    let secret = state
+        .master_key
+        .as_ref()
+        .and_then(|key| key.open(&sealed))
+        .and_then(|secret| String::from_utf8(secret).ok());
+
+    let Some(secret_access_key) = secret else {
+        tracing::error!("cannot decrypt the secret of access key {access_key_id}, was the master key changed?");
+        return Err(S3Error::internal());
+    };
+
+    let principal = Principal { access_key_id: access_key_id.to_string(), user: Some(user), canonical_id };
+
+    Ok((Credential { access_key_id: access_key_id.to_string(), secret_access_key }, principal))
+}
+
+
+/// Users may only do what the policies attached to them and their groups, or
+/// the policy of the bucket, allow, and anonymous requests what the bucket
+/// policy allows anyone. What no policy decides on, ACLs may grant; a policy
+/// denying a request wins over any grant.
+async fn check_policies(state: &AppState, request: &AccessRequest) -> ApiResult<()> {
+    let mut documents = Vec::new();
+
+    if let Some(user) = request.principal.as_ref().and_then(|p| p.user.as_deref()) {
+        for json in state.storage.user_policy_documents(user).await? {
+            match PolicyDocument::parse(&json) {
+                Ok(document) => documents.push(document),
+                Err(e) => tracing::warn!("ignoring a policy of user {user}: {e}"),
+            }
+        }
+    }
+
+    if let Some(bucket) = &request.bucket
+        && let Some(json) = state.storage.bucket_policy(bucket).await?
+    {
+        match PolicyDocument::parse_bucket_policy(&json, bucket) {
+            Ok(document) => documents.push(document),
+            Err(e) => tracing::warn!("ignoring the policy of bucket {bucket}: {e}"),
+        }
+    }
+
+    match auth::evaluate(&documents, request) {
+        Decision::Allow => Ok(()),
+        Decision::Implicit if acl_grants(state, request).await? => Ok(()),
+        decision => {
+            let who = request.principal.as_ref().map_or("anonymous", |p| p.access_key_id.as_str());
+            tracing::debug!("{decision:?} {who} {} on {}", request.action, request.resource);
+
+            match request.principal {
+                Some(_) => Err(S3Error::access_denied()),
+                None => Err(AuthError::MissingAuthentication.into()),
+            }
+        }
+    }
+}
+
+
+/// Whether the ACL of the bucket or object a request is about grants it
+async fn acl_grants(state: &AppState, request: &AccessRequest) -> ApiResult<bool> {
+    let Some(bucket) = &request.bucket else {
+        return Ok(false);
+    };
+
+    let grantee = request.principal.as_ref().map(|p| p.canonical_id.as_str());
+
+    let Some((on_object, permission)) = acl_permission(request.action) else {
+        return Ok(false);
+    };
+
+    let acl = match (on_object, &request.key) {
+        (true, Some(key)) => state.storage.object_acl(bucket, key, request.version_id.as_deref()).await?,
+        (true, None) => None,
+        (false, _) => state.storage.bucket_acl(bucket).await?,
+    };
+
+    Ok(acl.is_some_and(|acl| acl.allows(permission, grantee)))
+}
+
+
+/// The ACL permission an action needs, and whether it is the object's ACL
+/// that grants it rather than the bucket's. `None` for actions ACLs don't grant.
+fn acl_permission(action: &str) -> Option<(bool, Permission)> {
+    match action {
+        "s3:ListBucket" | "s3:ListBucketVersions" | "s3:ListBucketMultipartUploads" => Some((false, Permission::Read)),
+        "s3:PutObject" | "s3:DeleteObject" | "s3:DeleteObjectVersion" | "s3:AbortMultipartUpload"
+        | "s3:ListMultipartUploadParts" => Some((false, Permission::Write)),
+        "s3:GetBucketAcl" => Some((false, Permission::ReadAcp)),
+        "s3:PutBucketAcl" => Some((false, Permission::WriteAcp)),
+        "s3:GetObject" | "s3:GetObjectVersion" => Some((true, Permission::Read)),
+        "s3:GetObjectAcl" | "s3:GetObjectVersionAcl" => Some((true, Permission::ReadAcp)),
+        "s3:PutObjectAcl" | "s3:PutObjectVersionAcl" => Some((true, Permission::WriteAcp)),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, Uri};
+
+    use super::*;
+
+    use crate::storage::{Acl, CannedAcl};
+
+    /// Whether the bucket or object ACL grants an anonymous request
+    fn anonymous_allowed(method: Method, uri: &str, bucket_acl: &Acl, object_acl: &Acl) -> bool {
+        let request = AccessRequest::from_http(&method, &uri.parse::<Uri>().unwrap(), None, None, true);
+
+        acl_permission(request.action).is_some_and(|(on_object, permission)| {
+            let acl = if on_object { object_acl } else { bucket_acl };
+            acl.allows(permission, None)
+        })
+    }
+
+    #[test]
+    fn public_read_lets_anyone_read_but_not_write() {
+        let private = Acl::private("alice");
+        let public_read = Acl::canned(CannedAcl::PublicRead, "alice", None);
+
+        // a public-read object in a private bucket
+        assert!(anonymous_allowed(Method::GET, "/b/k", &private, &public_read));
+        assert!(anonymous_allowed(Method::HEAD, "/b/k", &private, &public_read));
+        assert!(!anonymous_allowed(Method::PUT, "/b/k", &private, &public_read));
+        assert!(!anonymous_allowed(Method::DELETE, "/b/k", &private, &public_read));
+        assert!(!anonymous_allowed(Method::GET, "/b/k?acl", &private, &public_read));
+        assert!(!anonymous_allowed(Method::GET, "/b?list-type=2", &private, &public_read));
+
+        // a public-read bucket lists, but writes to it still need the owner
+        assert!(anonymous_allowed(Method::GET, "/b?list-type=2", &public_read, &private));
+        assert!(!anonymous_allowed(Method::GET, "/b/k", &public_read, &private));
+        assert!(!anonymous_allowed(Method::PUT, "/b/k", &public_read, &private));
+        assert!(!anonymous_allowed(Method::PUT, "/b?acl", &public_read, &private));
+
+        let public_read_write = Acl::canned(CannedAcl::PublicReadWrite, "alice", None);
+        assert!(anonymous_allowed(Method::PUT, "/b/k", &public_read_write, &private));
+        assert!(anonymous_allowed(Method::DELETE, "/b/k", &public_read_write, &private));
+        assert!(!anonymous_allowed(Method::DELETE, "/b", &public_read_write, &private));
+    }
+
+    #[test]
+    fn acls_dont_grant_bucket_configuration() {
+        for action in ["s3:DeleteBucket", "s3:PutBucketPolicy", "s3:PutLifecycleConfiguration", "s3:CreateBucket"] {
+            assert_eq!(acl_permission(action), None, "{action}");
+        }
+    }
+}
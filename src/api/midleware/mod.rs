@@ -0,0 +1,10 @@
+mod auth;
+mod request_id;
+mod shutdown;
+mod subresource;
+
+
+pub use auth::authenticate;
+pub use request_id::request_context;
+pub use shutdown::abort_on_shutdown;
+pub use subresource::reject_unsupported_subresources;
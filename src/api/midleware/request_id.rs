@@ -0,0 +1,38 @@
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+use crate::api::error::S3Error;
+
+
+/// Tags every request with an id and renders `S3Error`s left on the response
+/// as `<Error>` documents naming the requested resource.
+pub async fn request_context(req: Request, next: Next) -> Response {
+    let request_id = Uuid::new_v4().simple().to_string().to_uppercase();
+    let resource = req.uri().path().to_string();
+
+    let mut response = next.run(req).await;
+
+    if let Some(err) = response.extensions_mut().remove::<S3Error>() {
+        let headers = std::mem::take(response.headers_mut());
+
+        response = err.render(&resource, &request_id);
+
+        // keep anything the handler set, except what describes the old empty body
+        for (name, value) in headers.iter() {
+            if !response.headers().contains_key(name) {
+                response.headers_mut().insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("x-amz-request-id", value);
+    }
+
+    response
+}
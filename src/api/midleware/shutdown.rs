@@ -0,0 +1,58 @@
+use std::io;
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream};
+use tokio_util::sync::CancellationToken;
+
+use crate::api::{AppState, error::S3Error};
+
+
+/// Cuts off request and response bodies still streaming once `state.abort`
+/// is cancelled, when requests outlive the grace period of a shutdown.
+///
+/// An upload then fails like it would on a dropped connection and removes
+/// what it staged, a download ends early. Handlers themselves always run to
+/// completion, so a write is never left half recorded. Clients are told to
+/// retry, which lands them on another instance during a rollout.
+pub async fn abort_on_shutdown(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let req = req.map(|body| abortable(body, state.abort.clone()));
+    let response = next.run(req).await;
+
+    if state.abort.is_cancelled() && response.status() == StatusCode::INTERNAL_SERVER_ERROR {
+        return S3Error::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ServiceUnavailable",
+            "The server is shutting down, please retry.",
+        )
+        .into_response();
+    }
+
+    // a body of known size is already complete, there is nothing to cut off
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+
+    response.map(|body| abortable(body, state.abort))
+}
+
+
+fn abortable(body: Body, abort: CancellationToken) -> Body {
+    let chunks = stream::unfold(Some((body.into_data_stream(), abort)), |state| async move {
+        let (mut chunks, abort) = state?;
+
+        tokio::select! {
+            chunk = chunks.next() => chunk.map(|chunk| (chunk, Some((chunks, abort)))),
+            _ = abort.cancelled() => {
+                Some((Err(axum::Error::new(io::Error::other("server is shutting down"))), None))
+            }
+        }
+    });
+
+    Body::from_stream(chunks)
+}
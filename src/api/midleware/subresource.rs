@@ -0,0 +1,25 @@
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use percent_encoding::percent_decode_str;
+
+use crate::{api::error::S3Error, auth};
+
+
+/// Refuses with `NotImplemented` requests to a sub-resource of a bucket or
+/// object the method doesn't serve, like `PUT /b/k?tagging` or `DELETE /b?acl`,
+/// which the handlers would otherwise serve as the plain operation and
+/// overwrite or delete with.
+pub async fn reject_unsupported_subresources(req: Request, next: Next) -> Response {
+    let path = percent_decode_str(req.uri().path()).decode_utf8_lossy();
+    let object = path.trim_start_matches('/').split_once('/').is_some_and(|(_, key)| !key.is_empty());
+
+    if let Some(subresource) = auth::unsupported_subresource(req.method(), req.uri().query().unwrap_or(""), object) {
+        tracing::debug!("refused {} {}?{}", req.method(), req.uri().path(), subresource);
+        return S3Error::not_implemented().into_response();
+    }
+
+    next.run(req).await
+}
@@ -1,6 +1,12 @@
 
 mod routes;
+mod client;
 mod handlers;
+mod midleware;
+mod error;
+mod state;
+mod types;
 
 
 pub use routes::create_router;
+pub use state::AppState;
@@ -1,7 +1,68 @@
-use axum::Router;
+use axum::{
+    Router,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
+};
 
-use super::handlers;
+use super::{AppState, handlers, midleware};
+
+pub fn create_router(state: AppState)-> Router {
+    let s3 = Router::new()
+        .route("/", get(handlers::list_buckets))
+        .route("/.admin/presign", get(handlers::presign))
+        .route("/.admin/stats", get(handlers::stats))
+        .route("/.admin/stats/{bucket}", get(handlers::bucket_stats))
+        .route("/.admin/quotas/buckets/{bucket}", put(handlers::put_bucket_quota))
+        .route("/.admin/quotas/owners/{owner}", get(handlers::get_owner_quota).put(handlers::put_owner_quota))
+        .route("/.admin/users", get(handlers::list_users))
+        .route(
+            "/.admin/users/{user}",
+            get(handlers::get_user).put(handlers::put_user).delete(handlers::delete_user),
+        )
+        .route("/.admin/users/{user}/access-keys", post(handlers::create_access_key))
+        .route(
+            "/.admin/users/{user}/policies/{policy}",
+            get(handlers::get_user_policy).put(handlers::put_user_policy).delete(handlers::delete_user_policy),
+        )
+        .route("/.admin/access-keys/{access_key_id}", delete(handlers::delete_access_key))
+        .route("/.admin/groups", get(handlers::list_groups))
+        .route(
+            "/.admin/groups/{group}",
+            get(handlers::get_group).put(handlers::put_group).delete(handlers::delete_group),
+        )
+        .route(
+            "/.admin/groups/{group}/members/{user}",
+            put(handlers::put_group_member).delete(handlers::delete_group_member),
+        )
+        .route(
+            "/.admin/groups/{group}/policies/{policy}",
+            get(handlers::get_group_policy).put(handlers::put_group_policy).delete(handlers::delete_group_policy),
+        )
+        .route(
+            "/{bucket}",
+            get(handlers::get_bucket)
+                .put(handlers::put_bucket)
+                .head(handlers::head_bucket)
+                .delete(handlers::delete_bucket)
+                .layer(middleware::from_fn(midleware::reject_unsupported_subresources)),
+        )
+        .route(
+            "/{bucket}/{*key}",
+            get(handlers::get_object)
+                .put(handlers::put_object)
+                .post(handlers::post_object)
+                .head(handlers::head_object)
+                .delete(handlers::delete_object)
+                .layer(middleware::from_fn(midleware::reject_unsupported_subresources)),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), midleware::authenticate));
 
-pub fn create_router()-> Router {
     Router::new()
+        .route("/.health", get(handlers::health_check))
+        .merge(s3)
+        .layer(DefaultBodyLimit::max(state.limits.max_request_body_size))
+        .layer(middleware::from_fn_with_state(state.clone(), midleware::abort_on_shutdown))
+        .layer(middleware::from_fn(midleware::request_context))
+        .with_state(state)
 }
@@ -0,0 +1,24 @@
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    auth::{CredentialStore, IpNetwork},
+    config::Limits,
+    crypto::MasterKey,
+    storage::Storage,
+};
+
+
+/// Shared state handed to every handler
+#[derive(Clone)]
+pub struct AppState {
+    pub storage: Storage,
+    /// Root credentials from the config, users' access keys are in the database
+    pub credentials: CredentialStore,
+    /// Seals the secrets of users' access keys, they can't have any without it
+    pub master_key: Option<MasterKey>,
+    pub limits: Limits,
+    /// Reverse proxies whose forwarding headers are believed, see `client.rs`
+    pub trusted_proxies: Vec<IpNetwork>,
+    /// Cancelled when requests still running at shutdown are to be cut off
+    pub abort: CancellationToken,
+}
@@ -0,0 +1,542 @@
+use axum::{
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize, de::IgnoredAny};
+
+pub const S3_XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// Single owner reported in listings until we have real principals
+pub const OWNER_ID: &str = crate::storage::DEFAULT_OWNER;
+pub const OWNER_DISPLAY_NAME: &str = "filia";
+
+/// We only have the one storage class
+pub const STORAGE_CLASS: &str = "STANDARD";
+
+
+/// S3 style XML response body, the XML counterpart of `axum::Json`
+pub struct Xml<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Xml<T> {
+    fn into_response(self) -> Response {
+        match quick_xml::se::to_string(&self.0) {
+            Ok(body) => (
+                [(header::CONTENT_TYPE, "application/xml")],
+                format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{body}"),
+            ).into_response(),
+            Err(e) => {
+                tracing::error!("failed to serialize XML response: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+
+/// Timestamps in S3 bodies are ISO 8601 with milliseconds
+pub fn s3_timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Owner {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(default)]
+    pub display_name: String,
+}
+
+impl Default for Owner {
+    fn default() -> Self {
+        Self {
+            id: OWNER_ID.to_string(),
+            display_name: OWNER_DISPLAY_NAME.to_string(),
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListAllMyBucketsResult", rename_all = "PascalCase")]
+pub struct ListAllMyBucketsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub owner: Owner,
+    pub buckets: Buckets,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Buckets {
+    #[serde(rename = "Bucket")]
+    pub bucket: Vec<BucketEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BucketEntry {
+    pub name: String,
+    pub creation_date: String,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "Error", rename_all = "PascalCase")]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    pub resource: String,
+    pub request_id: String,
+}
+
+
+/// Sub-resource query parameters S3 multiplexes onto object paths
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectQuery {
+    pub uploads: Option<String>,
+    pub upload_id: Option<String>,
+    pub part_number: Option<String>,
+    #[serde(rename = "max-parts")]
+    pub max_parts: Option<String>,
+    #[serde(rename = "part-number-marker")]
+    pub part_number_marker: Option<String>,
+    pub version_id: Option<String>,
+    pub acl: Option<String>,
+}
+
+
+/// Sub-resource query parameters S3 multiplexes onto bucket paths
+#[derive(Debug, Default, Deserialize)]
+pub struct BucketQuery {
+    pub uploads: Option<String>,
+    pub prefix: Option<String>,
+    #[serde(rename = "key-marker")]
+    pub key_marker: Option<String>,
+    #[serde(rename = "upload-id-marker")]
+    pub upload_id_marker: Option<String>,
+    #[serde(rename = "max-uploads")]
+    pub max_uploads: Option<String>,
+    #[serde(rename = "list-type")]
+    pub list_type: Option<String>,
+    pub delimiter: Option<String>,
+    #[serde(rename = "max-keys")]
+    pub max_keys: Option<String>,
+    #[serde(rename = "continuation-token")]
+    pub continuation_token: Option<String>,
+    #[serde(rename = "start-after")]
+    pub start_after: Option<String>,
+    #[serde(rename = "fetch-owner")]
+    pub fetch_owner: Option<String>,
+    #[serde(rename = "encoding-type")]
+    pub encoding_type: Option<String>,
+    pub versioning: Option<String>,
+    pub versions: Option<String>,
+    pub policy: Option<String>,
+    pub acl: Option<String>,
+    pub encryption: Option<String>,
+    #[serde(rename = "version-id-marker")]
+    pub version_id_marker: Option<String>,
+    pub lifecycle: Option<String>,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListBucketResult", rename_all = "PascalCase")]
+pub struct ListBucketResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    pub max_keys: usize,
+    pub key_count: usize,
+    pub is_truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_continuation_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_after: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<&'static str>,
+    pub contents: Vec<ObjectEntry>,
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObjectEntry {
+    pub key: String,
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    pub size: u64,
+    pub storage_class: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CommonPrefix {
+    pub prefix: String,
+}
+
+
+/// Body of PutBucketVersioning
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VersioningConfiguration {
+    pub status: Option<String>,
+}
+
+/// Body of the GetBucketVersioning response, without a status for buckets
+/// that never had versioning configured
+#[derive(Debug, Serialize)]
+#[serde(rename = "VersioningConfiguration", rename_all = "PascalCase")]
+pub struct VersioningConfigurationResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<&'static str>,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListVersionsResult", rename_all = "PascalCase")]
+pub struct ListVersionsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub name: String,
+    pub prefix: String,
+    pub key_marker: String,
+    pub version_id_marker: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_key_marker: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_version_id_marker: Option<String>,
+    pub max_keys: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    pub is_truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_type: Option<&'static str>,
+    /// Versions and delete markers interleaved in listing order
+    #[serde(rename = "$value")]
+    pub entries: Vec<VersionEntry>,
+    pub common_prefixes: Vec<CommonPrefix>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum VersionEntry {
+    Version(ObjectVersionEntry),
+    DeleteMarker(DeleteMarkerEntry),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ObjectVersionEntry {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    pub size: u64,
+    pub storage_class: &'static str,
+    pub owner: Owner,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeleteMarkerEntry {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub last_modified: String,
+    pub owner: Owner,
+}
+
+
+/// Body of PutBucketLifecycleConfiguration and of the
+/// GetBucketLifecycleConfiguration response. Parts we don't support are
+/// only read to reject them.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "LifecycleConfiguration", rename_all = "PascalCase")]
+pub struct BucketLifecycleConfiguration {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "Rule", default)]
+    pub rules: Vec<LifecycleRuleEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleRuleEntry {
+    #[serde(rename = "ID", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<LifecycleFilter>,
+    /// Rules from before `Filter` existed only have a prefix
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<LifecycleExpiration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noncurrent_version_expiration: Option<NoncurrentVersionExpiration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abort_incomplete_multipart_upload: Option<AbortIncompleteMultipartUpload>,
+    #[serde(rename = "Transition", default, skip_serializing)]
+    pub transitions: Vec<IgnoredAny>,
+    #[serde(rename = "NoncurrentVersionTransition", default, skip_serializing)]
+    pub noncurrent_version_transitions: Vec<IgnoredAny>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<TagEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub and: Option<LifecycleAnd>,
+    #[serde(skip_serializing)]
+    pub object_size_greater_than: Option<IgnoredAny>,
+    #[serde(skip_serializing)]
+    pub object_size_less_than: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleAnd {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(rename = "Tag", default)]
+    pub tags: Vec<TagEntry>,
+    #[serde(skip_serializing)]
+    pub object_size_greater_than: Option<IgnoredAny>,
+    #[serde(skip_serializing)]
+    pub object_size_less_than: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TagEntry {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LifecycleExpiration {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<u32>,
+    #[serde(skip_serializing)]
+    pub date: Option<IgnoredAny>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired_object_delete_marker: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct NoncurrentVersionExpiration {
+    pub noncurrent_days: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub newer_noncurrent_versions: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AbortIncompleteMultipartUpload {
+    pub days_after_initiation: u32,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "InitiateMultipartUploadResult", rename_all = "PascalCase")]
+pub struct InitiateMultipartUploadResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub bucket: String,
+    pub key: String,
+    pub upload_id: String,
+}
+
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CompleteMultipartUpload {
+    #[serde(rename = "Part", default)]
+    pub parts: Vec<CompletedPartEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CompletedPartEntry {
+    pub part_number: u32,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "CompleteMultipartUploadResult", rename_all = "PascalCase")]
+pub struct CompleteMultipartUploadResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub location: String,
+    pub bucket: String,
+    pub key: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "CopyObjectResult", rename_all = "PascalCase")]
+pub struct CopyObjectResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    pub last_modified: String,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListPartsResult", rename_all = "PascalCase")]
+pub struct ListPartsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub bucket: String,
+    pub key: String,
+    pub upload_id: String,
+    pub initiator: Owner,
+    pub owner: Owner,
+    pub storage_class: &'static str,
+    pub part_number_marker: u32,
+    pub next_part_number_marker: u32,
+    pub max_parts: usize,
+    pub is_truncated: bool,
+    #[serde(rename = "Part")]
+    pub parts: Vec<PartEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PartEntry {
+    pub part_number: u32,
+    pub last_modified: String,
+    #[serde(rename = "ETag")]
+    pub etag: String,
+    pub size: u64,
+}
+
+
+#[derive(Debug, Serialize)]
+#[serde(rename = "ListMultipartUploadsResult", rename_all = "PascalCase")]
+pub struct ListMultipartUploadsResult {
+    #[serde(rename = "@xmlns")]
+    pub xmlns: &'static str,
+    pub bucket: String,
+    pub key_marker: String,
+    pub upload_id_marker: String,
+    pub next_key_marker: String,
+    pub next_upload_id_marker: String,
+    pub prefix: String,
+    pub max_uploads: usize,
+    pub is_truncated: bool,
+    #[serde(rename = "Upload")]
+    pub uploads: Vec<UploadEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UploadEntry {
+    pub key: String,
+    pub upload_id: String,
+    pub initiator: Owner,
+    pub owner: Owner,
+    pub storage_class: &'static str,
+    pub initiated: String,
+}
+
+
+/// Body of PutBucketEncryption and of the GetBucketEncryption response
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "ServerSideEncryptionConfiguration", rename_all = "PascalCase")]
+pub struct ServerSideEncryptionConfiguration {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    #[serde(rename = "Rule", default)]
+    pub rules: Vec<ServerSideEncryptionRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ServerSideEncryptionRule {
+    pub apply_server_side_encryption_by_default: Option<ApplyServerSideEncryptionByDefault>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket_key_enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplyServerSideEncryptionByDefault {
+    #[serde(rename = "SSEAlgorithm")]
+    pub sse_algorithm: String,
+    #[serde(rename = "KMSMasterKeyID", default, skip_serializing_if = "Option::is_none")]
+    pub kms_master_key_id: Option<String>,
+}
+
+
+/// Body of PutBucketAcl and PutObjectAcl and of the GetBucketAcl and
+/// GetObjectAcl responses
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "AccessControlPolicy", rename_all = "PascalCase")]
+pub struct AccessControlPolicy {
+    #[serde(rename = "@xmlns", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns: Option<String>,
+    /// Ignored when putting an ACL, owners don't change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    pub access_control_list: AccessControlList,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AccessControlList {
+    #[serde(rename = "Grant", default)]
+    pub grants: Vec<GrantEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GrantEntry {
+    pub grantee: GranteeEntry,
+    pub permission: String,
+}
+
+/// A user by `ID` with `xsi:type="CanonicalUser"`, or a group by `URI` with
+/// `xsi:type="Group"`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GranteeEntry {
+    #[serde(rename = "@xmlns:xsi", default, skip_serializing_if = "Option::is_none")]
+    pub xmlns_xsi: Option<String>,
+    /// quick-xml matches attributes by their local name when reading
+    #[serde(rename(serialize = "@xsi:type", deserialize = "@type"), default)]
+    pub kind: String,
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(rename = "URI", default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_address: Option<String>,
+}
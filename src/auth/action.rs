@@ -0,0 +1,273 @@
+//! Which IAM action an S3 request performs, on which resource.
+//!
+//! See <https://docs.aws.amazon.com/service-authorization/latest/reference/list_amazons3.html>
+
+use std::net::IpAddr;
+
+use axum::http::{Method, Uri};
+use percent_encoding::percent_decode_str;
+
+use super::{
+    Principal,
+    policy::{ARN_PREFIX, AccessRequest},
+    sigv4,
+};
+
+/// The object a CopyObject reads, `bucket/key` URL encoded with an optional `?versionId=`
+pub const COPY_SOURCE_HEADER: &str = "x-amz-copy-source";
+
+/// Query parameters naming a sub-resource of a bucket or object in S3
+const SUBRESOURCES: [&str; 34] = [
+    "accelerate", "acl", "analytics", "attributes", "cors", "delete", "encryption", "intelligent-tiering",
+    "inventory", "legal-hold", "lifecycle", "location", "logging", "metadataTable", "metrics", "notification",
+    "object-lock", "ownershipControls", "policy", "policyStatus", "publicAccessBlock", "renameObject",
+    "replication", "requestPayment", "restore", "retention", "select", "session", "tagging", "torrent",
+    "uploads", "versioning", "versions", "website",
+];
+
+/// The sub-resources served on bucket paths, by method, as `get_bucket`,
+/// `put_bucket` and `delete_bucket` branch on them
+const BUCKET_SUBRESOURCES: [(Method, &str); 15] = [
+    (Method::GET, "acl"),
+    (Method::GET, "encryption"),
+    (Method::GET, "lifecycle"),
+    (Method::GET, "policy"),
+    (Method::GET, "uploads"),
+    (Method::GET, "versioning"),
+    (Method::GET, "versions"),
+    (Method::PUT, "acl"),
+    (Method::PUT, "encryption"),
+    (Method::PUT, "lifecycle"),
+    (Method::PUT, "policy"),
+    (Method::PUT, "versioning"),
+    (Method::DELETE, "encryption"),
+    (Method::DELETE, "lifecycle"),
+    (Method::DELETE, "policy"),
+];
+
+/// The sub-resources served on object paths, by method, as `get_object`,
+/// `put_object` and `post_object` branch on them
+const OBJECT_SUBRESOURCES: [(Method, &str); 3] = [
+    (Method::GET, "acl"),
+    (Method::PUT, "acl"),
+    (Method::POST, "uploads"),
+];
+
+/// Stands in for the action of a request to a sub-resource we don't serve, so
+/// that only policies allowing every action let it through to be refused
+const UNSUPPORTED_ACTION: &str = "s3:UnsupportedOperation";
+
+
+/// The first sub-resource in a raw query that `method` doesn't serve on a
+/// bucket path, or on an object path with `object`. Requests naming one are
+/// refused rather than served as the plain bucket or object operation.
+///
+/// HEAD is HeadBucket or HeadObject whatever the query says.
+pub fn unsupported_subresource(method: &Method, query: &str, object: bool) -> Option<String> {
+    if *method == Method::HEAD {
+        return None;
+    }
+
+    let served: &[(Method, &str)] = if object { &OBJECT_SUBRESOURCES } else { &BUCKET_SUBRESOURCES };
+
+    sigv4::query_pairs(query)
+        .into_iter()
+        .map(|(k, _)| k)
+        .find(|k| SUBRESOURCES.contains(&k.as_str()) && !served.iter().any(|(m, name)| m == method && name == k))
+}
+
+impl AccessRequest {
+
+    /// A request to the S3 API, its action and resource told apart the same
+    /// way the handlers route operations
+    pub fn from_http(
+        method: &Method,
+        uri: &Uri,
+        principal: Option<Principal>,
+        source_ip: Option<IpAddr>,
+        secure_transport: bool,
+    ) -> Self {
+        let (action, resource, bucket) = action_of(method, uri);
+
+        let key = bucket
+            .as_ref()
+            .and_then(|bucket| resource.strip_prefix(&format!("{ARN_PREFIX}{bucket}/")))
+            .map(str::to_string);
+
+        let version_id = sigv4::query_pairs(uri.query().unwrap_or(""))
+            .into_iter()
+            .find_map(|(k, v)| (k == "versionId").then_some(v));
+
+        Self { action, resource, bucket, key, version_id, principal, source_ip, secure_transport }
+    }
+
+
+    /// The read of its source a CopyObject does, from `x-amz-copy-source`.
+    /// `None` if that doesn't make a path.
+    pub fn copy_source(
+        source: &str,
+        principal: Option<Principal>,
+        source_ip: Option<IpAddr>,
+        secure_transport: bool,
+    ) -> Option<Self> {
+        let uri: Uri = format!("/{}", source.trim_start_matches('/')).parse().ok()?;
+
+        Some(Self::from_http(&Method::GET, &uri, principal, source_ip, secure_transport))
+    }
+}
+
+
+/// The IAM action of a request, the ARN of its resource and the bucket it is about
+fn action_of(method: &Method, uri: &Uri) -> (&'static str, String, Option<String>) {
+    let path = percent_decode_str(uri.path()).decode_utf8_lossy();
+    let query = sigv4::query_pairs(uri.query().unwrap_or(""));
+    let has = |name: &str| query.iter().any(|(k, _)| k == name);
+
+    let path = path.trim_start_matches('/');
+
+    if path.is_empty() {
+        return ("s3:ListAllMyBuckets", format!("{ARN_PREFIX}*"), None);
+    }
+
+    let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+
+    if unsupported_subresource(method, uri.query().unwrap_or(""), !key.is_empty()).is_some() {
+        let resource = match key {
+            "" => format!("{ARN_PREFIX}{bucket}"),
+            key => format!("{ARN_PREFIX}{bucket}/{key}"),
+        };
+
+        return (UNSUPPORTED_ACTION, resource, Some(bucket.to_string()));
+    }
+
+    if key.is_empty() {
+        let action = match *method {
+            Method::HEAD => "s3:ListBucket",
+            Method::GET if has("uploads") => "s3:ListBucketMultipartUploads",
+            Method::GET if has("versioning") => "s3:GetBucketVersioning",
+            Method::GET if has("versions") => "s3:ListBucketVersions",
+            Method::GET if has("lifecycle") => "s3:GetLifecycleConfiguration",
+            Method::GET if has("policy") => "s3:GetBucketPolicy",
+            Method::GET if has("acl") => "s3:GetBucketAcl",
+            Method::GET if has("encryption") => "s3:GetEncryptionConfiguration",
+            Method::GET => "s3:ListBucket",
+            Method::PUT if has("versioning") => "s3:PutBucketVersioning",
+            Method::PUT | Method::DELETE if has("lifecycle") => "s3:PutLifecycleConfiguration",
+            Method::PUT if has("policy") => "s3:PutBucketPolicy",
+            Method::DELETE if has("policy") => "s3:DeleteBucketPolicy",
+            Method::PUT if has("acl") => "s3:PutBucketAcl",
+            Method::PUT | Method::DELETE if has("encryption") => "s3:PutEncryptionConfiguration",
+            Method::DELETE => "s3:DeleteBucket",
+            _ => "s3:CreateBucket",
+        };
+
+        return (action, format!("{ARN_PREFIX}{bucket}"), Some(bucket.to_string()));
+    }
+
+    let action = match *method {
+        Method::GET if has("acl") && has("versionId") => "s3:GetObjectVersionAcl",
+        Method::GET if has("acl") => "s3:GetObjectAcl",
+        Method::GET if has("uploadId") => "s3:ListMultipartUploadParts",
+        Method::GET | Method::HEAD if has("versionId") => "s3:GetObjectVersion",
+        Method::GET | Method::HEAD => "s3:GetObject",
+        Method::DELETE if has("uploadId") => "s3:AbortMultipartUpload",
+        Method::DELETE if has("versionId") => "s3:DeleteObjectVersion",
+        Method::DELETE => "s3:DeleteObject",
+        Method::PUT if has("acl") && has("versionId") => "s3:PutObjectVersionAcl",
+        Method::PUT if has("acl") => "s3:PutObjectAcl",
+        // PutObject covers UploadPart and creating and completing multipart uploads
+        _ => "s3:PutObject",
+    };
+
+    (action, format!("{ARN_PREFIX}{bucket}/{key}"), Some(bucket.to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(method: Method, uri: &str) -> &'static str {
+        action_of(&method, &uri.parse().unwrap()).0
+    }
+
+    #[test]
+    fn finds_unsupported_subresources() {
+        let unsupported = |method: Method, query: &str, object: bool| unsupported_subresource(&method, query, object);
+
+        assert_eq!(unsupported(Method::PUT, "tagging", true).as_deref(), Some("tagging"));
+        assert_eq!(unsupported(Method::PUT, "x-id=PutObject&retention=", true).as_deref(), Some("retention"));
+        assert_eq!(unsupported(Method::GET, "cors", false).as_deref(), Some("cors"));
+
+        // served ones and plain parameters
+        assert_eq!(unsupported(Method::PUT, "", true), None);
+        assert_eq!(unsupported(Method::GET, "acl&versionId=v1", true), None);
+        assert_eq!(unsupported(Method::PUT, "uploadId=u&partNumber=1", true), None);
+        assert_eq!(unsupported(Method::POST, "uploads", true), None);
+        assert_eq!(unsupported(Method::GET, "list-type=2&prefix=tagging", false), None);
+        assert_eq!(unsupported(Method::PUT, "versioning", false), None);
+        assert_eq!(unsupported(Method::DELETE, "policy", false), None);
+        assert_eq!(unsupported(Method::HEAD, "tagging", true), None);
+
+        // bucket sub-resources aren't served on objects
+        assert_eq!(unsupported(Method::GET, "versioning", true).as_deref(), Some("versioning"));
+
+        // nor sub-resources by methods that don't serve them
+        for (method, query, object) in [
+            (Method::DELETE, "acl", false),
+            (Method::DELETE, "versioning", false),
+            (Method::DELETE, "uploads", false),
+            (Method::DELETE, "versions", false),
+            (Method::PUT, "uploads", false),
+            (Method::PUT, "versions", false),
+            (Method::DELETE, "acl", true),
+            (Method::PUT, "uploads", true),
+            (Method::GET, "uploads", true),
+        ] {
+            assert_eq!(unsupported(method.clone(), query, object).as_deref(), Some(query), "{method} {query}");
+        }
+    }
+
+    #[test]
+    fn maps_requests_to_actions() {
+        for (method, uri, expected) in [
+            (Method::GET, "/", "s3:ListAllMyBuckets"),
+            (Method::GET, "/b?list-type=2", "s3:ListBucket"),
+            (Method::PUT, "/b", "s3:CreateBucket"),
+            (Method::DELETE, "/b", "s3:DeleteBucket"),
+            (Method::DELETE, "/b?policy", "s3:DeleteBucketPolicy"),
+            (Method::PUT, "/b/k", "s3:PutObject"),
+            (Method::PUT, "/b/k?uploadId=u&partNumber=1", "s3:PutObject"),
+            (Method::DELETE, "/b/k", "s3:DeleteObject"),
+            (Method::DELETE, "/b/k?versionId=v", "s3:DeleteObjectVersion"),
+            (Method::HEAD, "/b/k?tagging", "s3:GetObject"),
+            (Method::POST, "/b/k?uploads", "s3:PutObject"),
+            (Method::DELETE, "/b?acl", UNSUPPORTED_ACTION),
+            (Method::PUT, "/b?uploads", UNSUPPORTED_ACTION),
+            (Method::DELETE, "/b/k?acl", UNSUPPORTED_ACTION),
+            (Method::PUT, "/b/k?uploads", UNSUPPORTED_ACTION),
+            (Method::GET, "/b/k?uploads", UNSUPPORTED_ACTION),
+        ] {
+            assert_eq!(action(method.clone(), uri), expected, "{method} {uri}");
+        }
+    }
+
+    #[test]
+    fn unsupported_subresources_dont_map_to_plain_operations() {
+        for (method, uri) in [
+            (Method::PUT, "/b/k?tagging"),
+            (Method::DELETE, "/b/k?tagging"),
+            (Method::PUT, "/b/k?retention"),
+            (Method::DELETE, "/b?tagging"),
+            (Method::DELETE, "/b?cors"),
+            (Method::PUT, "/b?website"),
+            (Method::GET, "/b?replication"),
+        ] {
+            assert_eq!(action(method.clone(), uri), UNSUPPORTED_ACTION, "{method} {uri}");
+        }
+
+        let (_, resource, bucket) = action_of(&Method::DELETE, &"/b/k?tagging".parse().unwrap());
+        assert_eq!(resource, format!("{ARN_PREFIX}b/k"));
+        assert_eq!(bucket.as_deref(), Some("b"));
+    }
+}
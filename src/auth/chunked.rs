@@ -0,0 +1,454 @@
+//! `aws-chunked` upload bodies. The data comes cut into chunks, each behind a
+//! line with its size in hex and, when signed, the chunk's signature:
+//!
+//! ```text
+//! 10000;chunk-signature=ad80c730..\r\n<65536 bytes>\r\n
+//! 400;chunk-signature=0055627c..\r\n<1024 bytes>\r\n
+//! 0;chunk-signature=b6c6ea8a..\r\n
+//! x-amz-checksum-crc32c:sOO8/Q==\r\n
+//! x-amz-trailer-signature:d81f82fc..\r\n
+//! \r\n
+//! ```
+//!
+//! The empty final chunk may be followed by trailing headers, a checksum of
+//! the data and, with signed chunks, their signature.
+//!
+//! See <https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-streaming.html>
+
+use std::io;
+
+use bytes::Bytes;
+use futures_util::stream;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio_util::io::StreamReader;
+
+use crate::{
+    error::ChunkError,
+    storage::{ChecksumAlgorithm, Hasher},
+};
+
+use super::sigv4::ChunkSigner;
+
+/// Longest chunk header or trailing header line accepted
+const MAX_LINE_LENGTH: u64 = 4096;
+
+/// Most bytes passed on at once
+const READ_SIZE: usize = 64 * 1024;
+
+const TRAILER_SIGNATURE: &str = "x-amz-trailer-signature";
+
+
+/// How the chunks of a body are framed and checked
+pub struct Framing {
+    /// Whether chunk headers carry a `chunk-signature`
+    pub signed: bool,
+    /// Checks those signatures, without one they are skipped over
+    pub signer: Option<ChunkSigner>,
+    /// `x-amz-decoded-content-length`, what the chunks hold together
+    pub decoded_length: u64,
+    /// Checksum header expected after the final chunk, from `x-amz-trailer`
+    pub trailer: Option<(String, ChecksumAlgorithm)>,
+}
+
+
+/// The data of an `aws-chunked` `body`. A chunk whose signature doesn't match,
+/// a body holding other than the declared length and a trailing checksum not
+/// matching the data fail the read with a `ChunkError`.
+pub fn decode_chunked<R>(body: R, framing: Framing) -> impl AsyncRead + Send + Unpin + 'static
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let decoder = Decoder {
+        body: BufReader::new(body),
+        previous: framing.signer.as_ref().map(|s| s.seed().to_string()).unwrap_or_default(),
+        remaining: framing.decoded_length,
+        hasher: framing.trailer.as_ref().map(|(_, algorithm)| Hasher::new(*algorithm)),
+        chunk: None,
+        done: false,
+        framing,
+    };
+
+    StreamReader::new(Box::pin(stream::try_unfold(decoder, |mut decoder| async move {
+        Ok::<_, io::Error>(decoder.next().await?.map(|data| (data, decoder)))
+    })))
+}
+
+
+struct Decoder<R> {
+    body: BufReader<R>,
+    framing: Framing,
+    /// Signature of the chunk before, the seed for the first one
+    previous: String,
+    /// Of the declared length
+    remaining: u64,
+    /// Hashing the data for the trailing checksum
+    hasher: Option<Hasher>,
+    chunk: Option<Chunk>,
+    done: bool,
+}
+
+/// The chunk being read
+struct Chunk {
+    left: u64,
+    signature: Option<String>,
+    sha256: Sha256,
+}
+
+
+impl<R: AsyncRead + Unpin> Decoder<R> {
+
+    /// The next piece of data, `None` once the final chunk and the trailer are read
+    async fn next(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+
+            let Some(chunk) = &mut self.chunk else {
+                self.start_chunk().await?;
+                continue;
+            };
+
+            if chunk.left == 0 {
+                self.end_chunk()?;
+
+                if self.read_line().await?.is_none_or(|line| !line.is_empty()) {
+                    return Err(malformed("chunk data not ended by CRLF"));
+                }
+
+                continue;
+            }
+
+            let available = self.body.fill_buf().await?;
+
+            if available.is_empty() {
+                return Err(chunk_error(ChunkError::IncompleteBody));
+            }
+
+            let n = available.len().min(READ_SIZE).min(chunk.left as usize);
+
+            if n as u64 > self.remaining {
+                return Err(chunk_error(ChunkError::IncompleteBody));
+            }
+
+            let data = Bytes::copy_from_slice(&available[..n]);
+            self.body.consume(n);
+
+            chunk.left -= n as u64;
+            chunk.sha256.update(&data);
+            self.remaining -= n as u64;
+
+            if let Some(hasher) = &mut self.hasher {
+                hasher.update(&data);
+            }
+
+            return Ok(Some(data));
+        }
+    }
+
+
+    /// Read a chunk header, for the final chunk the trailer behind it as well
+    async fn start_chunk(&mut self) -> io::Result<()> {
+        let line = self.read_line().await?.ok_or_else(|| chunk_error(ChunkError::IncompleteBody))?;
+
+        let (size, extension) = line.split_once(';').unwrap_or((&line, ""));
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| malformed(format!("invalid chunk size '{size}'")))?;
+
+        let signature = match extension.strip_prefix("chunk-signature=") {
+            Some(signature) if self.framing.signed => Some(signature.to_string()),
+            None if !self.framing.signed => None,
+            _ => return Err(malformed("unexpected chunk extension")),
+        };
+
+        self.chunk = Some(Chunk { left: size, signature, sha256: Sha256::new() });
+
+        // the final chunk is empty and followed by the trailer instead of a CRLF
+        if size == 0 {
+            self.end_chunk()?;
+            self.read_trailer().await?;
+            self.done = true;
+        }
+
+        Ok(())
+    }
+
+
+    /// Check the signature of the chunk just read, it is what the next one chains on
+    fn end_chunk(&mut self) -> io::Result<()> {
+        let chunk = self.chunk.take().expect("a chunk is being read");
+
+        if let (Some(signer), Some(signature)) = (&self.framing.signer, chunk.signature) {
+            if !signer.verify_chunk(&self.previous, &chunk.sha256.finalize(), &signature) {
+                return Err(chunk_error(ChunkError::SignatureMismatch));
+            }
+
+            self.previous = signature;
+        }
+
+        Ok(())
+    }
+
+
+    /// Trailing headers up to the empty line ending the body, checked against
+    /// the data and, for signed chunks, their signature
+    async fn read_trailer(&mut self) -> io::Result<()> {
+        let mut headers = Vec::new();
+
+        loop {
+            let line = self.read_line().await?.ok_or_else(|| chunk_error(ChunkError::IncompleteBody))?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            let (name, value) = line.split_once(':').ok_or_else(|| malformed("invalid trailing header"))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        if self.remaining != 0 {
+            return Err(chunk_error(ChunkError::IncompleteBody));
+        }
+
+        // with signed chunks trailing headers are signed as well
+        if let Some(signer) = &self.framing.signer
+            && !headers.is_empty()
+        {
+            let signature = headers
+                .iter()
+                .find(|(n, _)| n == TRAILER_SIGNATURE)
+                .map(|(_, v)| v.as_str())
+                .ok_or_else(|| malformed("missing trailer signature"))?;
+
+            let trailer: String = headers
+                .iter()
+                .filter(|(n, _)| n != TRAILER_SIGNATURE)
+                .map(|(n, v)| format!("{n}:{v}\n"))
+                .collect();
+
+            if !signer.verify_trailer(&self.previous, &trailer, signature) {
+                return Err(chunk_error(ChunkError::SignatureMismatch));
+            }
+        }
+
+        if let Some((name, _)) = &self.framing.trailer {
+            let value = headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+                .ok_or_else(|| malformed(format!("missing trailing header {name}")))?;
+
+            let actual = self.hasher.take().expect("hashed for the trailer").finish();
+
+            if !base64_matches_hex(value, &actual) {
+                return Err(chunk_error(ChunkError::ChecksumMismatch(name.clone())));
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// One line without its CRLF, `None` at the end of the body
+    async fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        (&mut self.body).take(MAX_LINE_LENGTH).read_until(b'\n', &mut line).await?;
+
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let line = line
+            .strip_suffix(b"\r\n")
+            .ok_or_else(|| malformed("line not ended by CRLF or too long"))?;
+
+        String::from_utf8(line.to_vec()).map(Some).map_err(|_| malformed("non UTF-8 line"))
+    }
+}
+
+
+fn base64_matches_hex(base64: &str, hex_digest: &str) -> bool {
+    use base64::Engine;
+
+    base64::engine::general_purpose::STANDARD
+        .decode(base64)
+        .is_ok_and(|digest| hex::encode(digest) == hex_digest)
+}
+
+
+fn malformed(message: impl Into<String>) -> io::Error {
+    chunk_error(ChunkError::Malformed(message.into()))
+}
+
+
+fn chunk_error(err: ChunkError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the examples of
+    // https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-streaming.html
+    // https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-streaming-trailers.html
+    // which upload 65536 + 1024 times 'a'
+    const SEED: &str = "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9";
+    const SIGNATURES: [&str; 3] = [
+        "ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648",
+        "0055627c9e194cb4542bae2aa5492e3c1575bbb81b612b7d234b86a503ef5497",
+        "b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9",
+    ];
+    const TRAILER_SEED: &str = "106e2a8a18243abcf37539882f36619c00e2dfc72633413f02d3b74544bfeb8e";
+    const TRAILER_SIGNATURES: [&str; 3] = [
+        "b474d8862b1487a5145d686f57f013e54db672cee1c953b3010fb58501ef5aa2",
+        "1c1344b170168f8e65b41376b44b20fe354e373826ccbbe2c1d40a8cae51e5c7",
+        "2ca2aba2005185cf7159c6277faf83795951dd77a3a99e6e65d5c9f85863f992",
+    ];
+    const CRC32C: &str = "sOO8/Q==";
+    const TRAILER_SIGNATURE_VALUE: &str = "d81f82fc3505edab99d459891051a732e8730629a2e4a59689829ca17fe2e435";
+    const DECODED_LENGTH: u64 = 66560;
+
+    /// The example body, with `signatures` on its chunks and `trailer` after them
+    fn body(signatures: Option<[&str; 3]>, trailer: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        for (i, size) in [65536, 1024, 0].into_iter().enumerate() {
+            body.extend(format!("{size:x}").as_bytes());
+
+            if let Some(signatures) = signatures {
+                body.extend(format!(";chunk-signature={}", signatures[i]).as_bytes());
+            }
+
+            body.extend(b"\r\n");
+
+            if size > 0 {
+                body.extend(vec![b'a'; size]);
+                body.extend(b"\r\n");
+            }
+        }
+
+        body.extend(trailer.as_bytes());
+        body.extend(b"\r\n");
+        body
+    }
+
+    fn framing(signed: bool, signer: Option<ChunkSigner>, trailer: bool) -> Framing {
+        Framing {
+            signed,
+            signer,
+            decoded_length: DECODED_LENGTH,
+            trailer: trailer.then(|| ("x-amz-checksum-crc32c".to_string(), ChecksumAlgorithm::Crc32c)),
+        }
+    }
+
+    async fn read(body: Vec<u8>, framing: Framing) -> Result<Vec<u8>, ChunkError> {
+        let mut data = Vec::new();
+
+        match decode_chunked(std::io::Cursor::new(body), framing).read_to_end(&mut data).await {
+            Ok(_) => Ok(data),
+            Err(e) => Err(*e.into_inner().unwrap().downcast::<ChunkError>().unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_signed_chunks() {
+        let signer = ChunkSigner::aws_example(SEED);
+        let data = read(body(Some(SIGNATURES), ""), framing(true, Some(signer), false)).await.unwrap();
+
+        assert_eq!(data, vec![b'a'; DECODED_LENGTH as usize]);
+
+        // without a signer, as with authentication off, the framing is only stripped
+        let data = read(body(Some(SIGNATURES), ""), framing(true, None, false)).await.unwrap();
+        assert_eq!(data.len() as u64, DECODED_LENGTH);
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_not_matching_their_signature() {
+        let signer = ChunkSigner::aws_example(SEED);
+
+        let mut tampered = body(Some(SIGNATURES), "");
+        tampered[100] = b'b';
+        assert!(matches!(
+            read(tampered, framing(true, Some(signer.clone()), false)).await,
+            Err(ChunkError::SignatureMismatch)
+        ));
+
+        // chunks can't be left out or reordered, the signatures chain
+        let swapped = [SIGNATURES[1], SIGNATURES[0], SIGNATURES[2]];
+        assert!(matches!(
+            read(body(Some(swapped), ""), framing(true, Some(signer.clone()), false)).await,
+            Err(ChunkError::SignatureMismatch)
+        ));
+
+        // nor can the signatures be dropped
+        assert!(matches!(
+            read(body(None, ""), framing(true, Some(signer), false)).await,
+            Err(ChunkError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_not_holding_the_declared_length() {
+        let signer = ChunkSigner::aws_example(SEED);
+
+        let mut truncated = body(Some(SIGNATURES), "");
+        truncated.truncate(30_000);
+        assert!(matches!(
+            read(truncated, framing(true, Some(signer.clone()), false)).await,
+            Err(ChunkError::IncompleteBody)
+        ));
+
+        for decoded_length in [DECODED_LENGTH - 1, DECODED_LENGTH + 1] {
+            let framing = Framing { decoded_length, ..framing(true, Some(signer.clone()), false) };
+            assert!(matches!(
+                read(body(Some(SIGNATURES), ""), framing).await,
+                Err(ChunkError::IncompleteBody)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_signed_trailer() {
+        let signer = ChunkSigner::aws_example(TRAILER_SEED);
+        let trailer = format!("x-amz-checksum-crc32c:{CRC32C}\r\nx-amz-trailer-signature:{TRAILER_SIGNATURE_VALUE}\r\n");
+
+        let data = read(body(Some(TRAILER_SIGNATURES), &trailer), framing(true, Some(signer.clone()), true))
+            .await
+            .unwrap();
+        assert_eq!(data.len() as u64, DECODED_LENGTH);
+
+        // the checksum is signed
+        let forged = trailer.replace(CRC32C, "AAAAAA==");
+        assert!(matches!(
+            read(body(Some(TRAILER_SIGNATURES), &forged), framing(true, Some(signer.clone()), true)).await,
+            Err(ChunkError::SignatureMismatch)
+        ));
+
+        let unsigned = format!("x-amz-checksum-crc32c:{CRC32C}\r\n");
+        assert!(matches!(
+            read(body(Some(TRAILER_SIGNATURES), &unsigned), framing(true, Some(signer), true)).await,
+            Err(ChunkError::Malformed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn checks_unsigned_trailer() {
+        let trailer = format!("x-amz-checksum-crc32c:{CRC32C}\r\n");
+        let data = read(body(None, &trailer), framing(false, None, true)).await.unwrap();
+        assert_eq!(data, vec![b'a'; DECODED_LENGTH as usize]);
+
+        let wrong = "x-amz-checksum-crc32c:AAAAAA==\r\n";
+        assert!(matches!(
+            read(body(None, wrong), framing(false, None, true)).await,
+            Err(ChunkError::ChecksumMismatch(name)) if name == "x-amz-checksum-crc32c"
+        ));
+
+        assert!(matches!(
+            read(body(None, ""), framing(false, None, true)).await,
+            Err(ChunkError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! The `Condition` block of policy statements. Only the keys we can know
+//! about a request are supported: `aws:SourceIp` with `IpAddress` and
+//! `NotIpAddress`, and `aws:SecureTransport` with `Bool`.
+//!
+//! See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_elements_condition_operators.html>
+
+use std::{collections::BTreeMap, net::IpAddr, str::FromStr};
+
+use serde::{Deserialize, Deserializer, de::Error};
+use serde_json::Value;
+
+use super::policy::AccessRequest;
+
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// The client address is in one of the networks, or with `negated` in none of them
+    SourceIp { networks: Vec<IpNetwork>, negated: bool },
+    /// Whether the request came over HTTPS
+    SecureTransport(bool),
+}
+
+
+/// An address range in CIDR notation, a single address without a prefix length
+#[derive(Debug, Clone, Copy)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+
+impl Condition {
+
+    pub fn matches(&self, request: &AccessRequest) -> bool {
+        match self {
+            Self::SourceIp { networks, negated } => {
+                let inside = request
+                    .source_ip
+                    .is_some_and(|ip| networks.iter().any(|network| network.contains(ip)));
+
+                inside != *negated
+            }
+            Self::SecureTransport(secure) => request.secure_transport == *secure,
+        }
+    }
+}
+
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // the prefix of an IPv4-mapped network counts the 96 bits of the mapping
+        let (addr, prefix) = match self.addr {
+            IpAddr::V6(v6) if v6.to_ipv4_mapped().is_some() && self.prefix >= 96 => (self.addr.to_canonical(), self.prefix - 96),
+            addr => (addr, self.prefix),
+        };
+
+        match (addr, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid IP address or CIDR block {s}");
+
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(addr, prefix)| (addr, Some(prefix)));
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|p| *p <= max_prefix).ok_or_else(invalid)?,
+            None => max_prefix,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+
+impl<'de> Deserialize<'de> for IpNetwork {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+
+/// Read a `Condition` block, `{"<operator>": {"<key>": value or [values]}}`,
+/// failing on operators and keys that are not supported
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Condition>, D::Error> {
+    let block = BTreeMap::<String, BTreeMap<String, Value>>::deserialize(deserializer)?;
+    let mut conditions = Vec::new();
+
+    for (operator, keys) in block {
+        for (key, values) in keys {
+            let values = match values {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+
+            let values: Vec<String> = values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(s) => Ok(s),
+                    Value::Bool(b) => Ok(b.to_string()),
+                    other => Err(D::Error::custom(format!("invalid value {other} for condition key {key}"))),
+                })
+                .collect::<Result<_, _>>()?;
+
+            let condition = match (operator.as_str(), key.to_ascii_lowercase().as_str()) {
+                ("IpAddress" | "NotIpAddress", "aws:sourceip") => Condition::SourceIp {
+                    networks: values.iter().map(|v| v.parse()).collect::<Result<_, _>>().map_err(D::Error::custom)?,
+                    negated: operator == "NotIpAddress",
+                },
+                ("Bool", "aws:securetransport") => match values.as_slice() {
+                    [value] if value.eq_ignore_ascii_case("true") => Condition::SecureTransport(true),
+                    [value] if value.eq_ignore_ascii_case("false") => Condition::SecureTransport(false),
+                    _ => return Err(D::Error::custom("aws:SecureTransport must be true or false")),
+                },
+                ("IpAddress" | "NotIpAddress" | "Bool", _) => {
+                    return Err(D::Error::custom(format!(
+                        "unsupported condition key {key}, use aws:SourceIp or aws:SecureTransport"
+                    )));
+                }
+                _ => {
+                    return Err(D::Error::custom(format!(
+                        "unsupported condition operator {operator}, use IpAddress, NotIpAddress or Bool"
+                    )));
+                }
+            };
+
+            conditions.push(condition);
+        }
+    }
+
+    Ok(conditions)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_cidr_blocks() {
+        for (s, addr, prefix) in [
+            ("10.0.0.0/8", "10.0.0.0", 8),
+            ("192.0.2.7", "192.0.2.7", 32),
+            ("0.0.0.0/0", "0.0.0.0", 0),
+            ("2001:db8::/32", "2001:db8::", 32),
+            ("::1", "::1", 128),
+        ] {
+            let network = network(s);
+            assert_eq!((network.addr, network.prefix), (ip(addr), prefix), "{s}");
+        }
+
+        for s in ["", "10.0.0.0/33", "2001:db8::/129", "10.0.0.0/", "10.0.0.0/x", "10.0.0/8", "example.com", "10.0.0.0/8/8"] {
+            assert!(s.parse::<IpNetwork>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn contains_addresses() {
+        let v4 = network("192.0.2.0/24");
+        assert!(v4.contains(ip("192.0.2.0")));
+        assert!(v4.contains(ip("192.0.2.255")));
+        assert!(!v4.contains(ip("192.0.3.0")));
+
+        // a v4 client seen through a dual stack socket
+        assert!(v4.contains(ip("::ffff:192.0.2.9")));
+        assert!(!v4.contains(ip("2001:db8::1")));
+
+        // host bits of the network address don't matter
+        assert!(network("10.1.2.3/8").contains(ip("10.200.0.1")));
+
+        assert!(network("0.0.0.0/0").contains(ip("203.0.113.1")));
+        assert!(network("192.0.2.7").contains(ip("192.0.2.7")));
+        assert!(!network("192.0.2.7").contains(ip("192.0.2.8")));
+
+        let v6 = network("2001:db8::/32");
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+        assert!(!v6.contains(ip("192.0.2.1")));
+
+        // a v4 network written in its IPv4-mapped form
+        let mapped = network("::ffff:10.0.0.0/104");
+        assert!(mapped.contains(ip("10.1.2.3")));
+        assert!(mapped.contains(ip("::ffff:10.200.0.1")));
+        assert!(!mapped.contains(ip("11.0.0.1")));
+        assert!(network("::ffff:192.0.2.7").contains(ip("192.0.2.7")));
+        assert!(network("::/0").contains(ip("::1")));
+    }
+}
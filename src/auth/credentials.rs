@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+
+use crate::crypto;
+
+/// Characters of generated access key ids, the RFC 4648 base32 alphabet
+const KEY_ID_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// An access key pair clients sign requests with
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credential {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+
+impl Credential {
+    /// Root key pair from `FILIA_ACCESS_KEY_ID` / `FILIA_SECRET_ACCESS_KEY`, if both are set
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(&|name| std::env::var(name).ok())
+    }
+
+
+    /// Root key pair from the variables `var` looks up, see `from_env`
+    pub fn from_vars(var: &dyn Fn(&str) -> Option<String>) -> Option<Self> {
+        match (var("FILIA_ACCESS_KEY_ID"), var("FILIA_SECRET_ACCESS_KEY")) {
+            (Some(access_key_id), Some(secret_access_key)) if !access_key_id.is_empty() => {
+                Some(Self { access_key_id, secret_access_key })
+            }
+            _ => None,
+        }
+    }
+
+
+    /// A new random key pair shaped like AWS ones: a 20 character id starting
+    /// with `AKIA` and a 40 character secret
+    pub fn generate() -> Self {
+        let access_key_id = "AKIA"
+            .chars()
+            .chain(crypto::random_bytes(16).iter().map(|b| KEY_ID_ALPHABET[*b as usize % 32] as char))
+            .collect();
+
+        Self { access_key_id, secret_access_key: BASE64.encode(crypto::random_bytes(30)) }
+    }
+}
+
+
+/// Never print the secret
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credential")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+
+/// Who an authenticated request was signed by
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub access_key_id: String,
+    /// User the access key belongs to, `None` for the root credentials of the
+    /// config, which may do anything
+    pub user: Option<String>,
+    /// Id the principal owns buckets and objects and is granted ACLs by: the
+    /// generated id of a user, `root:` and the access key id for the root
+    /// credentials. Unlike names these are never reused.
+    pub canonical_id: String,
+}
+
+
+impl Principal {
+
+    /// The root credentials with `access_key_id`
+    pub fn root(access_key_id: &str) -> Self {
+        Self {
+            access_key_id: access_key_id.to_string(),
+            user: None,
+            canonical_id: format!("root:{access_key_id}"),
+        }
+    }
+}
+
+
+/// Access keys the server accepts, looked up by access key id
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStore {
+    keys: Arc<HashMap<String, Credential>>,
+}
+
+
+impl CredentialStore {
+
+    pub fn new(credentials: impl IntoIterator<Item = Credential>) -> Self {
+        let keys = credentials
+            .into_iter()
+            .map(|c| (c.access_key_id.clone(), c))
+            .collect();
+
+        Self { keys: Arc::new(keys) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get(&self, access_key_id: &str) -> Option<&Credential> {
+        self.keys.get(access_key_id)
+    }
+}
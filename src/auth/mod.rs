@@ -0,0 +1,14 @@
+mod action;
+mod chunked;
+mod condition;
+mod credentials;
+mod policy;
+mod presign;
+pub mod sigv4;
+
+pub use action::{COPY_SOURCE_HEADER, unsupported_subresource};
+pub use chunked::{Framing, decode_chunked};
+pub use condition::IpNetwork;
+pub use credentials::{Credential, CredentialStore, Principal};
+pub use policy::{AccessRequest, Decision, PolicyDocument, evaluate};
+pub use presign::presign_url;
@@ -0,0 +1,489 @@
+//! IAM style JSON policy documents and their evaluation: the identity
+//! policies attached to users and groups, and bucket policies, which also
+//! name the principals their statements are about.
+//!
+//! A request is allowed when a statement of some policy allows it and none
+//! denies it; anything not explicitly allowed is denied.
+//!
+//! See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html>
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Deserializer, de::{DeserializeOwned, Error}};
+
+use crate::error::{AuthError, AuthResult};
+
+use super::{Principal, condition::{self, Condition}};
+
+/// Policy language versions AWS accepts
+const VERSIONS: [&str; 2] = ["2012-10-17", "2008-10-17"];
+
+/// Resources are S3 ARNs, `arn:aws:s3:::bucket` or `arn:aws:s3:::bucket/key`
+pub const ARN_PREFIX: &str = "arn:aws:s3:::";
+
+/// Principals are `arn:aws:iam::<account>:user/<name>` for users and
+/// `arn:aws:iam::<account>:root` for the root credentials, any account
+const PRINCIPAL_ARN_PREFIX: &str = "arn:aws:iam::";
+
+
+#[allow(dead_code)] // Id and Sid only label documents and statements
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct PolicyDocument {
+    pub version: Option<String>,
+    pub id: Option<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub statement: Vec<Statement>,
+}
+
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct Statement {
+    pub sid: Option<String>,
+    pub effect: Effect,
+    /// Who the statement applies to, only in bucket policies
+    #[serde(default, deserialize_with = "principals")]
+    pub principal: Option<Vec<String>>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub action: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub resource: Vec<String>,
+    /// All of these have to hold for the statement to apply
+    #[serde(default, deserialize_with = "condition::deserialize")]
+    pub condition: Vec<Condition>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+
+/// What policies say about a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    /// A statement denies the request, whatever else allows it
+    Deny,
+    /// No statement applies, which denies the request too
+    Implicit,
+}
+
+
+/// An action on a resource, e.g. `s3:GetObject` on `arn:aws:s3:::photos/cat.jpg`,
+/// and what is known about who asks for it
+#[derive(Debug, Clone)]
+pub struct AccessRequest {
+    pub action: &'static str,
+    pub resource: String,
+    /// Bucket whose policy applies, if the request is about one
+    pub bucket: Option<String>,
+    /// Object key and version, for the object ACL to consult
+    pub key: Option<String>,
+    pub version_id: Option<String>,
+    /// Who signed the request, `None` for anonymous requests
+    pub principal: Option<Principal>,
+    pub source_ip: Option<IpAddr>,
+    pub secure_transport: bool,
+}
+
+
+impl PolicyDocument {
+
+    /// Parse and check an identity policy, as given to the admin API
+    pub fn parse(json: &str) -> AuthResult<Self> {
+        let document: Self = serde_json::from_str(json).map_err(|e| AuthError::MalformedPolicy(e.to_string()))?;
+
+        document.validate()?;
+
+        if document.statement.iter().any(|s| s.principal.is_some()) {
+            return Err(AuthError::MalformedPolicy(
+                "policies of users and groups apply to them and cannot have a Principal".to_string(),
+            ));
+        }
+
+        Ok(document)
+    }
+
+
+    /// Parse and check the policy of `bucket`: every statement names its
+    /// principals and is about the bucket or objects in it
+    pub fn parse_bucket_policy(json: &str, bucket: &str) -> AuthResult<Self> {
+        let document: Self = serde_json::from_str(json).map_err(|e| AuthError::MalformedPolicy(e.to_string()))?;
+
+        document.validate()?;
+
+        let bucket_arn = format!("{ARN_PREFIX}{bucket}");
+
+        for statement in &document.statement {
+            if statement.principal.is_none() {
+                return Err(AuthError::MalformedPolicy("every statement needs a Principal".to_string()));
+            }
+
+            let in_bucket = |r: &&String| **r == bucket_arn || r.starts_with(&format!("{bucket_arn}/"));
+
+            if let Some(resource) = statement.resource.iter().find(|r| !in_bucket(r)) {
+                return Err(AuthError::MalformedPolicy(format!(
+                    "resource {resource} is not bucket {bucket} or an object in it"
+                )));
+            }
+        }
+
+        Ok(document)
+    }
+
+
+    fn validate(&self) -> AuthResult<()> {
+        let malformed = |msg: String| Err(AuthError::MalformedPolicy(msg));
+
+        if let Some(version) = &self.version && !VERSIONS.contains(&version.as_str()) {
+            return malformed(format!("unsupported Version {version}, use {}", VERSIONS[0]));
+        }
+
+        if self.statement.is_empty() {
+            return malformed("Statement must not be empty".to_string());
+        }
+
+        for statement in &self.statement {
+            if statement.action.is_empty() || statement.resource.is_empty() {
+                return malformed("every statement needs an Action and a Resource".to_string());
+            }
+
+            if let Some(action) = statement.action.iter().find(|a| *a != "*" && !a.to_ascii_lowercase().starts_with("s3:")) {
+                return malformed(format!("unsupported action {action}, actions start with s3:"));
+            }
+
+            if let Some(resource) = statement.resource.iter().find(|r| *r != "*" && !r.starts_with(ARN_PREFIX)) {
+                return malformed(format!("unsupported resource {resource}, resources start with {ARN_PREFIX}"));
+            }
+        }
+
+        Ok(())
+    }
+
+
+    pub fn evaluate(&self, request: &AccessRequest) -> Decision {
+        let mut decision = Decision::Implicit;
+
+        for statement in self.statement.iter().filter(|s| s.applies_to(request)) {
+            match statement.effect {
+                Effect::Deny => return Decision::Deny,
+                Effect::Allow => decision = Decision::Allow,
+            }
+        }
+
+        decision
+    }
+}
+
+
+impl Statement {
+    fn applies_to(&self, request: &AccessRequest) -> bool {
+        let action = request.action.to_ascii_lowercase();
+
+        self.principal.as_ref().is_none_or(|principals| principals.iter().any(|p| principal_match(p, request)))
+            && self.action.iter().any(|pattern| wildcard_match(&pattern.to_ascii_lowercase(), &action))
+            && self.resource.iter().any(|pattern| wildcard_match(pattern, &request.resource))
+            && self.condition.iter().all(|condition| condition.matches(request))
+    }
+}
+
+
+/// Whether a principal of a bucket policy names who signed the request.
+/// Only `*` covers anonymous requests.
+fn principal_match(principal: &str, request: &AccessRequest) -> bool {
+    if principal == "*" {
+        return true;
+    }
+
+    let Some(signer) = &request.principal else {
+        return false;
+    };
+
+    match principal_arn(principal) {
+        Some(PrincipalArn::Root) => signer.user.is_none(),
+        Some(PrincipalArn::User(name)) => signer.user.as_deref() == Some(name),
+        None => false,
+    }
+}
+
+
+enum PrincipalArn<'a> {
+    Root,
+    User(&'a str),
+}
+
+
+fn principal_arn(arn: &str) -> Option<PrincipalArn<'_>> {
+    let (_account, resource) = arn.strip_prefix(PRINCIPAL_ARN_PREFIX)?.split_once(':')?;
+
+    match resource {
+        "root" => Some(PrincipalArn::Root),
+        _ => resource.strip_prefix("user/").filter(|name| !name.is_empty()).map(PrincipalArn::User),
+    }
+}
+
+
+/// Combine the decisions of several policies: one deny wins over any allow
+pub fn evaluate(documents: &[PolicyDocument], request: &AccessRequest) -> Decision {
+    let mut decision = Decision::Implicit;
+
+    for document in documents {
+        match document.evaluate(request) {
+            Decision::Deny => return Decision::Deny,
+            Decision::Allow => decision = Decision::Allow,
+            Decision::Implicit => {}
+        }
+    }
+
+    decision
+}
+
+
+/// Match `value` against a pattern where `*` stands for any run of characters
+/// and `?` for any single one
+pub fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    // where the last `*` was, and the value position it currently swallows up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        // a `*` is a wildcard even where the value has a `*` too
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, v));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if let Some((star_p, star_v)) = star {
+            // let the `*` swallow one more character and retry
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+
+/// `"Principal": "*"` or `"Principal": {"AWS": <ARN or "*", or an array of them>}`
+fn principals<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Aws {
+        #[serde(rename = "AWS", deserialize_with = "one_or_many")]
+        aws: Vec<String>,
+    }
+
+    let principals = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) if s == "*" => vec![s],
+        value => serde_json::from_value::<Aws>(value).map_err(D::Error::custom)?.aws,
+    };
+
+    if let Some(principal) = principals.iter().find(|p| *p != "*" && principal_arn(p).is_none()) {
+        return Err(D::Error::custom(format!(
+            "invalid principal {principal}, use \"*\", {PRINCIPAL_ARN_PREFIX}<account>:user/<name> or \
+             {PRINCIPAL_ARN_PREFIX}<account>:root"
+        )));
+    }
+
+    Ok(Some(principals))
+}
+
+
+/// A single value or an array of them, as policy documents allow for most fields
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+
+    match value {
+        serde_json::Value::Array(_) => serde_json::from_value(value),
+        value => serde_json::from_value(value).map(|one| vec![one]),
+    }
+    .map_err(D::Error::custom)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(action: &'static str, resource: &str, user: Option<&str>) -> AccessRequest {
+        AccessRequest {
+            action,
+            resource: resource.to_string(),
+            bucket: None,
+            key: None,
+            version_id: None,
+            principal: user.map(|name| Principal {
+                access_key_id: "AKIAEXAMPLE".to_string(),
+                user: Some(name.to_string()),
+                canonical_id: format!("{name}-id"),
+            }),
+            source_ip: Some("192.0.2.10".parse().unwrap()),
+            secure_transport: false,
+        }
+    }
+
+    #[test]
+    fn wildcards() {
+        for (pattern, value) in [
+            ("*", ""),
+            ("*", "anything"),
+            ("abc", "abc"),
+            ("a*", "a"),
+            ("a*c", "abbbc"),
+            ("a?c", "abc"),
+            ("*/*.jpg", "photos/2024/cat.jpg"),
+            ("a*b*c", "axxbyybzzc"),
+            ("**", "x"),
+            ("ü?", "üß"),
+            ("*", "*x"),
+            ("b/*", "b/*secret"),
+            ("a*c", "a*bc"),
+        ] {
+            assert!(wildcard_match(pattern, value), "{pattern} should match {value}");
+        }
+
+        for (pattern, value) in [
+            ("", "a"),
+            ("abc", "abd"),
+            ("abc", "ab"),
+            ("a?c", "ac"),
+            ("a*c", "abcd"),
+            ("*.jpg", "cat.jpeg"),
+            ("a*b*c", "axxbyy"),
+        ] {
+            assert!(!wildcard_match(pattern, value), "{pattern} should not match {value}");
+        }
+    }
+
+    #[test]
+    fn deny_beats_allow() {
+        let document = PolicyDocument::parse(
+            r#"{
+                "Version": "2012-10-17",
+                "Statement": [
+                    {"Effect": "Allow", "Action": "s3:*", "Resource": "arn:aws:s3:::photos/*"},
+                    {"Effect": "Deny", "Action": "s3:DeleteObject", "Resource": "arn:aws:s3:::photos/keep/*"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::photos/keep/a", None)), Decision::Allow);
+        assert_eq!(document.evaluate(&request("s3:DeleteObject", "arn:aws:s3:::photos/a", None)), Decision::Allow);
+        assert_eq!(document.evaluate(&request("s3:DeleteObject", "arn:aws:s3:::photos/keep/a", None)), Decision::Deny);
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::other/a", None)), Decision::Implicit);
+        assert_eq!(document.evaluate(&request("s3:ListBucket", "arn:aws:s3:::photos", None)), Decision::Implicit);
+    }
+
+    #[test]
+    fn deny_covers_keys_starting_with_a_star() {
+        let document = PolicyDocument::parse(
+            r#"{
+                "Statement": [
+                    {"Effect": "Allow", "Action": "s3:*", "Resource": "*"},
+                    {"Effect": "Deny", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::bucket/*secret", None)), Decision::Deny);
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::bucket/*", None)), Decision::Deny);
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::other/*secret", None)), Decision::Allow);
+    }
+
+    #[test]
+    fn actions_ignore_case() {
+        let document =
+            PolicyDocument::parse(r#"{"Statement": {"Effect": "Allow", "Action": "S3:get*", "Resource": "*"}}"#).unwrap();
+
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::b/k", None)), Decision::Allow);
+        assert_eq!(document.evaluate(&request("s3:PutObject", "arn:aws:s3:::b/k", None)), Decision::Implicit);
+    }
+
+    #[test]
+    fn deny_in_any_document_wins() {
+        let allow = PolicyDocument::parse(r#"{"Statement": {"Effect": "Allow", "Action": "*", "Resource": "*"}}"#).unwrap();
+        let deny = PolicyDocument::parse(
+            r#"{"Statement": {"Effect": "Deny", "Action": "s3:PutObject", "Resource": "arn:aws:s3:::b/*"}}"#,
+        )
+        .unwrap();
+        let documents = [allow, deny];
+
+        assert_eq!(evaluate(&documents, &request("s3:GetObject", "arn:aws:s3:::b/k", None)), Decision::Allow);
+        assert_eq!(evaluate(&documents, &request("s3:PutObject", "arn:aws:s3:::b/k", None)), Decision::Deny);
+        assert_eq!(evaluate(&documents[1..], &request("s3:GetObject", "arn:aws:s3:::b/k", None)), Decision::Implicit);
+        assert_eq!(evaluate(&[], &request("s3:GetObject", "arn:aws:s3:::b/k", None)), Decision::Implicit);
+    }
+
+    #[test]
+    fn bucket_policy_principals_and_conditions() {
+        let document = PolicyDocument::parse_bucket_policy(
+            r#"{
+                "Statement": [
+                    {"Effect": "Allow", "Principal": "*", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::b/public/*"},
+                    {"Effect": "Allow", "Principal": {"AWS": "arn:aws:iam::123456789012:user/alice"},
+                     "Action": "s3:PutObject", "Resource": "arn:aws:s3:::b/*",
+                     "Condition": {"IpAddress": {"aws:SourceIp": "192.0.2.0/24"}}},
+                    {"Effect": "Deny", "Principal": "*", "Action": "s3:*", "Resource": "arn:aws:s3:::b/*",
+                     "Condition": {"NotIpAddress": {"aws:SourceIp": ["192.0.2.0/24", "2001:db8::/32"]}}}
+                ]
+            }"#,
+            "b",
+        )
+        .unwrap();
+
+        assert_eq!(document.evaluate(&request("s3:GetObject", "arn:aws:s3:::b/public/a", None)), Decision::Allow);
+        assert_eq!(document.evaluate(&request("s3:PutObject", "arn:aws:s3:::b/a", None)), Decision::Implicit);
+        assert_eq!(document.evaluate(&request("s3:PutObject", "arn:aws:s3:::b/a", Some("bob"))), Decision::Implicit);
+        assert_eq!(document.evaluate(&request("s3:PutObject", "arn:aws:s3:::b/a", Some("alice"))), Decision::Allow);
+
+        let mut elsewhere = request("s3:PutObject", "arn:aws:s3:::b/a", Some("alice"));
+        elsewhere.source_ip = Some("198.51.100.1".parse().unwrap());
+        assert_eq!(document.evaluate(&elsewhere), Decision::Deny);
+
+        // an unknown address is in no network
+        elsewhere.source_ip = None;
+        assert_eq!(document.evaluate(&elsewhere), Decision::Deny);
+    }
+
+    #[test]
+    fn rejects_malformed_policies() {
+        for json in [
+            r#"{"Statement": []}"#,
+            r#"{"Version": "2020-01-01", "Statement": {"Effect": "Allow", "Action": "*", "Resource": "*"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Action": "iam:*", "Resource": "*"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Action": "*", "Resource": "arn:aws:sqs:::q"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "*", "Resource": "*"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Action": "*", "Resource": "*",
+                "Condition": {"StringEquals": {"aws:UserAgent": "x"}}}}"#,
+        ] {
+            assert!(PolicyDocument::parse(json).is_err(), "{json}");
+        }
+
+        for json in [
+            r#"{"Statement": {"Effect": "Allow", "Action": "*", "Resource": "arn:aws:s3:::b/*"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "*", "Resource": "arn:aws:s3:::other/*"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Principal": "*", "Action": "*", "Resource": "arn:aws:s3:::bucket"}}"#,
+            r#"{"Statement": {"Effect": "Allow", "Principal": {"AWS": "alice"}, "Action": "*", "Resource": "arn:aws:s3:::b"}}"#,
+        ] {
+            assert!(PolicyDocument::parse_bucket_policy(json, "b").is_err(), "{json}");
+        }
+    }
+}
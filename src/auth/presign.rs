@@ -0,0 +1,168 @@
+use axum::http::{HeaderMap, HeaderValue, Method, Uri, header};
+use chrono::{DateTime, Utc};
+
+use crate::error::{AuthError, AuthResult};
+
+use super::{
+    Credential,
+    sigv4::{self, Scope},
+};
+
+
+/// Build a presigned URL for `method` on `bucket/key`, valid for `expires_secs`
+/// from `now`. `endpoint` is the scheme and authority clients will use, e.g.
+/// `https://s3.example.com`, since the host is part of the signature.
+pub fn presign_url(
+    credential: &Credential,
+    method: &Method,
+    endpoint: &str,
+    bucket: &str,
+    key: &str,
+    expires_secs: i64,
+    now: DateTime<Utc>,
+) -> AuthResult<String> {
+    if !(1..=sigv4::MAX_PRESIGN_EXPIRY_SECS).contains(&expires_secs) {
+        return Err(AuthError::MalformedAuthorization(format!(
+            "expiry must be between 1 and {} seconds",
+            sigv4::MAX_PRESIGN_EXPIRY_SECS
+        )));
+    }
+
+    let endpoint = endpoint.trim_end_matches('/');
+    let host = endpoint
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .filter(|host| !host.is_empty() && !host.contains('/'))
+        .ok_or_else(|| AuthError::MalformedAuthorization(format!("invalid endpoint '{endpoint}'")))?;
+
+    let amz_date = sigv4::format_amz_date(&now);
+    let scope = Scope {
+        date: amz_date[..8].to_string(),
+        region: sigv4::DEFAULT_REGION.to_string(),
+    };
+
+    let path = format!("/{}/{}", sigv4::uri_encode(bucket, true), sigv4::uri_encode(key, false));
+    let query = [
+        ("X-Amz-Algorithm", sigv4::ALGORITHM.to_string()),
+        ("X-Amz-Credential", format!("{}/{}", credential.access_key_id, scope)),
+        ("X-Amz-Date", amz_date.clone()),
+        ("X-Amz-Expires", expires_secs.to_string()),
+        ("X-Amz-SignedHeaders", "host".to_string()),
+    ]
+    .iter()
+    .map(|(k, v)| format!("{}={}", k, sigv4::uri_encode(v, true)))
+    .collect::<Vec<_>>()
+    .join("&");
+
+    let unsigned = format!("{endpoint}{path}?{query}");
+
+    // sign exactly what the verifier will reconstruct from the request
+    let uri: Uri = unsigned
+        .parse()
+        .map_err(|_| AuthError::MalformedAuthorization(format!("invalid URL '{unsigned}'")))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::HOST,
+        HeaderValue::from_str(host).map_err(|_| AuthError::MalformedAuthorization(format!("invalid host '{host}'")))?,
+    );
+
+    let canonical_request = sigv4::canonical_request(
+        method,
+        &uri,
+        &headers,
+        &["host".to_string()],
+        sigv4::UNSIGNED_PAYLOAD,
+        true,
+    )?;
+
+    let string_to_sign = sigv4::string_to_sign(&amz_date, &scope, &canonical_request);
+    let signature = sigv4::hmac(
+        &sigv4::signing_key(&credential.secret_access_key, &scope),
+        string_to_sign.as_bytes(),
+    );
+
+    Ok(format!("{unsigned}&X-Amz-Signature={}", hex::encode(signature)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn credential() -> Credential {
+        Credential { access_key_id: "AKIAEXAMPLE".to_string(), secret_access_key: "secret+/key".to_string() }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    /// The request a client makes with a presigned URL, as the server sees it
+    fn request(url: &str) -> (Uri, HeaderMap) {
+        let (endpoint, path_and_query) = url.split_at(url.find("/bucket").unwrap());
+        let host = endpoint.split_once("://").unwrap().1;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_str(host).unwrap());
+
+        (path_and_query.parse().unwrap(), headers)
+    }
+
+    fn check(url: &str, method: &Method, at: DateTime<Utc>) -> AuthResult<()> {
+        let (uri, headers) = request(url);
+        let signed = sigv4::parse(&uri, &headers, at)?;
+
+        sigv4::verify(&signed, &credential(), method, &uri, &headers)
+    }
+
+    #[test]
+    fn round_trips() {
+        for key in ["plain.txt", "dir/with spaces/a+b c.txt", "odd/%41?&=#.bin", "ünï/cödé"] {
+            let url = presign_url(&credential(), &Method::GET, "http://localhost:3000", "bucket", key, 600, now()).unwrap();
+            let (uri, _) = request(&url);
+
+            assert_eq!(percent_encoding::percent_decode_str(uri.path()).decode_utf8().unwrap(), format!("/bucket/{key}"));
+            check(&url, &Method::GET, now() + Duration::seconds(599)).unwrap();
+        }
+
+        let url = presign_url(&credential(), &Method::PUT, "https://s3.example.com/", "bucket", "a+b", 60, now()).unwrap();
+        assert!(url.starts_with("https://s3.example.com/bucket/a%2Bb?"));
+        check(&url, &Method::PUT, now()).unwrap();
+    }
+
+    #[test]
+    fn is_bound_to_method_key_and_host() {
+        let url = presign_url(&credential(), &Method::GET, "http://localhost:3000", "bucket", "a b", 600, now()).unwrap();
+
+        assert!(matches!(check(&url, &Method::PUT, now()), Err(AuthError::SignatureMismatch)));
+        assert!(matches!(
+            check(&url.replace("/bucket/a%20b", "/bucket/a%20c"), &Method::GET, now()),
+            Err(AuthError::SignatureMismatch)
+        ));
+        assert!(matches!(
+            check(&url.replace("localhost:3000", "localhost:3001"), &Method::GET, now()),
+            Err(AuthError::SignatureMismatch)
+        ));
+    }
+
+    #[test]
+    fn expires() {
+        let url = presign_url(&credential(), &Method::GET, "http://localhost:3000", "bucket", "k", 600, now()).unwrap();
+
+        assert!(matches!(check(&url, &Method::GET, now() + Duration::seconds(601)), Err(AuthError::RequestExpired)));
+    }
+
+    #[test]
+    fn rejects_bad_expiry_and_endpoint() {
+        for expires in [0, sigv4::MAX_PRESIGN_EXPIRY_SECS + 1] {
+            assert!(presign_url(&credential(), &Method::GET, "http://localhost", "bucket", "k", expires, now()).is_err());
+        }
+
+        for endpoint in ["localhost:3000", "http://", "http://host/path"] {
+            assert!(presign_url(&credential(), &Method::GET, endpoint, "bucket", "k", 60, now()).is_err());
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Offline subcommands, `filia_s3 <command> ...` runs one of these instead of the server
+
+use std::error::Error;
+
+use axum::http::Method;
+use chrono::Utc;
+
+use crate::auth::{self, Credential};
+
+const USAGE: &str = "usage: filia_s3 presign [--method GET|PUT] [--expires SECS] [--endpoint URL] <bucket> <key>";
+
+const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:3000";
+const DEFAULT_EXPIRES_SECS: i64 = 60 * 60;
+
+
+pub fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match args.first().map(String::as_str) {
+        Some("presign") => presign(&args[1..]),
+        _ => Err(USAGE.into()),
+    }
+}
+
+
+/// Print a presigned URL signed with the key pair from the environment
+fn presign(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut method = Method::GET;
+    let mut expires = DEFAULT_EXPIRES_SECS;
+    let mut endpoint = DEFAULT_ENDPOINT.to_string();
+    let mut positional = Vec::new();
+
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--method" => {
+                method = match iter.next().map(|m| m.to_ascii_uppercase()).as_deref() {
+                    Some("GET") => Method::GET,
+                    Some("PUT") => Method::PUT,
+                    _ => return Err("--method must be GET or PUT".into()),
+                }
+            }
+            "--expires" => {
+                expires = iter.next().ok_or(USAGE)?.parse()?;
+            }
+            "--endpoint" => {
+                endpoint = iter.next().ok_or(USAGE)?.clone();
+            }
+            other if other.starts_with("--") => return Err(format!("unknown option {other}\n{USAGE}").into()),
+            other => positional.push(other),
+        }
+    }
+
+    let [bucket, key] = positional.as_slice() else {
+        return Err(USAGE.into());
+    };
+
+    let credential = Credential::from_env()
+        .ok_or("FILIA_ACCESS_KEY_ID and FILIA_SECRET_ACCESS_KEY must be set to presign URLs")?;
+
+    let url = auth::presign_url(&credential, &method, &endpoint, bucket, key, expires, Utc::now())?;
+
+    println!("{url}");
+
+    Ok(())
+}
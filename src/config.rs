@@ -0,0 +1,442 @@
+//! Server settings, read from a TOML file and then overridden by `FILIA_*`
+//! environment variables. See `filia.example.toml` for every setting.
+
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    auth::{Credential, IpNetwork},
+    crypto::MasterKey,
+    error::{ConfigError, ConfigResult as Result},
+};
+
+/// Read when `FILIA_CONFIG` doesn't name another file, if it exists
+const DEFAULT_CONFIG_FILE: &str = "filia.toml";
+
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address the server listens on
+    pub listen: SocketAddr,
+    /// `tracing` filter directives, e.g. `info` or `filia_s3=debug,warn`
+    pub log_level: String,
+    /// Seconds between runs of the worker applying bucket lifecycle rules
+    pub lifecycle_interval_secs: u64,
+    /// Seconds running requests get to finish after SIGTERM or SIGINT before
+    /// their uploads and downloads are cut off
+    pub shutdown_timeout_secs: u64,
+    pub storage: StorageConfig,
+    pub limits: Limits,
+    /// Root access keys requests must be signed with, none to run without
+    /// authentication. They may do anything, unlike the keys of users, and
+    /// are required once there is a master key or users.
+    pub credentials: Vec<Credential>,
+    /// 32 byte AES key, base64 encoded, the secrets of users' access keys and
+    /// the data keys of encrypted objects are sealed with. Users can't be given
+    /// access keys and objects can't be encrypted without one.
+    pub master_key: Option<MasterKey>,
+    /// Addresses or CIDR blocks of the reverse proxies in front of the
+    /// server. Only requests coming from them are believed about the client
+    /// address and scheme in `X-Forwarded-For` and `X-Forwarded-Proto`.
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: BackendKind,
+    /// Directory object data is kept in by the filesystem backend
+    pub data_root: PathBuf,
+    /// sqlx SQLite URL of the database, under the data root unless set.
+    /// The memory backend always keeps it in memory.
+    pub database_url: Option<String>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Filesystem,
+    /// Everything, database included, is kept in memory until the process exits
+    Memory,
+}
+
+
+/// Caps on what a single request may send
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Largest body of a single PutObject or UploadPart
+    pub max_object_size: u64,
+    /// Largest request body read into memory, like the XML of a
+    /// CompleteMultipartUpload. Object data is streamed and not limited by it.
+    pub max_request_body_size: usize,
+}
+
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            log_level: "info".to_string(),
+            lifecycle_interval_secs: 60 * 60,
+            // a little less than the 30 seconds Kubernetes waits before killing a pod
+            shutdown_timeout_secs: 25,
+            storage: StorageConfig::default(),
+            limits: Limits::default(),
+            credentials: Vec::new(),
+            master_key: None,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendKind::Filesystem,
+            data_root: PathBuf::from("./data"),
+            database_url: None,
+        }
+    }
+}
+
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            // same as S3
+            max_object_size: 5 * 1024 * 1024 * 1024,
+            max_request_body_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+
+impl Config {
+
+    /// Read the file named by `FILIA_CONFIG`, or `filia.toml` if there is one,
+    /// apply the environment overrides and check the result
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Env(&|name| std::env::var(name).ok()))
+    }
+
+
+    fn load_from(env: &Env) -> Result<Self> {
+        let mut config = match env.get("FILIA_CONFIG") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Self::default(),
+        };
+
+        config.apply_env(env)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse { path: path.display().to_string(), source })
+    }
+
+
+    fn apply_env(&mut self, env: &Env) -> Result<()> {
+        if let Some(listen) = env.parse("FILIA_LISTEN")? {
+            self.listen = listen;
+        }
+
+        if let Some(log_level) = env.get("FILIA_LOG_LEVEL") {
+            self.log_level = log_level;
+        }
+
+        if let Some(secs) = env.parse("FILIA_LIFECYCLE_INTERVAL_SECS")? {
+            self.lifecycle_interval_secs = secs;
+        }
+
+        if let Some(secs) = env.parse("FILIA_SHUTDOWN_TIMEOUT_SECS")? {
+            self.shutdown_timeout_secs = secs;
+        }
+
+        if let Some(backend) = env.parse("FILIA_STORAGE_BACKEND")? {
+            self.storage.backend = backend;
+        }
+
+        if let Some(data_root) = env.get("FILIA_DATA_ROOT") {
+            self.storage.data_root = PathBuf::from(data_root);
+        }
+
+        if let Some(database_url) = env.get("FILIA_DATABASE_URL") {
+            self.storage.database_url = Some(database_url);
+        }
+
+        if let Some(size) = env.parse("FILIA_MAX_OBJECT_SIZE")? {
+            self.limits.max_object_size = size;
+        }
+
+        if let Some(size) = env.parse("FILIA_MAX_REQUEST_BODY_SIZE")? {
+            self.limits.max_request_body_size = size;
+        }
+
+        if let Some(master_key) = env.parse("FILIA_MASTER_KEY")? {
+            self.master_key = Some(master_key);
+        }
+
+        if let Some(proxies) = env.get("FILIA_TRUSTED_PROXIES") {
+            self.trusted_proxies = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| proxy.parse().map_err(|e| invalid("FILIA_TRUSTED_PROXIES", e)))
+                .collect::<Result<_>>()?;
+        }
+
+        // the root key pair from the environment replaces a key with the same id from the file
+        if let Some(credential) = Credential::from_vars(env.0) {
+            self.credentials.retain(|c| c.access_key_id != credential.access_key_id);
+            self.credentials.push(credential);
+        }
+
+        Ok(())
+    }
+
+
+    fn validate(&self) -> Result<()> {
+        if let Err(e) = EnvFilter::try_new(&self.log_level) {
+            return Err(invalid("log_level", e));
+        }
+
+        if self.lifecycle_interval_secs == 0 {
+            return Err(invalid("lifecycle_interval_secs", "must be at least 1"));
+        }
+
+        if self.storage.data_root.as_os_str().is_empty() {
+            return Err(invalid("storage.data_root", "must not be empty"));
+        }
+
+        if self.limits.max_object_size == 0 {
+            return Err(invalid("limits.max_object_size", "must be at least 1"));
+        }
+
+        if self.limits.max_request_body_size == 0 {
+            return Err(invalid("limits.max_request_body_size", "must be at least 1"));
+        }
+
+        // without root credentials nothing is authenticated, users' keys included
+        if self.master_key.is_some() && self.credentials.is_empty() {
+            return Err(invalid("credentials", "a root access key is required when master_key is set"));
+        }
+
+        for (i, credential) in self.credentials.iter().enumerate() {
+            if credential.access_key_id.is_empty() || credential.secret_access_key.is_empty() {
+                return Err(invalid("credentials", "access_key_id and secret_access_key must not be empty"));
+            }
+
+            if self.credentials[..i].iter().any(|c| c.access_key_id == credential.access_key_id) {
+                return Err(invalid("credentials", format!("access key {} is listed twice", credential.access_key_id)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+impl StorageConfig {
+
+    pub fn database_url(&self) -> String {
+        match (self.backend, &self.database_url) {
+            (BackendKind::Memory, _) => "sqlite::memory:".to_string(),
+            (BackendKind::Filesystem, Some(url)) => url.clone(),
+            (BackendKind::Filesystem, None) => {
+                format!("sqlite://{}", self.data_root.join(".metadata/filia.db").display())
+            }
+        }
+    }
+}
+
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "filesystem" => Ok(Self::Filesystem),
+            "memory" => Ok(Self::Memory),
+            other => Err(format!("unknown storage backend {other}, use filesystem or memory")),
+        }
+    }
+}
+
+
+/// Environment variables overriding the settings of the file, looked up in
+/// the process environment outside of tests
+struct Env<'a>(&'a dyn Fn(&str) -> Option<String>);
+
+impl Env<'_> {
+
+    /// A set, non-empty variable. Empty ones are treated as unset so a copied
+    /// `.env.example` doesn't override anything.
+    fn get(&self, name: &str) -> Option<String> {
+        (self.0)(name).filter(|v| !v.is_empty())
+    }
+
+
+    fn parse<T>(&self, name: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get(name)
+            .map(|value| value.parse().map_err(|e| invalid(name, format!("{value:?}: {e}"))))
+            .transpose()
+    }
+}
+
+
+fn invalid(name: &str, message: impl Display) -> ConfigError {
+    ConfigError::Invalid { name: name.to_string(), message: message.to_string() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("filia-config-{}.toml", uuid::Uuid::new_v4()))
+    }
+
+    fn config_file(contents: &str) -> PathBuf {
+        let path = temp_path();
+        std::fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    fn load(vars: &[(&str, &str)]) -> Result<Config> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+        Config::load_from(&Env(&|name| vars.get(name).cloned()))
+    }
+
+    const FILE: &str = r#"
+        listen = "0.0.0.0:9000"
+        lifecycle_interval_secs = 60
+        trusted_proxies = ["10.0.0.0/8"]
+
+        [storage]
+        backend = "memory"
+
+        [limits]
+        max_object_size = 1024
+
+        [[credentials]]
+        access_key_id = "filekey"
+        secret_access_key = "filesecret"
+    "#;
+
+    #[test]
+    fn reads_the_file() {
+        let path = config_file(FILE);
+        let config = load(&[("FILIA_CONFIG", path.to_str().unwrap())]).unwrap();
+
+        assert_eq!(config.listen, SocketAddr::from(([0, 0, 0, 0], 9000)));
+        assert_eq!(config.lifecycle_interval_secs, 60);
+        assert_eq!(config.storage.backend, BackendKind::Memory);
+        assert_eq!(config.storage.database_url(), "sqlite::memory:");
+        assert_eq!(config.limits.max_object_size, 1024);
+        assert_eq!(config.trusted_proxies.len(), 1);
+        assert!(config.trusted_proxies[0].contains("10.1.2.3".parse().unwrap()));
+        assert_eq!(config.credentials.len(), 1);
+
+        // unset settings keep their defaults
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.shutdown_timeout_secs, 25);
+        assert_eq!(config.limits.max_request_body_size, Limits::default().max_request_body_size);
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let path = config_file(FILE);
+        let config = load(&[
+            ("FILIA_CONFIG", path.to_str().unwrap()),
+            ("FILIA_LISTEN", "127.0.0.1:8080"),
+            ("FILIA_STORAGE_BACKEND", "filesystem"),
+            ("FILIA_DATA_ROOT", "/srv/filia"),
+            ("FILIA_MAX_OBJECT_SIZE", "2048"),
+            ("FILIA_TRUSTED_PROXIES", "192.0.2.1, 2001:db8::/32"),
+            // empty counts as unset
+            ("FILIA_LOG_LEVEL", ""),
+            ("FILIA_ACCESS_KEY_ID", "filekey"),
+            ("FILIA_SECRET_ACCESS_KEY", "envsecret"),
+        ])
+        .unwrap();
+
+        assert_eq!(config.listen, SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert_eq!(config.storage.backend, BackendKind::Filesystem);
+        assert_eq!(config.storage.database_url(), "sqlite:///srv/filia/.metadata/filia.db");
+        assert_eq!(config.limits.max_object_size, 2048);
+        assert_eq!(config.trusted_proxies.len(), 2);
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.lifecycle_interval_secs, 60);
+
+        // the root key from the environment replaces the one with its id
+        assert_eq!(config.credentials.len(), 1);
+        assert_eq!(config.credentials[0].secret_access_key, "envsecret");
+    }
+
+    #[test]
+    fn bad_environment_values_name_the_variable() {
+        for (name, value) in [
+            ("FILIA_LISTEN", "localhost"),
+            ("FILIA_LIFECYCLE_INTERVAL_SECS", "-1"),
+            ("FILIA_STORAGE_BACKEND", "s3"),
+            ("FILIA_MAX_OBJECT_SIZE", "5GB"),
+            ("FILIA_MASTER_KEY", "too short"),
+            ("FILIA_TRUSTED_PROXIES", "10.0.0.0/33"),
+        ] {
+            let empty = config_file("");
+
+            match load(&[("FILIA_CONFIG", empty.to_str().unwrap()), (name, value)]) {
+                Err(ConfigError::Invalid { name: invalid, .. }) => assert_eq!(invalid, name),
+                other => panic!("{name}={value} gave {other:?}"),
+            }
+        }
+
+        // parsed fine, but not a usable setting
+        let empty = config_file("");
+        assert!(matches!(
+            load(&[("FILIA_CONFIG", empty.to_str().unwrap()), ("FILIA_LIFECYCLE_INTERVAL_SECS", "0")]),
+            Err(ConfigError::Invalid { name, .. }) if name == "lifecycle_interval_secs",
+        ));
+    }
+
+    #[test]
+    fn unreadable_files() {
+        let missing = temp_path();
+        assert!(matches!(load(&[("FILIA_CONFIG", missing.to_str().unwrap())]), Err(ConfigError::Read { .. })));
+
+        for contents in ["listen = ", "listen = 3000", "no_such_setting = true"] {
+            let path = config_file(contents);
+            assert!(matches!(load(&[("FILIA_CONFIG", path.to_str().unwrap())]), Err(ConfigError::Parse { .. })));
+        }
+    }
+}
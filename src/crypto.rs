@@ -0,0 +1,82 @@
+//! Secrets the server keeps at rest are sealed with AES-256-GCM under a
+//! master key from the configuration.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, rand_core::RngCore},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Deserializer};
+
+/// Bytes of the random nonce sealed data starts with
+const NONCE_SIZE: usize = 12;
+
+
+/// The AES-256 key sealing secrets, given base64 encoded in the configuration
+#[derive(Clone)]
+pub struct MasterKey {
+    cipher: Arc<Aes256Gcm>,
+}
+
+
+impl MasterKey {
+
+    /// Encrypt `plaintext`, the random nonce goes in front of the ciphertext
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext).expect("AES-GCM encrypts any plaintext");
+
+        [nonce.as_slice(), &ciphertext].concat()
+    }
+
+
+    /// Decrypt what `seal` produced, `None` if it was sealed with another key or tampered with
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_SIZE {
+            return None;
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+    }
+}
+
+
+impl FromStr for MasterKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = BASE64.decode(s.trim()).map_err(|_| "must be base64 encoded".to_string())?;
+
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| format!("must be 32 bytes, got {}", key.len()))?;
+
+        Ok(Self { cipher: Arc::new(cipher) })
+    }
+}
+
+
+impl<'de> Deserialize<'de> for MasterKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+
+/// Never print the key
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterKey(..)")
+    }
+}
+
+
+/// `n` bytes from the operating system's secure random number generator
+pub fn random_bytes(n: usize) -> Vec<u8> {
+    let mut bytes = vec![0; n];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
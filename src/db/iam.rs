@@ -0,0 +1,364 @@
+use chrono::{DateTime, Utc};
+use sqlx::Row;
+
+use crate::{
+    error::{DbError, DbResult as Result},
+    storage::{GroupInfo, UserInfo},
+};
+
+use super::Database;
+
+
+/// What an inline policy document is attached to
+#[derive(Debug, Clone, Copy)]
+pub enum PolicyTarget {
+    User,
+    Group,
+}
+
+
+impl PolicyTarget {
+    fn noun(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Group => "group",
+        }
+    }
+
+    /// Table of the users or groups, and the one their policies are kept in
+    fn tables(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::User => ("users", "user_policies", "user_id"),
+            Self::Group => ("groups", "group_policies", "group_id"),
+        }
+    }
+}
+
+
+impl Database {
+
+    pub async fn create_user(&self, name: &str, canonical_id: &str) -> Result<()> {
+        sqlx::query("INSERT INTO users (name, canonical_id, created_at) VALUES (?, ?, ?)")
+            .bind(name)
+            .bind(canonical_id)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| already_exists(e, || format!("user {name}")))?;
+
+        Ok(())
+    }
+
+
+    /// Delete a user along with its access keys, policies, group memberships
+    /// and owner quota. Its buckets are kept, owned by an id nobody has anymore.
+    pub async fn delete_user(&self, name: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let canonical_id: String = sqlx::query_scalar("DELETE FROM users WHERE name = ? RETURNING canonical_id")
+            .bind(name)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| DbError::NoSuchEntity(format!("user {name}")))?;
+
+        sqlx::query("DELETE FROM owner_quotas WHERE owner = ?")
+            .bind(&canonical_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+
+    pub async fn get_user(&self, name: &str) -> Result<UserInfo> {
+        let (id, created_at) = self.entity(PolicyTarget::User, name).await?;
+
+        let canonical_id = sqlx::query_scalar("SELECT canonical_id FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let groups = sqlx::query_scalar(
+            "SELECT g.name FROM groups g JOIN group_members m ON m.group_id = g.id WHERE m.user_id = ? ORDER BY g.name"
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let access_keys = sqlx::query_scalar("SELECT access_key_id FROM access_keys WHERE user_id = ? ORDER BY created_at")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(UserInfo {
+            name: name.to_string(),
+            canonical_id,
+            created_at,
+            groups,
+            access_keys,
+            policies: self.policy_names(PolicyTarget::User, id).await?,
+        })
+    }
+
+
+    pub async fn list_users(&self) -> Result<Vec<UserInfo>> {
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM users ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut users = Vec::with_capacity(names.len());
+
+        for name in names {
+            users.push(self.get_user(&name).await?);
+        }
+
+        Ok(users)
+    }
+
+
+    pub async fn create_group(&self, name: &str) -> Result<()> {
+        sqlx::query("INSERT INTO groups (name, created_at) VALUES (?, ?)")
+            .bind(name)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| already_exists(e, || format!("group {name}")))?;
+
+        Ok(())
+    }
+
+
+    /// Delete a group along with its policies, its members are kept
+    pub async fn delete_group(&self, name: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM groups WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NoSuchEntity(format!("group {name}")));
+        }
+
+        Ok(())
+    }
+
+
+    pub async fn get_group(&self, name: &str) -> Result<GroupInfo> {
+        let (id, created_at) = self.entity(PolicyTarget::Group, name).await?;
+
+        let members = sqlx::query_scalar(
+            "SELECT u.name FROM users u JOIN group_members m ON m.user_id = u.id WHERE m.group_id = ? ORDER BY u.name"
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(GroupInfo {
+            name: name.to_string(),
+            created_at,
+            members,
+            policies: self.policy_names(PolicyTarget::Group, id).await?,
+        })
+    }
+
+
+    pub async fn list_groups(&self) -> Result<Vec<GroupInfo>> {
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM groups ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut groups = Vec::with_capacity(names.len());
+
+        for name in names {
+            groups.push(self.get_group(&name).await?);
+        }
+
+        Ok(groups)
+    }
+
+
+    /// Add a user to a group, nothing changes if it already is a member
+    pub async fn add_group_member(&self, group: &str, user: &str) -> Result<()> {
+        let (group_id, _) = self.entity(PolicyTarget::Group, group).await?;
+        let (user_id, _) = self.entity(PolicyTarget::User, user).await?;
+
+        sqlx::query("INSERT OR IGNORE INTO group_members (group_id, user_id) VALUES (?, ?)")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    pub async fn remove_group_member(&self, group: &str, user: &str) -> Result<()> {
+        let (group_id, _) = self.entity(PolicyTarget::Group, group).await?;
+        let (user_id, _) = self.entity(PolicyTarget::User, user).await?;
+
+        let result = sqlx::query("DELETE FROM group_members WHERE group_id = ? AND user_id = ?")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NoSuchEntity(format!("member {user} of group {group}")));
+        }
+
+        Ok(())
+    }
+
+
+    /// Attach a policy document to a user or group, replacing one of the same name
+    pub async fn put_policy(&self, target: PolicyTarget, owner: &str, name: &str, document: &str) -> Result<()> {
+        let (id, _) = self.entity(target, owner).await?;
+        let (_, policies, column) = target.tables();
+
+        sqlx::query(&format!(
+            "INSERT INTO {policies} ({column}, name, document) VALUES (?, ?, ?) \
+             ON CONFLICT({column}, name) DO UPDATE SET document = excluded.document"
+        ))
+        .bind(id)
+        .bind(name)
+        .bind(document)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+
+    pub async fn get_policy(&self, target: PolicyTarget, owner: &str, name: &str) -> Result<String> {
+        let (id, _) = self.entity(target, owner).await?;
+        let (_, policies, column) = target.tables();
+
+        sqlx::query_scalar(&format!("SELECT document FROM {policies} WHERE {column} = ? AND name = ?"))
+            .bind(id)
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| DbError::NoSuchEntity(format!("policy {name} of {} {owner}", target.noun())))
+    }
+
+
+    pub async fn delete_policy(&self, target: PolicyTarget, owner: &str, name: &str) -> Result<()> {
+        let (id, _) = self.entity(target, owner).await?;
+        let (_, policies, column) = target.tables();
+
+        let result = sqlx::query(&format!("DELETE FROM {policies} WHERE {column} = ? AND name = ?"))
+            .bind(id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NoSuchEntity(format!("policy {name} of {} {owner}", target.noun())));
+        }
+
+        Ok(())
+    }
+
+
+    /// Every policy document that applies to a user: its own and those of its groups
+    pub async fn user_policy_documents(&self, user: &str) -> Result<Vec<String>> {
+        let documents = sqlx::query_scalar(
+            r#"
+            SELECT p.document FROM user_policies p JOIN users u ON u.id = p.user_id WHERE u.name = ?1
+            UNION ALL
+            SELECT p.document FROM group_policies p
+            JOIN group_members m ON m.group_id = p.group_id
+            JOIN users u ON u.id = m.user_id
+            WHERE u.name = ?1
+            "#
+        )
+        .bind(user)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(documents)
+    }
+
+
+    /// Store an access key of a user, its secret sealed with the master key
+    pub async fn create_access_key(&self, user: &str, access_key_id: &str, sealed_secret: &[u8]) -> Result<()> {
+        let (user_id, _) = self.entity(PolicyTarget::User, user).await?;
+
+        sqlx::query("INSERT INTO access_keys (access_key_id, user_id, secret, created_at) VALUES (?, ?, ?, ?)")
+            .bind(access_key_id)
+            .bind(user_id)
+            .bind(sealed_secret)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| already_exists(e, || format!("access key {access_key_id}")))?;
+
+        Ok(())
+    }
+
+
+    pub async fn delete_access_key(&self, access_key_id: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM access_keys WHERE access_key_id = ?")
+            .bind(access_key_id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NoSuchEntity(format!("access key {access_key_id}")));
+        }
+
+        Ok(())
+    }
+
+
+    /// The name and canonical id of the user an access key belongs to, and its
+    /// sealed secret
+    pub async fn get_access_key(&self, access_key_id: &str) -> Result<Option<(String, String, Vec<u8>)>> {
+        let row = sqlx::query(
+            "SELECT u.name, u.canonical_id, k.secret FROM access_keys k JOIN users u ON u.id = k.user_id \
+             WHERE k.access_key_id = ?"
+        )
+        .bind(access_key_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| (row.get("name"), row.get("canonical_id"), row.get("secret"))))
+    }
+
+
+    /// Id and creation time of a user or group
+    async fn entity(&self, target: PolicyTarget, name: &str) -> Result<(i64, DateTime<Utc>)> {
+        let (table, _, _) = target.tables();
+
+        let row = sqlx::query(&format!("SELECT id, created_at FROM {table} WHERE name = ?"))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| DbError::NoSuchEntity(format!("{} {name}", target.noun())))?;
+
+        Ok((row.get("id"), row.get("created_at")))
+    }
+
+
+    async fn policy_names(&self, target: PolicyTarget, id: i64) -> Result<Vec<String>> {
+        let (_, policies, column) = target.tables();
+
+        let names = sqlx::query_scalar(&format!("SELECT name FROM {policies} WHERE {column} = ? ORDER BY name"))
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(names)
+    }
+}
+
+
+/// `EntityAlreadyExists` for an insert that hit a unique constraint
+fn already_exists(err: sqlx::Error, entity: impl FnOnce() -> String) -> DbError {
+    match &err {
+        sqlx::Error::Database(e) if e.is_unique_violation() => DbError::EntityAlreadyExists(entity()),
+        _ => DbError::SqlxError(err),
+    }
+}
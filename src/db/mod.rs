@@ -1,45 +1,86 @@
-use std::collections::HashMap;
+mod iam;
+
+use std::{collections::HashMap, str::FromStr, time::Duration};
 
 use chrono::{DateTime, Utc};
-use sqlx::{SqlitePool, sqlite::{SqlitePoolOptions, SqliteRow}, Row,migrate};
-use thiserror::Error;
+use sqlx::{
+    Row, Sqlite, SqlitePool, Transaction, migrate,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteRow},
+};
 
+pub use iam::PolicyTarget;
 
-#[derive(Error, Debug)]
-pub enum DbError {
-    #[error("Database error: {0}")]
-    SqlxError(#[from] sqlx::Error),
+use crate::{
+    error::{DbError, DbResult as Result},
+    storage::{BucketVersioning, NULL_VERSION_ID, ObjectMetadata, OwnerUsage, Quota, ServerSideEncryption},
+};
 
-    #[error("Bucket not found: {0}")]
-    BucketNotFound(String),
 
-    #[error("Object not found: {0}")]
-    ObjectNotFound(String),
-}
+/// How long a statement waits for another connection to release its lock
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Columns of `buckets` `row_to_bucket_record` reads
+const BUCKET_COLUMNS: &str = "id, name, owner, versioning, lifecycle, policy, acl, encryption, object_count, total_size, max_objects, max_size, \
+    created_at, updated_at";
 
 
-pub type Result<T> = std::result::Result<T, DbError>;
+/// Columns of `objects` in the order `row_to_object_record` reads them
+const OBJECT_COLUMNS: &str = "id, bucket_id, key, version_id, is_latest, is_delete_marker, size, content_type, etag, \
+    md5_checksum, sha256_checksum, sha1_checksum, crc32_checksum, crc32c_checksum, storage_path, acl, encryption, data_key, customer_key_hash, created_at, modified_at";
 
+
+#[allow(dead_code)] // rows are read whole, not every column has a user yet
 #[derive(Debug, Clone)]
 pub struct BucketRecord {
     pub id: i64,
     pub name: String,
+    /// Canonical id of who created the bucket
+    pub owner: Option<String>,
+    pub versioning: BucketVersioning,
+    /// Lifecycle configuration as JSON
+    pub lifecycle: Option<String>,
+    /// Bucket policy document
+    pub policy: Option<String>,
+    /// Access control list as JSON, `None` for the default of the owner having full control
+    pub acl: Option<String>,
+    /// Default encryption of objects written to the bucket
+    pub encryption: Option<ServerSideEncryption>,
+    /// Object versions stored in the bucket and their combined size, delete markers aside
+    pub object_count: i64,
+    pub total_size: i64,
+    pub quota: Quota,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
 
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct ObjectRecord {
     pub id: i64,
     pub bucket_id: i64,
     pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub is_delete_marker: bool,
     pub size: i64,
     pub content_type: String,
+    pub etag: String,
     pub md5_checksum: String,
     pub sha256_checksum: String,
+    pub sha1_checksum: String,
+    pub crc32_checksum: String,
+    pub crc32c_checksum: String,
     pub storage_path: String,
+    /// Access control list as JSON, `None` for the default of the bucket owner having full control
+    pub acl: Option<String>,
+    pub encryption: Option<ServerSideEncryption>,
+    /// Key the body is encrypted with, sealed with the master key
+    pub data_key: Option<Vec<u8>>,
+    /// Salted hash of the key an SSE-C body is encrypted with
+    pub customer_key_hash: Option<Vec<u8>>,
     pub created_at: DateTime<Utc>,
     pub modified_at: DateTime<Utc>,
 }
@@ -53,10 +94,28 @@ pub struct Database {
 
 impl Database {
     pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(database_url)
-            .await?;
+        let mut options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .busy_timeout(BUSY_TIMEOUT);
+        let in_memory = database_url.contains(":memory:");
+
+        // readers don't block the writer and the writer doesn't block readers
+        if !in_memory {
+            options = options.journal_mode(SqliteJournalMode::Wal);
+        }
+
+        if !in_memory && let Some(parent) = options.get_filename().parent() {
+            std::fs::create_dir_all(parent).map_err(sqlx::Error::Io)?;
+        }
+
+        let mut pool_options = SqlitePoolOptions::new().max_connections(5);
+
+        // an in-memory database is gone once its last connection closes
+        if in_memory {
+            pool_options = pool_options.min_connections(1).idle_timeout(None).max_lifetime(None);
+        }
+
+        let pool = pool_options.connect_with(options).await?;
 
         // Run migrations
         migrate!("./migrations").run(&pool).await?;
@@ -66,21 +125,51 @@ impl Database {
 
 
 
-    pub async fn create_bucket(&self, name: &str) -> Result<BucketRecord> {
+    /// Start a transaction that writes. It takes the write lock up front, so
+    /// a concurrent writer waits out `BUSY_TIMEOUT` for it instead of failing
+    /// with "database is locked" when a deferred transaction tries to upgrade
+    /// its read lock.
+    async fn begin_write(&self) -> Result<Transaction<'static, Sqlite>> {
+        Ok(self.pool.begin_with("BEGIN IMMEDIATE").await?)
+    }
+
+
+    /// Close all connections, waiting for those in use to be returned
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+
+    pub async fn create_bucket(&self, name: &str, owner: Option<&str>, acl: Option<&str>) -> Result<BucketRecord> {
         let now = Utc::now();
 
         let result = sqlx::query(
-            "INSERT INTO buckets (name, created_at, updated_at) VALUES (?, ?, ?)"
+            "INSERT INTO buckets (name, owner, acl, created_at, updated_at) VALUES (?, ?, ?, ?, ?)"
         )
         .bind(name)
+        .bind(owner)
+        .bind(acl)
         .bind(now)
         .bind(now)
         .execute(&self.pool)
-        .await?;
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => DbError::BucketAlreadyExists(name.to_string()),
+            _ => DbError::SqlxError(e),
+        })?;
 
         Ok(BucketRecord {
             id: result.last_insert_rowid(),
             name: name.to_string(),
+            owner: owner.map(str::to_string),
+            versioning: BucketVersioning::Unversioned,
+            lifecycle: None,
+            policy: None,
+            acl: acl.map(str::to_string),
+            encryption: None,
+            object_count: 0,
+            total_size: 0,
+            quota: Quota::default(),
             created_at: now,
             updated_at: now,
         })
@@ -88,221 +177,859 @@ impl Database {
 
 
     pub async fn get_bucket(&self, name: &str) -> Result<BucketRecord> {
-           let row = sqlx::query(
-               "SELECT id, name, created_at, updated_at FROM buckets WHERE name = ?"
-           )
-           .bind(name)
-           .fetch_optional(&self.pool)
-           .await?
-           .ok_or_else(|| DbError::BucketNotFound(name.to_string()))?;
-
-           Ok(BucketRecord {
-               id: row.get("id"),
-               name: row.get("name"),
-               created_at: row.get("created_at"),
-               updated_at: row.get("updated_at"),
-           })
-       }
-
-       pub async fn list_buckets(&self) -> Result<Vec<BucketRecord>> {
-           let rows = sqlx::query(
-               "SELECT id, name, created_at, updated_at FROM buckets ORDER BY name"
-           )
-           .fetch_all(&self.pool)
-           .await?;
-
-           Ok(rows.into_iter().map(|row| BucketRecord {
-               id: row.get("id"),
-               name: row.get("name"),
-               created_at: row.get("created_at"),
-               updated_at: row.get("updated_at"),
-           }).collect())
-       }
-
-
-       pub async fn delete_bucket(&self, name: &str) -> Result<()> {
-              let result = sqlx::query("DELETE FROM buckets WHERE name = ?")
-                  .bind(name)
-                  .execute(&self.pool)
-                  .await?;
-
-              if result.rows_affected() == 0 {
-                  return Err(DbError::BucketNotFound(name.to_string()));
-              }
-
-              Ok(())
-          }
-
-
-          pub async fn create_object(
-                  &self,
-                  bucket_id: i64,
-                  key: &str,
-                  size: i64,
-                  content_type: &str,
-                  md5_checksum: &str,
-                  sha256_checksum: &str,
-                  storage_path: &str,
-                  custom_metadata: Option<HashMap<String, String>>,
-              ) -> Result<ObjectRecord> {
-                  let now = Utc::now();
-
-                  let mut tx = self.pool.begin().await?;
-
-                  // Insert or update object
-                  let result = sqlx::query(
-                      r#"
-                      INSERT INTO objects (bucket_id, key, size, content_type, md5_checksum,
-                                         sha256_checksum, storage_path, created_at, modified_at)
-                      VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                      ON CONFLICT(bucket_id, key) DO UPDATE SET
-                          size = excluded.size,
-                          content_type = excluded.content_type,
-                          md5_checksum = excluded.md5_checksum,
-                          sha256_checksum = excluded.sha256_checksum,
-                          storage_path = excluded.storage_path,
-                          modified_at = excluded.modified_at
-                      "#
-                  )
-                  .bind(bucket_id)
-                  .bind(key)
-                  .bind(size)
-                  .bind(content_type)
-                  .bind(md5_checksum)
-                  .bind(sha256_checksum)
-                  .bind(storage_path)
-                  .bind(now)
-                  .bind(now)
-                  .execute(&mut *tx)
-                  .await?;
-
-                  let object_id = result.last_insert_rowid();
-
-                  // Insert custom metadata if provided
-                  if let Some(metadata) = custom_metadata {
-                      // Delete existing metadata
-                      sqlx::query("DELETE FROM object_metadata WHERE object_id = ?")
-                          .bind(object_id)
-                          .execute(&mut *tx)
-                          .await?;
-
-                      // Insert new metadata
-                      for (k, v) in metadata {
-                          sqlx::query("INSERT INTO object_metadata (object_id, key, value) VALUES (?, ?, ?)")
-                              .bind(object_id)
-                              .bind(&k)
-                              .bind(&v)
-                              .execute(&mut *tx)
-                              .await?;
-                      }
-                  }
-
-                  tx.commit().await?;
-
-                  Ok(ObjectRecord {
-                      id: object_id,
-                      bucket_id,
-                      key: key.to_string(),
-                      size,
-                      content_type: content_type.to_string(),
-                      md5_checksum: md5_checksum.to_string(),
-                      sha256_checksum: sha256_checksum.to_string(),
-                      storage_path: storage_path.to_string(),
-                      created_at: now,
-                      modified_at: now,
-                  })
-              }
-
-
-              pub async fn get_object(&self, bucket_id: i64, key: &str) -> Result<ObjectRecord> {
-                   let row = sqlx::query(
-                       r#"
-                       SELECT id, bucket_id, key, size, content_type, md5_checksum,
-                              sha256_checksum, storage_path, created_at, modified_at
-                       FROM objects WHERE bucket_id = ? AND key = ?
-                       "#
-                   )
-                   .bind(bucket_id)
-                   .bind(key)
-                   .fetch_optional(&self.pool)
-                   .await?
-                   .ok_or_else(|| DbError::ObjectNotFound(key.to_string()))?;
-
-                   Ok(self.row_to_object_record(row))
-               }
-
-
-               pub async fn get_object_metadata(&self, object_id: i64) -> Result<HashMap<String, String>> {
-                      let rows = sqlx::query("SELECT key, value FROM object_metadata WHERE object_id = ?")
-                          .bind(object_id)
-                          .fetch_all(&self.pool)
-                          .await?;
-
-                      Ok(rows.into_iter().map(|row| {
-                          (row.get::<String, _>("key"), row.get::<String, _>("value"))
-                      }).collect())
-                  }
-
-
-
-                  pub async fn list_objects(&self, bucket_id: i64, prefix: Option<&str>) -> Result<Vec<ObjectRecord>> {
-                         let rows = if let Some(pfx) = prefix {
-                             sqlx::query(
-                                 r#"
-                                 SELECT id, bucket_id, key, size, content_type, md5_checksum,
-                                        sha256_checksum, storage_path, created_at, modified_at
-                                 FROM objects WHERE bucket_id = ? AND key LIKE ? ORDER BY key
-                                 "#
-                             )
-                             .bind(bucket_id)
-                             .bind(format!("{}%", pfx))
-                             .fetch_all(&self.pool)
-                             .await?
-                         } else {
-                             sqlx::query(
-                                 r#"
-                                 SELECT id, bucket_id, key, size, content_type, md5_checksum,
-                                        sha256_checksum, storage_path, created_at, modified_at
-                                 FROM objects WHERE bucket_id = ? ORDER BY key
-                                 "#
-                             )
-                             .bind(bucket_id)
-                             .fetch_all(&self.pool)
-                             .await?
-                         };
-
-                         Ok(rows.into_iter().map(|row| self.row_to_object_record(row)).collect())
-                     }
-
-
-                     pub async fn delete_object(&self, bucket_id: i64, key: &str) -> Result<()> {
-                          let result = sqlx::query("DELETE FROM objects WHERE bucket_id = ? AND key = ?")
-                              .bind(bucket_id)
-                              .bind(key)
-                              .execute(&self.pool)
-                              .await?;
-
-                          if result.rows_affected() == 0 {
-                              return Err(DbError::ObjectNotFound(key.to_string()));
-                          }
-
-                          Ok(())
-                      }
-
-
-                      fn row_to_object_record(&self, row: SqliteRow) -> ObjectRecord {
-                          ObjectRecord {
-                              id: row.get("id"),
-                              bucket_id: row.get("bucket_id"),
-                              key: row.get("key"),
-                              size: row.get("size"),
-                              content_type: row.get("content_type"),
-                              md5_checksum: row.get("md5_checksum"),
-                              sha256_checksum: row.get("sha256_checksum"),
-                              storage_path: row.get("storage_path"),
-                              created_at: row.get("created_at"),
-                              modified_at: row.get("modified_at"),
-                          }
-                      }
+        let row = sqlx::query(&format!("SELECT {BUCKET_COLUMNS} FROM buckets WHERE name = ?"))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::BucketNotFound(name.to_string()))?;
+
+        Ok(self.row_to_bucket_record(row))
+    }
+
+    pub async fn list_buckets(&self) -> Result<Vec<BucketRecord>> {
+        let rows = sqlx::query(&format!("SELECT {BUCKET_COLUMNS} FROM buckets ORDER BY name"))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| self.row_to_bucket_record(row)).collect())
+    }
+
+
+    pub async fn set_bucket_versioning(&self, bucket_id: i64, versioning: BucketVersioning) -> Result<()> {
+        sqlx::query("UPDATE buckets SET versioning = ?, updated_at = ? WHERE id = ?")
+            .bind(versioning.as_str())
+            .bind(Utc::now())
+            .bind(bucket_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// Set or, with `None`, remove the lifecycle configuration of a bucket
+    pub async fn set_bucket_lifecycle(&self, bucket_id: i64, lifecycle: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE buckets SET lifecycle = ?, updated_at = ? WHERE id = ?")
+            .bind(lifecycle)
+            .bind(Utc::now())
+            .bind(bucket_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// Set or, with `None`, remove the policy of a bucket
+    pub async fn set_bucket_policy(&self, bucket_id: i64, policy: Option<&str>) -> Result<()> {
+        sqlx::query("UPDATE buckets SET policy = ?, updated_at = ? WHERE id = ?")
+            .bind(policy)
+            .bind(Utc::now())
+            .bind(bucket_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    pub async fn set_bucket_acl(&self, bucket_id: i64, acl: &str) -> Result<()> {
+        sqlx::query("UPDATE buckets SET acl = ?, updated_at = ? WHERE id = ?")
+            .bind(acl)
+            .bind(Utc::now())
+            .bind(bucket_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// Set or, with `None`, remove the default encryption of a bucket
+    pub async fn set_bucket_encryption(&self, bucket_id: i64, encryption: Option<ServerSideEncryption>) -> Result<()> {
+        sqlx::query("UPDATE buckets SET encryption = ?, updated_at = ? WHERE id = ?")
+            .bind(encryption.map(|e| e.as_str()))
+            .bind(Utc::now())
+            .bind(bucket_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    pub async fn set_bucket_quota(&self, bucket_id: i64, quota: &Quota) -> Result<()> {
+        sqlx::query("UPDATE buckets SET max_objects = ?, max_size = ?, updated_at = ? WHERE id = ?")
+            .bind(quota.max_objects.map(|n| n as i64))
+            .bind(quota.max_size.map(|n| n as i64))
+            .bind(Utc::now())
+            .bind(bucket_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    /// What the buckets of `owner` hold together and the owner's quota. An
+    /// owner without buckets or a quota holds nothing and has no limit.
+    pub async fn owner_usage(&self, owner: &str) -> Result<OwnerUsage> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                (SELECT COALESCE(SUM(object_count), 0) FROM buckets WHERE owner = ?1) AS object_count,
+                (SELECT COALESCE(SUM(total_size), 0) FROM buckets WHERE owner = ?1) AS total_size,
+                (SELECT max_objects FROM owner_quotas WHERE owner = ?1) AS max_objects,
+                (SELECT max_size FROM owner_quotas WHERE owner = ?1) AS max_size
+            "#
+        )
+        .bind(owner)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(OwnerUsage {
+            owner: owner.to_string(),
+            object_count: row.get::<i64, _>("object_count") as usize,
+            total_size: row.get::<i64, _>("total_size") as u64,
+            quota: row_to_quota(&row),
+        })
+    }
+
+
+    /// Fail with `QuotaExceeded` if adding `objects` objects and `bytes` bytes
+    /// to the bucket would take it or its owner over quota. A quick check
+    /// before a body is read, `create_object` has the final say.
+    pub async fn check_quota_room(&self, bucket_id: i64, objects: i64, bytes: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let before = bucket_usage(&mut tx, bucket_id).await?;
+
+        check_quota(&mut tx, bucket_id, before, (objects, bytes)).await
+    }
+
+
+    /// Set the quota of an owner, removing it when it has no limits
+    pub async fn set_owner_quota(&self, owner: &str, quota: &Quota) -> Result<()> {
+        if *quota == Quota::default() {
+            sqlx::query("DELETE FROM owner_quotas WHERE owner = ?")
+                .bind(owner)
+                .execute(&self.pool)
+                .await?;
+
+            return Ok(());
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO owner_quotas (owner, max_objects, max_size) VALUES (?, ?, ?)
+            ON CONFLICT(owner) DO UPDATE SET max_objects = excluded.max_objects, max_size = excluded.max_size
+            "#
+        )
+        .bind(owner)
+        .bind(quota.max_objects.map(|n| n as i64))
+        .bind(quota.max_size.map(|n| n as i64))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+
+    /// Delete a bucket, its objects go with it. Returns the storage paths of
+    /// blobs no object references anymore.
+    pub async fn delete_bucket(&self, name: &str) -> Result<Vec<String>> {
+        let mut tx = self.begin_write().await?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT sha256_checksum, storage_path FROM objects
+            WHERE bucket_id = (SELECT id FROM buckets WHERE name = ?) AND is_delete_marker = 0
+            "#
+        )
+        .bind(name)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut orphaned = Vec::new();
+
+        for row in rows {
+            orphaned.extend(release_blob(&mut tx, row.get("sha256_checksum"), row.get("storage_path")).await?);
+        }
+
+        let result = sqlx::query("DELETE FROM buckets WHERE name = ?")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::BucketNotFound(name.to_string()));
+        }
+
+        tx.commit().await?;
+
+        Ok(orphaned)
+    }
+
+
+    pub async fn has_objects(&self, bucket_id: i64) -> Result<bool> {
+        let row = sqlx::query("SELECT EXISTS (SELECT 1 FROM objects WHERE bucket_id = ?) AS found")
+            .bind(bucket_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(row.get("found"))
+    }
+
+
+    /// Add a version of an object along with its custom metadata and make it
+    /// the latest. A `null` version replaces the one the key had.
+    ///
+    /// The version takes a reference on the blob at `storage_path`, unless it
+    /// is encrypted: encrypted bodies are never shared and belong to their
+    /// version alone. `data_key` is its key sealed with the master key,
+    /// `customer_key_hash` the salted hash of an SSE-C key. Returns the paths of blobs a replaced
+    /// version held the last reference to.
+    ///
+    /// Fails with `QuotaExceeded`, changing nothing, if the bucket or its owner
+    /// would end up holding more than their quota allows.
+    pub async fn create_object(
+        &self,
+        bucket_id: i64,
+        metadata: &ObjectMetadata,
+        storage_path: &str,
+        acl: Option<&str>,
+        data_key: Option<&[u8]>,
+        customer_key_hash: Option<&[u8]>,
+    ) -> Result<(ObjectRecord, Vec<String>)> {
+        let mut tx = self.begin_write().await?;
+
+        let before = bucket_usage(&mut tx, bucket_id).await?;
+
+        // Take the new reference before the replaced version drops its own,
+        // so overwriting an object with the same content keeps the blob.
+        if metadata.encryption.is_none() {
+            sqlx::query(
+                r#"
+                INSERT INTO blobs (sha256, storage_path, size, ref_count, created_at)
+                VALUES (?, ?, ?, 1, ?)
+                ON CONFLICT(sha256) DO UPDATE SET ref_count = ref_count + 1
+                "#
+            )
+            .bind(&metadata.checksums.sha256)
+            .bind(storage_path)
+            .bind(metadata.size as i64)
+            .bind(metadata.modified_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let orphaned = make_room_for_latest(&mut tx, bucket_id, &metadata.key, &metadata.version_id).await?;
+
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO objects (bucket_id, key, version_id, is_latest, is_delete_marker, size, content_type, etag,
+                                 md5_checksum, sha256_checksum, sha1_checksum, crc32_checksum, crc32c_checksum,
+                                 storage_path, acl, encryption, data_key, customer_key_hash, created_at, modified_at)
+            VALUES (?, ?, ?, 1, 0, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING {OBJECT_COLUMNS}
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(&metadata.key)
+        .bind(&metadata.version_id)
+        .bind(metadata.size as i64)
+        .bind(&metadata.content_type)
+        .bind(&metadata.etag)
+        .bind(&metadata.checksums.md5)
+        .bind(&metadata.checksums.sha256)
+        .bind(&metadata.checksums.sha1)
+        .bind(&metadata.checksums.crc32)
+        .bind(&metadata.checksums.crc32c)
+        .bind(storage_path)
+        .bind(acl)
+        .bind(metadata.encryption.map(|e| e.as_str()))
+        .bind(data_key)
+        .bind(customer_key_hash)
+        .bind(metadata.created_at)
+        .bind(metadata.modified_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let record = self.row_to_object_record(row);
+
+        update_bucket_stats(&mut tx, bucket_id, 1, record.size).await?;
+        check_quota(&mut tx, bucket_id, before, (0, 0)).await?;
+
+        for (k, v) in &metadata.custom_metadata {
+            sqlx::query("INSERT INTO object_metadata (object_id, key, value) VALUES (?, ?, ?)")
+                .bind(record.id)
+                .bind(k)
+                .bind(v)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (k, v) in &metadata.tags {
+            sqlx::query("INSERT INTO object_tags (object_id, key, value) VALUES (?, ?, ?)")
+                .bind(record.id)
+                .bind(k)
+                .bind(v)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok((record, orphaned.into_iter().collect()))
+    }
+
+
+    /// Add a delete marker as the latest version of `key`. It has no body and
+    /// holds no blob, but like any version replaces an earlier `null` one.
+    pub async fn create_delete_marker(
+        &self,
+        bucket_id: i64,
+        key: &str,
+        version_id: &str,
+    ) -> Result<(ObjectRecord, Vec<String>)> {
+        let mut tx = self.begin_write().await?;
+
+        let orphaned = make_room_for_latest(&mut tx, bucket_id, key, version_id).await?;
+        let now = Utc::now();
+
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO objects (bucket_id, key, version_id, is_latest, is_delete_marker, size, content_type, etag,
+                                 md5_checksum, sha256_checksum, storage_path, created_at, modified_at)
+            VALUES (?, ?, ?, 1, 1, 0, '', '', '', '', '', ?, ?)
+            RETURNING {OBJECT_COLUMNS}
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok((self.row_to_object_record(row), orphaned.into_iter().collect()))
+    }
+
+
+    /// A version of an object, the latest one when `version_id` is `None`.
+    /// That may be a delete marker.
+    pub async fn get_object(&self, bucket_id: i64, key: &str, version_id: Option<&str>) -> Result<ObjectRecord> {
+        let row = sqlx::query(&format!(
+            r#"
+            SELECT {OBJECT_COLUMNS} FROM objects
+            WHERE bucket_id = ? AND key = ?
+              AND CASE WHEN ? IS NULL THEN is_latest = 1 ELSE version_id = ? END
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .bind(version_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| DbError::ObjectNotFound(key.to_string()))?;
+
+        Ok(self.row_to_object_record(row))
+    }
+
+
+    /// Replace the access control list of an object version
+    pub async fn set_object_acl(&self, object_id: i64, acl: &str) -> Result<()> {
+        sqlx::query("UPDATE objects SET acl = ? WHERE id = ?")
+            .bind(acl)
+            .bind(object_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+
+    pub async fn get_object_metadata(&self, object_id: i64) -> Result<HashMap<String, String>> {
+        let rows = sqlx::query("SELECT key, value FROM object_metadata WHERE object_id = ?")
+            .bind(object_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| {
+            (row.get::<String, _>("key"), row.get::<String, _>("value"))
+        }).collect())
+    }
+
+
+
+    pub async fn get_object_tags(&self, object_id: i64) -> Result<HashMap<String, String>> {
+        let rows = sqlx::query("SELECT key, value FROM object_tags WHERE object_id = ?")
+            .bind(object_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| {
+            (row.get::<String, _>("key"), row.get::<String, _>("value"))
+        }).collect())
+    }
+
+
+    /// Up to `limit` current versions under `prefix` last written before
+    /// `modified_before`, oldest first and after the `(modified_at, id)` of
+    /// the last one of the previous batch. Delete markers are left out.
+    pub async fn expired_objects(
+        &self,
+        bucket_id: i64,
+        prefix: &str,
+        modified_before: DateTime<Utc>,
+        after: Option<(DateTime<Utc>, i64)>,
+        limit: usize,
+    ) -> Result<Vec<ObjectRecord>> {
+        let (after_modified, after_id) = after.map_or((None, 0), |(m, id)| (Some(m), id));
+        let end = successor(prefix);
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {OBJECT_COLUMNS} FROM objects
+            WHERE bucket_id = ? AND modified_at < ? AND is_latest = 1 AND is_delete_marker = 0
+              AND key >= ? AND key < coalesce(?, x'') AND substr(key, 1, ?) = ?
+              AND (? IS NULL OR (modified_at, id) > (?, ?))
+            ORDER BY modified_at, id LIMIT ?
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(modified_before)
+        .bind(prefix)
+        .bind(&end)
+        .bind(prefix.chars().count() as i64)
+        .bind(prefix)
+        .bind(after_modified)
+        .bind(after_modified)
+        .bind(after_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| self.row_to_object_record(row)).collect())
+    }
+
+
+    /// Up to `limit` versions under `prefix`, delete markers included, that
+    /// stopped being current before `noncurrent_before` and have at least
+    /// `newer_noncurrent` noncurrent versions newer than them. A version stops
+    /// being current when the next one is written. Ordered by key and id,
+    /// after the `(key, id)` of the last one of the previous batch.
+    pub async fn noncurrent_versions(
+        &self,
+        bucket_id: i64,
+        prefix: &str,
+        noncurrent_before: DateTime<Utc>,
+        newer_noncurrent: u32,
+        after: (&str, i64),
+        limit: usize,
+    ) -> Result<Vec<ObjectRecord>> {
+        let end = successor(prefix);
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {OBJECT_COLUMNS} FROM objects o
+            WHERE bucket_id = ? AND is_latest = 0 AND key >= ? AND key < coalesce(?, x'') AND substr(key, 1, ?) = ?
+              AND (key, id) > (?, ?)
+              AND (SELECT n.modified_at FROM objects n
+                   WHERE n.bucket_id = o.bucket_id AND n.key = o.key AND n.id > o.id
+                   ORDER BY n.id LIMIT 1) < ?
+              AND (SELECT COUNT(*) FROM objects n
+                   WHERE n.bucket_id = o.bucket_id AND n.key = o.key AND n.id > o.id AND n.is_latest = 0) >= ?
+            ORDER BY key, id LIMIT ?
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(prefix)
+        .bind(&end)
+        .bind(prefix.chars().count() as i64)
+        .bind(prefix)
+        .bind(after.0)
+        .bind(after.1)
+        .bind(noncurrent_before)
+        .bind(newer_noncurrent as i64)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| self.row_to_object_record(row)).collect())
+    }
+
+
+    /// Up to `limit` delete markers under `prefix` that are the only version
+    /// left of their key, after the `(key, id)` of the last one of the
+    /// previous batch
+    pub async fn expired_delete_markers(
+        &self,
+        bucket_id: i64,
+        prefix: &str,
+        after: (&str, i64),
+        limit: usize,
+    ) -> Result<Vec<ObjectRecord>> {
+        let end = successor(prefix);
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {OBJECT_COLUMNS} FROM objects o
+            WHERE bucket_id = ? AND is_latest = 1 AND is_delete_marker = 1
+              AND key >= ? AND key < coalesce(?, x'') AND substr(key, 1, ?) = ? AND (key, id) > (?, ?)
+              AND NOT EXISTS (SELECT 1 FROM objects n WHERE n.bucket_id = o.bucket_id AND n.key = o.key AND n.id != o.id)
+            ORDER BY key, id LIMIT ?
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(prefix)
+        .bind(&end)
+        .bind(prefix.chars().count() as i64)
+        .bind(prefix)
+        .bind(after.0)
+        .bind(after.1)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| self.row_to_object_record(row)).collect())
+    }
+
+
+    /// Up to `limit` objects under `prefix` ordered by key, starting at the
+    /// first key >= `from`. Only the latest version of each key is listed,
+    /// unless that is a delete marker. Seeks on the (bucket_id, is_latest, key)
+    /// index and stops at the end of the prefix so paging deep into big
+    /// buckets stays cheap.
+    pub async fn list_objects(&self, bucket_id: i64, prefix: &str, from: &str, limit: usize) -> Result<Vec<ObjectRecord>> {
+        let end = successor(prefix);
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {OBJECT_COLUMNS} FROM objects
+            WHERE bucket_id = ? AND is_latest = 1 AND key >= ? AND key < coalesce(?, x'') AND substr(key, 1, ?) = ?
+              AND is_delete_marker = 0
+            ORDER BY key LIMIT ?
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(from)
+        .bind(&end)
+        .bind(prefix.chars().count() as i64)
+        .bind(prefix)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| self.row_to_object_record(row)).collect())
+    }
+
+
+    /// Up to `limit` versions under `prefix` ordered by key and then newest
+    /// first, starting with the versions of `from_key` older than the row
+    /// `before_id`. Delete markers are included.
+    pub async fn list_object_versions(
+        &self,
+        bucket_id: i64,
+        prefix: &str,
+        from_key: &str,
+        before_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ObjectRecord>> {
+        let end = successor(prefix);
+
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {OBJECT_COLUMNS} FROM objects
+            WHERE bucket_id = ? AND key >= ? AND key < coalesce(?, x'') AND (key > ? OR id < ?) AND substr(key, 1, ?) = ?
+            ORDER BY key, id DESC LIMIT ?
+            "#
+        ))
+        .bind(bucket_id)
+        .bind(from_key)
+        .bind(&end)
+        .bind(from_key)
+        .bind(before_id)
+        .bind(prefix.chars().count() as i64)
+        .bind(prefix)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| self.row_to_object_record(row)).collect())
+    }
+
+
+    /// Permanently delete one version of an object. If it was the latest the
+    /// next newest version takes its place. Returns the deleted version and
+    /// the storage path of its blob if this was the last reference to it.
+    pub async fn delete_object(&self, bucket_id: i64, key: &str, version_id: &str) -> Result<(ObjectRecord, Option<String>)> {
+        let mut tx = self.begin_write().await?;
+
+        let row = sqlx::query(&format!(
+            "DELETE FROM objects WHERE bucket_id = ? AND key = ? AND version_id = ? RETURNING {OBJECT_COLUMNS}"
+        ))
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| DbError::ObjectNotFound(key.to_string()))?;
+
+        let record = self.row_to_object_record(row);
+
+        let orphaned = if record.is_delete_marker {
+            None
+        } else {
+            update_bucket_stats(&mut tx, bucket_id, -1, -record.size).await?;
+            release_blob(&mut tx, record.sha256_checksum.clone(), record.storage_path.clone()).await?
+        };
+
+        if record.is_latest {
+            sqlx::query(
+                r#"
+                UPDATE objects SET is_latest = 1
+                WHERE id = (SELECT id FROM objects WHERE bucket_id = ? AND key = ? ORDER BY id DESC LIMIT 1)
+                "#
+            )
+            .bind(bucket_id)
+            .bind(key)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok((record, orphaned))
+    }
+
+
+    fn row_to_bucket_record(&self, row: SqliteRow) -> BucketRecord {
+        BucketRecord {
+            id: row.get("id"),
+            name: row.get("name"),
+            owner: row.get("owner"),
+            versioning: BucketVersioning::parse(row.get("versioning")).unwrap_or_default(),
+            lifecycle: row.get("lifecycle"),
+            policy: row.get("policy"),
+            acl: row.get("acl"),
+            encryption: row.get::<Option<&str>, _>("encryption").and_then(ServerSideEncryption::parse),
+            object_count: row.get("object_count"),
+            total_size: row.get("total_size"),
+            quota: row_to_quota(&row),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }
+    }
+
+
+    fn row_to_object_record(&self, row: SqliteRow) -> ObjectRecord {
+        ObjectRecord {
+            id: row.get("id"),
+            bucket_id: row.get("bucket_id"),
+            key: row.get("key"),
+            version_id: row.get("version_id"),
+            is_latest: row.get("is_latest"),
+            is_delete_marker: row.get("is_delete_marker"),
+            size: row.get("size"),
+            content_type: row.get("content_type"),
+            etag: row.get("etag"),
+            md5_checksum: row.get("md5_checksum"),
+            sha256_checksum: row.get("sha256_checksum"),
+            sha1_checksum: row.get("sha1_checksum"),
+            crc32_checksum: row.get("crc32_checksum"),
+            crc32c_checksum: row.get("crc32c_checksum"),
+            storage_path: row.get("storage_path"),
+            acl: row.get("acl"),
+            encryption: row.get::<Option<&str>, _>("encryption").and_then(ServerSideEncryption::parse),
+            data_key: row.get("data_key"),
+            customer_key_hash: row.get("customer_key_hash"),
+            created_at: row.get("created_at"),
+            modified_at: row.get("modified_at"),
+        }
+    }
+
+}
+
+
+/// Drop one reference on a blob, returning its storage path when that was the
+/// last one. Objects stored before blobs existed have no blob row and own
+/// their file outright.
+async fn release_blob(tx: &mut Transaction<'_, Sqlite>, sha256: String, storage_path: String) -> Result<Option<String>> {
+    let row = sqlx::query("UPDATE blobs SET ref_count = ref_count - 1 WHERE sha256 = ? AND storage_path = ? RETURNING ref_count")
+        .bind(&sha256)
+        .bind(&storage_path)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    let Some(row) = row else {
+        return Ok(Some(storage_path));
+    };
+
+    if row.get::<i64, _>("ref_count") > 0 {
+        return Ok(None);
+    }
+
+    sqlx::query("DELETE FROM blobs WHERE sha256 = ?")
+        .bind(&sha256)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(Some(storage_path))
+}
+
+
+/// Make way for a new latest version of `key`: the current latest one stops
+/// being it, and when the new version is the `null` one the old `null`
+/// version is dropped. Returns the storage path of the blob that one held the
+/// last reference to.
+async fn make_room_for_latest(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: i64,
+    key: &str,
+    version_id: &str,
+) -> Result<Option<String>> {
+    let mut orphaned = None;
+
+    if version_id == NULL_VERSION_ID {
+        let replaced = sqlx::query(
+            r#"
+            DELETE FROM objects WHERE bucket_id = ? AND key = ? AND version_id = ?
+            RETURNING is_delete_marker, size, sha256_checksum, storage_path
+            "#
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        if let Some(row) = replaced
+            && !row.get::<bool, _>("is_delete_marker")
+        {
+            update_bucket_stats(tx, bucket_id, -1, -row.get::<i64, _>("size")).await?;
+            orphaned = release_blob(tx, row.get("sha256_checksum"), row.get("storage_path")).await?;
+        }
+    }
+
+    sqlx::query("UPDATE objects SET is_latest = 0 WHERE bucket_id = ? AND key = ? AND is_latest = 1")
+        .bind(bucket_id)
+        .bind(key)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(orphaned)
+}
+
+
+/// Adjust the running object count and total size of a bucket
+async fn update_bucket_stats(tx: &mut Transaction<'_, Sqlite>, bucket_id: i64, objects: i64, bytes: i64) -> Result<()> {
+    sqlx::query("UPDATE buckets SET object_count = object_count + ?, total_size = total_size + ? WHERE id = ?")
+        .bind(objects)
+        .bind(bytes)
+        .bind(bucket_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+
+/// Object count and total size of a bucket
+async fn bucket_usage(tx: &mut Transaction<'_, Sqlite>, bucket_id: i64) -> Result<(i64, i64)> {
+    let row = sqlx::query("SELECT object_count, total_size FROM buckets WHERE id = ?")
+        .bind(bucket_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    Ok((row.get("object_count"), row.get("total_size")))
+}
+
+
+/// Fail with `QuotaExceeded` if the bucket or its owner now hold more than
+/// their quota allows, counting `pending` objects and bytes not stored yet on
+/// top. Only what grew since `before` is checked, so a bucket already over a
+/// lowered quota can still have objects replaced by smaller ones.
+async fn check_quota(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: i64,
+    before: (i64, i64),
+    pending: (i64, i64),
+) -> Result<()> {
+    let row = sqlx::query(
+        r#"
+        SELECT b.object_count, b.total_size, b.max_objects, b.max_size,
+               q.max_objects AS owner_max_objects, q.max_size AS owner_max_size,
+               (SELECT SUM(object_count) FROM buckets WHERE owner = b.owner) AS owner_objects,
+               (SELECT SUM(total_size) FROM buckets WHERE owner = b.owner) AS owner_size
+        FROM buckets b LEFT JOIN owner_quotas q ON q.owner = b.owner
+        WHERE b.id = ?
+        "#
+    )
+    .bind(bucket_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let objects = row.get::<i64, _>("object_count") + pending.0;
+    let size = row.get::<i64, _>("total_size") + pending.1;
+    let owner_objects = row.get::<Option<i64>, _>("owner_objects").map(|n| n + pending.0);
+    let owner_size = row.get::<Option<i64>, _>("owner_size").map(|n| n + pending.1);
+    let over = |used: Option<i64>, limit: Option<i64>| matches!((used, limit), (Some(used), Some(limit)) if used > limit);
+
+    if objects > before.0 {
+        if over(Some(objects), row.get("max_objects")) {
+            return Err(DbError::QuotaExceeded("The bucket holds its maximum number of objects".to_string()));
+        }
+        if over(owner_objects, row.get("owner_max_objects")) {
+            return Err(DbError::QuotaExceeded("The buckets of the owner hold their maximum number of objects".to_string()));
+        }
+    }
+
+    if size > before.1 {
+        if over(Some(size), row.get("max_size")) {
+            return Err(DbError::QuotaExceeded("The bucket would exceed its maximum size".to_string()));
+        }
+        if over(owner_size, row.get("owner_max_size")) {
+            return Err(DbError::QuotaExceeded("The buckets of the owner would exceed their maximum size".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+
+fn row_to_quota(row: &SqliteRow) -> Quota {
+    Quota {
+        max_objects: row.get::<Option<i64>, _>("max_objects").map(|n| n as u64),
+        max_size: row.get::<Option<i64>, _>("max_size").map(|n| n as u64),
+    }
+}
+
+
+/// Smallest string greater than every string starting with `prefix`, found by
+/// bumping its last character. `None` if nothing can sort after it.
+///
+/// Queries stop at it with `key < coalesce(?, x'')`: text sorts before any
+/// blob, so `None` cuts nothing off, and unlike `? IS NULL OR key < ?` the
+/// plain comparison bounds the seek on the key index.
+pub fn successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+
+    while let Some(last) = chars.pop() {
+        let next = match last as u32 + 1 {
+            0xD800 => Some('\u{E000}'),
+            n => char::from_u32(n),
+        };
+
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
 
+    None
 }
@@ -8,20 +8,66 @@ pub enum StorageError {
     #[error("Bucket already exists: {0}")]
     BucketAlreadyExists(String),
 
+    #[error("Bucket already owned by you: {0}")]
+    BucketAlreadyOwnedByYou(String),
+
+    #[error("Bucket not empty: {0}")]
+    BucketNotEmpty(String),
+
     #[error("Object not found: {0}")]
     ObjectNotFound(String),
 
-    #[error("Object already exists: {0}")]
-    ObjectAlreadyExists(String),
+    #[error("Object version not found: {0}")]
+    VersionNotFound(String),
+
+    /// The version asked for, or the latest when none was, is a delete marker
+    #[error("Object version is a delete marker: {version_id}")]
+    DeleteMarker { version_id: String, requested: bool },
+
+    #[error("Bucket has no lifecycle configuration: {0}")]
+    NoLifecycleConfiguration(String),
+
+    #[error("Bucket has no policy: {0}")]
+    NoBucketPolicy(String),
+
+    #[error("Bucket has no encryption configuration: {0}")]
+    NoEncryptionConfiguration(String),
+
+    /// Encryption was asked for but the server has no master key to wrap data keys with
+    #[error("No master key configured")]
+    NoMasterKey,
 
-    #[error("Duplicate content detected: {0}")]
-    DuplicateContent(String),
+    /// The object is encrypted with a customer key (SSE-C) the request didn't give
+    #[error("The object is encrypted with a customer-provided key")]
+    CustomerKeyRequired,
+
+    /// A customer key was given for an object that isn't encrypted with one
+    #[error("The object is not encrypted with a customer-provided key")]
+    CustomerKeyNotApplicable,
+
+    /// The customer key given is not the one the object is encrypted with
+    #[error("The customer-provided key does not match")]
+    CustomerKeyMismatch,
+
+    /// Storing the object would take a bucket or its owner over their quota
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    /// A user, group, access key or policy of the IAM API that doesn't exist
+    #[error("No such entity: {0}")]
+    NoSuchEntity(String),
+
+    #[error("Entity already exists: {0}")]
+    EntityAlreadyExists(String),
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
-    #[error("Database error: {0}")]
-    DatabaseError(#[from] DbError),
+    #[error(transparent)]
+    DatabaseError(DbError),
+
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
 
     #[error("Invalid bucket name: {0}")]
     InvalidBucketName(String),
@@ -31,6 +77,24 @@ pub enum StorageError {
 
     #[error("Checksum mismatch: expected {expected}, got {actual}")]
     ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Requested range not satisfiable for object of {0} bytes")]
+    InvalidRange(u64),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Multipart upload not found: {0}")]
+    UploadNotFound(String),
+
+    #[error("Invalid part: {0}")]
+    InvalidPart(String),
+
+    #[error("Parts must be listed in ascending order")]
+    InvalidPartOrder,
+
+    #[error("Part {0} is smaller than the minimum allowed part size")]
+    PartTooSmall(u32),
 }
 
 
@@ -39,12 +103,110 @@ pub enum DbError {
     #[error("Database error: {0}")]
     SqlxError(#[from] sqlx::Error),
 
+    #[error("Migration error: {0}")]
+    MigrationError(#[from] sqlx::migrate::MigrateError),
+
     #[error("Bucket not found: {0}")]
     BucketNotFound(String),
 
+    #[error("Bucket already exists: {0}")]
+    BucketAlreadyExists(String),
+
     #[error("Object not found: {0}")]
     ObjectNotFound(String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("No such entity: {0}")]
+    NoSuchEntity(String),
+
+    #[error("Entity already exists: {0}")]
+    EntityAlreadyExists(String),
 }
 
+/// Lookups that miss in the database surface as the matching storage error
+impl From<DbError> for StorageError {
+    fn from(err: DbError) -> Self {
+        match err {
+            DbError::BucketNotFound(name) => StorageError::BucketNotFound(name),
+            DbError::BucketAlreadyExists(name) => StorageError::BucketAlreadyExists(name),
+            DbError::ObjectNotFound(key) => StorageError::ObjectNotFound(key),
+            DbError::QuotaExceeded(msg) => StorageError::QuotaExceeded(msg),
+            DbError::NoSuchEntity(name) => StorageError::NoSuchEntity(name),
+            DbError::EntityAlreadyExists(name) => StorageError::EntityAlreadyExists(name),
+            e => StorageError::DatabaseError(e),
+        }
+    }
+}
+
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Request is not signed")]
+    MissingAuthentication,
+
+    #[error("Malformed authorization: {0}")]
+    MalformedAuthorization(String),
+
+    #[error("Unknown access key: {0}")]
+    InvalidAccessKeyId(String),
+
+    #[error("Signature does not match")]
+    SignatureMismatch,
+
+    #[error("Request time too skewed: {0}")]
+    RequestTimeTooSkewed(String),
+
+    #[error("Presigned request has expired")]
+    RequestExpired,
+
+    #[error("Unsupported payload signing: {0}")]
+    UnsupportedPayload(String),
+
+    #[error("x-amz-content-sha256 does not match the payload")]
+    ContentSha256Mismatch,
+
+    #[error("Malformed policy: {0}")]
+    MalformedPolicy(String),
+}
+
+
+/// What can be wrong with an `aws-chunked` upload body. It is found while
+/// the body is read, so it travels inside the `io::Error` of the read.
+#[derive(Error, Debug)]
+pub enum ChunkError {
+    #[error("Chunk signature does not match")]
+    SignatureMismatch,
+
+    #[error("Malformed chunked body: {0}")]
+    Malformed(String),
+
+    /// The chunks hold more or less than `x-amz-decoded-content-length` said
+    #[error("Chunked body does not hold the declared length")]
+    IncompleteBody,
+
+    /// The trailing checksum, named by its header, doesn't match the data
+    #[error("Trailing checksum {0} does not match")]
+    ChecksumMismatch(String),
+}
+
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Cannot read config file {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+
+    #[error("Invalid config file {path}: {source}")]
+    Parse { path: String, source: toml::de::Error },
+
+    /// A setting, named by its config key or environment variable, has a bad value
+    #[error("Invalid {name}: {message}")]
+    Invalid { name: String, message: String },
+}
+
+
 pub type Result<T> = std::result::Result<T, StorageError>;
 pub type DbResult<T> = std::result::Result<T, DbError>;
+pub type AuthResult<T> = std::result::Result<T, AuthError>;
+pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
@@ -1,29 +1,134 @@
 mod error;
 mod storage;
 mod api;
-// mod db;
+mod auth;
+mod cli;
+mod config;
+mod crypto;
+mod db;
 
-use std::net::SocketAddr;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::{net::SocketAddr, pin::pin, sync::Arc, time::Duration};
+
+use config::BackendKind;
+use storage::{FilesystemBackend, MemoryBackend, StorageBackend};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
+    // Subcommands run instead of the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
+
+    // Settings from filia.toml (or the file FILIA_CONFIG names) and FILIA_* environment variables
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
     // Initialize tracing
     tracing_subscriber::registry()
+        .with(EnvFilter::new(&config.log_level))
         .with(tracing_subscriber::fmt::layer())
         .init();
 
 
+    // Open storage
+    let backend: Arc<dyn StorageBackend> = match config.storage.backend {
+        BackendKind::Filesystem => Arc::new(FilesystemBackend::new(&config.storage.data_root).await?),
+        BackendKind::Memory => Arc::new(MemoryBackend::default()),
+    };
+
+    let storage = storage::Storage::new(backend, &config.storage.database_url(), config.master_key.clone()).await?;
+
+    // Cancelled once SIGTERM or SIGINT arrives
+    let stopping = CancellationToken::new();
+
+    // Cancelled when requests still running after the grace period are to be cut off
+    let abort = CancellationToken::new();
+
+    // Expire objects by the buckets' lifecycle rules
+    let lifecycle = storage.spawn_lifecycle_worker(Duration::from_secs(config.lifecycle_interval_secs), stopping.clone());
+
+    // Access keys requests must be signed with
+    let credentials = auth::CredentialStore::new(config.credentials);
+
+    if credentials.is_empty() {
+        // users would silently lose their restrictions
+        if !storage.list_users().await?.is_empty() {
+            return Err("users exist but no root credentials are configured, requests would not be authenticated".into());
+        }
+
+        tracing::warn!("no credentials configured, requests are not authenticated");
+    }
+
     // Create router
-    let app = api::create_router();
+    let app = api::create_router(api::AppState {
+        storage: storage.clone(),
+        credentials,
+        master_key: config.master_key,
+        limits: config.limits,
+        trusted_proxies: config.trusted_proxies,
+        abort: abort.clone(),
+    });
 
     // Start server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    tracing::info!("Server listening on {}", addr);
+    tracing::info!("Server listening on {}", config.listen);
+
+    let listener = tokio::net::TcpListener::bind(config.listen).await?;
+
+    // bucket policies can match the client address
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+
+    let server = axum::serve(listener, app).with_graceful_shutdown({
+        let stopping = stopping.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!("shutting down, waiting for running requests to finish");
+            stopping.cancel();
+        }
+    });
+    let mut server = pin!(server.into_future());
+
+    // after a signal no connections are accepted, the running requests get a grace period
+    let grace_period = async {
+        stopping.cancelled().await;
+        tokio::time::sleep(Duration::from_secs(config.shutdown_timeout_secs)).await;
+    };
+
+    tokio::select! {
+        result = &mut server => result?,
+        _ = grace_period => {
+            tracing::warn!("requests still running after {}s, aborting them", config.shutdown_timeout_secs);
+            abort.cancel();
+            server.await?;
+        }
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // nothing uses the storage anymore once the worker is done with its rule
+    lifecycle.await?;
+    storage.close().await?;
+
+    tracing::info!("shutdown complete");
 
     Ok(())
 }
+
+
+/// Resolves once the process gets SIGTERM or SIGINT
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to install the SIGTERM handler");
+
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
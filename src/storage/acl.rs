@@ -0,0 +1,115 @@
+use crate::{
+    db::{BucketRecord, ObjectRecord},
+    error::DbError,
+    storage::{Acl, DEFAULT_OWNER, Result, Storage, StorageError},
+};
+
+
+impl Storage {
+
+    pub async fn get_bucket_acl(&self, bucket: &str) -> Result<Acl> {
+        self.validate_bucket_name(bucket)?;
+
+        bucket_acl(&self.db().get_bucket(bucket).await?)
+    }
+
+
+    pub async fn put_bucket_acl(&self, bucket: &str, acl: &Acl) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_acl(record.id, &serde_json::to_string(acl)?).await?;
+
+        Ok(())
+    }
+
+
+    /// The ACL of a version of an object, the latest one without a `version_id`
+    pub async fn get_object_acl(&self, bucket: &str, key: &str, version_id: Option<&str>) -> Result<Acl> {
+        let (bucket_record, record) = self.acl_object(bucket, key, version_id).await?;
+
+        object_acl(&bucket_record, &record)
+    }
+
+
+    pub async fn put_object_acl(&self, bucket: &str, key: &str, version_id: Option<&str>, acl: &Acl) -> Result<()> {
+        let (_, record) = self.acl_object(bucket, key, version_id).await?;
+
+        self.db().set_object_acl(record.id, &serde_json::to_string(acl)?).await?;
+
+        Ok(())
+    }
+
+
+    /// The ACL of a bucket for authorizing a request, `None` when the bucket
+    /// doesn't exist, which the handler reports
+    pub async fn bucket_acl(&self, bucket: &str) -> Result<Option<Acl>> {
+        match self.db().get_bucket(bucket).await {
+            Ok(record) => bucket_acl(&record).map(Some),
+            Err(DbError::BucketNotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+
+    /// The ACL of an object version for authorizing a request, `None` when
+    /// there is no such version or it is a delete marker
+    pub async fn object_acl(&self, bucket: &str, key: &str, version_id: Option<&str>) -> Result<Option<Acl>> {
+        match self.acl_object(bucket, key, version_id).await {
+            Ok((bucket_record, record)) => object_acl(&bucket_record, &record).map(Some),
+            Err(
+                StorageError::BucketNotFound(_)
+                | StorageError::ObjectNotFound(_)
+                | StorageError::VersionNotFound(_)
+                | StorageError::DeleteMarker { .. }
+                | StorageError::InvalidBucketName(_)
+                | StorageError::InvalidObjectKey(_)
+                | StorageError::InvalidArgument(_),
+            ) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+
+    /// The bucket and the version of an object whose ACL is read or replaced.
+    /// Delete markers have no ACL.
+    async fn acl_object(&self, bucket: &str, key: &str, version_id: Option<&str>) -> Result<(BucketRecord, ObjectRecord)> {
+        self.validate_bucket_name(bucket)?;
+        self.validate_object_key(key)?;
+
+        if let Some(version_id) = version_id {
+            self.validate_version_id(version_id)?;
+        }
+
+        let bucket_record = self.db().get_bucket(bucket).await?;
+
+        let record = self.db().get_object(bucket_record.id, key, version_id).await.map_err(|e| match (e, version_id) {
+            (DbError::ObjectNotFound(_), Some(version_id)) => StorageError::VersionNotFound(version_id.to_string()),
+            (e, _) => e.into(),
+        })?;
+
+        if record.is_delete_marker {
+            return Err(StorageError::DeleteMarker { version_id: record.version_id, requested: version_id.is_some() });
+        }
+
+        Ok((bucket_record, record))
+    }
+}
+
+
+/// Buckets from before ACLs belong to whoever created them
+fn bucket_acl(record: &BucketRecord) -> Result<Acl> {
+    match &record.acl {
+        Some(json) => Ok(serde_json::from_str(json)?),
+        None => Ok(Acl::private(record.owner.as_deref().unwrap_or(DEFAULT_OWNER))),
+    }
+}
+
+
+/// Objects from before ACLs belong to the owner of their bucket
+pub(super) fn object_acl(bucket: &BucketRecord, record: &ObjectRecord) -> Result<Acl> {
+    match &record.acl {
+        Some(json) => Ok(serde_json::from_str(json)?),
+        None => Ok(Acl::private(&bucket_acl(bucket)?.owner)),
+    }
+}
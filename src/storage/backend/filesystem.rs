@@ -0,0 +1,221 @@
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use futures_util::{FutureExt, future::BoxFuture};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader},
+};
+
+use crate::storage::ObjectReader;
+
+use super::StorageBackend;
+
+/// Read buffer for streaming uploads to disk, also the most an upload holds in memory
+const WRITE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Times a file is created in a directory that a concurrent `delete` keeps pruning
+const CREATE_ATTEMPTS: usize = 3;
+
+
+/// Keeps every path as a file under `root`. Writes are synced before they
+/// return and renames are synced to the directory, so once `Storage` has
+/// moved a file into place it survives a crash.
+pub struct FilesystemBackend {
+    root: PathBuf,
+}
+
+
+impl FilesystemBackend {
+    pub async fn new<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+
+        fs::create_dir_all(&root).await?;
+
+        Ok(Self { root })
+    }
+
+
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+
+    /// Remove `dir` and its parents while they are empty, stopping at the root
+    async fn prune_empty_dirs(&self, mut dir: Option<&Path>) {
+        while let Some(current) = dir {
+            if current == self.root || !current.starts_with(&self.root) {
+                break;
+            }
+
+            // remove_dir fails on non-empty directories, which is where we stop
+            if fs::remove_dir(current).await.is_err() {
+                break;
+            }
+
+            dir = current.parent();
+        }
+    }
+
+
+    /// Paths of all files below `dir`, relative to the root
+    async fn walk(&self, dir: PathBuf) -> io::Result<Vec<String>> {
+        let mut files = Vec::new();
+        let mut pending = vec![dir];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                    let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+                    files.push(parts.join("/"));
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+
+impl StorageBackend for FilesystemBackend {
+    fn put<'a>(&'a self, path: &'a str, data: &'a mut (dyn AsyncRead + Send + Unpin)) -> BoxFuture<'a, io::Result<u64>> {
+        async move {
+            let path = self.resolve(path);
+            let parent = path.parent().unwrap_or(&self.root);
+
+            let mut reader = BufReader::with_capacity(WRITE_BUFFER_SIZE, data);
+            let mut file = in_dir(parent, || fs::File::create(&path)).await?;
+
+            let written = async {
+                let written = tokio::io::copy_buf(&mut reader, &mut file).await?;
+                file.flush().await?;
+                file.sync_all().await?;
+                Ok(written)
+            }.await;
+
+            if written.is_err() {
+                let _ = fs::remove_file(&path).await;
+            }
+
+            written
+        }.boxed()
+    }
+
+
+    fn get<'a>(&'a self, path: &'a str, range: Option<(u64, u64)>) -> BoxFuture<'a, io::Result<ObjectReader>> {
+        async move {
+            let mut file = fs::File::open(self.resolve(path)).await?;
+
+            let Some((start, end)) = range else {
+                return Ok(Box::pin(file) as ObjectReader);
+            };
+
+            file.seek(SeekFrom::Start(start)).await?;
+
+            Ok(Box::pin(file.take(end - start + 1)) as ObjectReader)
+        }.boxed()
+    }
+
+
+    fn stat<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<Option<u64>>> {
+        async move {
+            match fs::metadata(self.resolve(path)).await {
+                Ok(metadata) => Ok(Some(metadata.len())),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        }.boxed()
+    }
+
+
+    fn delete<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let path = self.resolve(path);
+
+            match fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            // don't leave the directories the path needed behind
+            self.prune_empty_dirs(path.parent()).await;
+
+            Ok(())
+        }.boxed()
+    }
+
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, io::Result<Vec<String>>> {
+        async move {
+            // only walk the directory the prefix points into
+            let dir = match prefix.rfind('/') {
+                Some(i) => self.resolve(&prefix[..i]),
+                None => self.root.clone(),
+            };
+
+            let mut files = self.walk(dir).await?;
+            files.retain(|f| f.starts_with(prefix));
+
+            Ok(files)
+        }.boxed()
+    }
+
+
+    fn rename<'a>(&'a self, from: &'a str, to: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let (from, to) = (self.resolve(from), self.resolve(to));
+            let parent = to.parent().unwrap_or(&self.root);
+
+            in_dir(parent, || fs::rename(&from, &to)).await?;
+
+            sync_dir(parent).await
+        }.boxed()
+    }
+}
+
+
+/// Create `dir` and run `create`, which makes an entry in it. A `delete` of
+/// the last file in `dir` prunes it, and may do so in between, so on
+/// NotFound the directory is created again and `create` retried.
+async fn in_dir<T, F, Fut>(dir: &Path, mut create: F) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut attempts = 1;
+
+    loop {
+        fs::create_dir_all(dir).await?;
+
+        match create().await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound && attempts < CREATE_ATTEMPTS => attempts += 1,
+            result => return result,
+        }
+    }
+}
+
+
+/// Make a rename or removal in `dir` survive a crash
+async fn sync_dir(dir: &Path) -> io::Result<()> {
+    // directories can't be opened for syncing everywhere, there the rename is as durable as it gets
+    #[cfg(unix)]
+    fs::File::open(dir).await?.sync_all().await?;
+
+    #[cfg(not(unix))]
+    let _ = dir;
+
+    Ok(())
+}
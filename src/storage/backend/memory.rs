@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    io::{self, Cursor},
+    sync::RwLock,
+};
+
+use bytes::Bytes;
+use futures_util::{FutureExt, future::BoxFuture};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::storage::ObjectReader;
+
+use super::StorageBackend;
+
+
+/// Keeps everything in memory and forgets it on exit, for tests and throwaway
+/// instances
+#[derive(Default)]
+pub struct MemoryBackend {
+    files: RwLock<HashMap<String, Bytes>>,
+}
+
+
+impl MemoryBackend {
+    fn file(&self, path: &str) -> io::Result<Bytes> {
+        self.files
+            .read()
+            .expect("memory backend lock poisoned")
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+}
+
+
+impl StorageBackend for MemoryBackend {
+    fn put<'a>(&'a self, path: &'a str, data: &'a mut (dyn AsyncRead + Send + Unpin)) -> BoxFuture<'a, io::Result<u64>> {
+        async move {
+            let mut buf = Vec::new();
+            let written = data.read_to_end(&mut buf).await? as u64;
+
+            self.files.write().expect("memory backend lock poisoned").insert(path.to_string(), buf.into());
+
+            Ok(written)
+        }.boxed()
+    }
+
+
+    fn get<'a>(&'a self, path: &'a str, range: Option<(u64, u64)>) -> BoxFuture<'a, io::Result<ObjectReader>> {
+        async move {
+            let mut data = self.file(path)?;
+
+            if let Some((start, end)) = range {
+                let end = (end + 1).min(data.len() as u64);
+                data = data.slice(start.min(end) as usize..end as usize);
+            }
+
+            Ok(Box::pin(Cursor::new(data)) as ObjectReader)
+        }.boxed()
+    }
+
+
+    fn stat<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<Option<u64>>> {
+        async move { Ok(self.file(path).ok().map(|data| data.len() as u64)) }.boxed()
+    }
+
+
+    fn delete<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            self.files.write().expect("memory backend lock poisoned").remove(path);
+            Ok(())
+        }.boxed()
+    }
+
+
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, io::Result<Vec<String>>> {
+        async move {
+            Ok(self
+                .files
+                .read()
+                .expect("memory backend lock poisoned")
+                .keys()
+                .filter(|path| path.starts_with(prefix))
+                .cloned()
+                .collect())
+        }.boxed()
+    }
+
+
+    fn rename<'a>(&'a self, from: &'a str, to: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        async move {
+            let mut files = self.files.write().expect("memory backend lock poisoned");
+
+            let data = files
+                .remove(from)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, from.to_string()))?;
+
+            files.insert(to.to_string(), data);
+
+            Ok(())
+        }.boxed()
+    }
+}
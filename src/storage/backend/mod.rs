@@ -0,0 +1,40 @@
+mod filesystem;
+mod memory;
+
+use std::io;
+
+use futures_util::future::BoxFuture;
+use tokio::io::AsyncRead;
+
+use super::ObjectReader;
+
+pub use filesystem::FilesystemBackend;
+pub use memory::MemoryBackend;
+
+
+/// Where `Storage` keeps its bytes: object blobs, multipart parts and the
+/// bookkeeping files next to them. Everything else, what objects exist and
+/// which blob holds each, lives in the database.
+///
+/// Paths are `/` separated and relative, always built by `Storage` itself and
+/// never taken from a request.
+pub trait StorageBackend: Send + Sync {
+    /// Stream `data` to `path`, creating or replacing it, and make it durable.
+    /// Returns the number of bytes written.
+    fn put<'a>(&'a self, path: &'a str, data: &'a mut (dyn AsyncRead + Send + Unpin)) -> BoxFuture<'a, io::Result<u64>>;
+
+    /// Read `path`, or only the inclusive byte range of it
+    fn get<'a>(&'a self, path: &'a str, range: Option<(u64, u64)>) -> BoxFuture<'a, io::Result<ObjectReader>>;
+
+    /// Size of `path`, `None` if there is nothing there
+    fn stat<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<Option<u64>>>;
+
+    /// Remove `path`. Removing a missing path is not an error.
+    fn delete<'a>(&'a self, path: &'a str) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Every path starting with `prefix`, in no particular order
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, io::Result<Vec<String>>>;
+
+    /// Atomically move `from` to `to`, replacing whatever is there
+    fn rename<'a>(&'a self, from: &'a str, to: &'a str) -> BoxFuture<'a, io::Result<()>>;
+}
@@ -0,0 +1,127 @@
+use uuid::Uuid;
+
+use crate::storage::{Result, Storage};
+
+/// Root of the content-addressed store. The leading dot keeps it from ever
+/// being taken for a bucket.
+const BLOBS_DIR: &str = ".blobs";
+
+
+/// Object bodies are stored once per distinct content, named by their sha256
+/// and fanned out over two directory levels: `.blobs/ab/cd/abcd...`.
+///
+/// Which blobs are in use is tracked with reference counts in the database.
+/// Placing a blob and recording the reference, and dropping the last
+/// reference and removing the file, each happen under `blob_lock` so a blob
+/// is never removed from under an upload that just found it already there.
+impl Storage {
+
+    /// Move a staged upload into the blob store under `sha256`. If an identical
+    /// blob is already there the staged copy is dropped instead.
+    ///
+    /// Returns the blob's storage path and whether this call created it.
+    pub(super) async fn place_blob(&self, staged: &str, sha256: &str) -> Result<(String, bool)> {
+        let storage_path = blob_path(sha256);
+
+        if self.backend().stat(&storage_path).await?.is_some() {
+            self.backend().delete(staged).await?;
+            return Ok((storage_path, false));
+        }
+
+        self.commit_staged(staged, &storage_path).await?;
+
+        Ok((storage_path, true))
+    }
+
+
+    /// Move a staged upload into the blob store under a fresh name, for
+    /// encrypted bodies which are never shared. Returns its storage path.
+    pub(super) async fn place_unique_blob(&self, staged: &str) -> Result<String> {
+        let storage_path = blob_path(&Uuid::new_v4().simple().to_string());
+
+        self.commit_staged(staged, &storage_path).await?;
+
+        Ok(storage_path)
+    }
+
+
+    /// Remove blobs no object references anymore. Failures only leak space,
+    /// so they are logged.
+    pub(super) async fn remove_orphans(&self, storage_paths: &[String]) {
+        for storage_path in storage_paths {
+            if let Err(e) = self.backend().delete(storage_path).await {
+                tracing::warn!("failed to remove unreferenced blob {}: {}", storage_path, e);
+            }
+        }
+    }
+}
+
+
+fn blob_path(sha256: &str) -> String {
+    format!("{BLOBS_DIR}/{}/{}/{sha256}", &sha256[..2], &sha256[2..4])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::{BucketVersioning, PutObjectOptions};
+
+    async fn put(storage: &Storage, key: &str, body: &[u8]) {
+        storage.put_object("bucket", key, body, PutObjectOptions::default()).await.unwrap();
+    }
+
+    async fn blobs(storage: &Storage) -> Vec<String> {
+        storage.backend().list(&format!("{BLOBS_DIR}/")).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn stores_identical_content_once() {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        put(&storage, "a", b"same").await;
+        put(&storage, "b", b"same").await;
+        put(&storage, "c", b"other").await;
+
+        let stored = blobs(&storage).await;
+        assert_eq!(stored.len(), 2);
+        assert!(stored.contains(&blob_path("0967115f2813a3541eaef77de9d9d5773f1c0c04314b0bbfe4ff3b3b1c55b5d5")));
+
+        // the blob stays while another object still references it
+        storage.delete_object("bucket", "a", None).await.unwrap();
+        assert_eq!(blobs(&storage).await.len(), 2);
+        assert!(storage.get_object("bucket", "b", None, None, None).await.is_ok());
+
+        storage.delete_object("bucket", "b", None).await.unwrap();
+        assert_eq!(blobs(&storage).await.len(), 1);
+
+        // overwriting with identical content keeps the blob, other content orphans it
+        put(&storage, "c", b"other").await;
+        assert_eq!(blobs(&storage).await.len(), 1);
+        assert!(storage.get_object("bucket", "c", None, None, None).await.is_ok());
+
+        put(&storage, "c", b"new").await;
+        assert_eq!(blobs(&storage).await.len(), 1);
+
+        storage.delete_object("bucket", "c", None).await.unwrap();
+        assert!(blobs(&storage).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn versions_share_blobs() {
+        let storage = Storage::in_memory_with_bucket().await;
+        storage.put_bucket_versioning("bucket", BucketVersioning::Enabled).await.unwrap();
+
+        put(&storage, "key", b"same").await;
+        put(&storage, "key", b"same").await;
+        assert_eq!(blobs(&storage).await.len(), 1);
+
+        // a delete marker references nothing, the versions behind it still do
+        storage.delete_object("bucket", "key", None).await.unwrap();
+        assert_eq!(blobs(&storage).await.len(), 1);
+
+        storage.delete_bucket("bucket", true).await.unwrap();
+        assert!(blobs(&storage).await.is_empty());
+    }
+}
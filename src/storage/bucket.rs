@@ -1,53 +1,89 @@
-use chrono::Utc;
-use tokio::fs;
 
-use crate::storage::{BucketInfo, StorageError};
+use crate::{
+    db::BucketRecord,
+    error::DbError,
+    storage::{Acl, BucketInfo, BucketVersioning, StorageError},
+};
 
 use super:: {Storage, Result};
 
 impl Storage {
 
-    pub async fn create_bucket(&self, bucket_name:&str) -> Result<BucketInfo> {
+    /// Create a bucket owned by `owner`, a canonical id, whose quota then
+    /// covers it
+    pub async fn create_bucket(&self, bucket_name:&str, owner: Option<&str>, acl: &Acl) -> Result<BucketInfo> {
         self.validate_bucket_name(bucket_name)?;
 
-        let bucket_path = self.get_bucket_path(bucket_name);
+        match self.db().create_bucket(bucket_name, owner, Some(&serde_json::to_string(acl)?)).await {
+            Ok(record) => Ok(bucket_info(record)),
+            Err(DbError::BucketAlreadyExists(_)) => {
+                let existing = self.db().get_bucket(bucket_name).await?;
 
-        if bucket_path.exists() {
-            return Err(StorageError::BucketAlreadyExists(bucket_name.to_string()));
+                Err(if existing.owner.as_deref() == owner {
+                    StorageError::BucketAlreadyOwnedByYou(bucket_name.to_string())
+                } else {
+                    StorageError::BucketAlreadyExists(bucket_name.to_string())
+                })
+            }
+            Err(e) => Err(e.into()),
         }
+    }
 
-        fs::create_dir_all(&bucket_path).await?;
 
-        Ok(BucketInfo {
-            name: bucket_name.to_string(),
-            created_at: Utc::now(),
-            object_count: 0,
-            total_size: 0
-        })
+    /// check that a bucket exists
+    pub async fn head_bucket(&self, bucket_name: &str) -> Result<()> {
+        self.validate_bucket_name(bucket_name)?;
+        self.db().get_bucket(bucket_name).await?;
+
+        Ok(())
     }
 
 
-    /// delete a bucket - must be empty or force flag =true
-    pub async fn delete_bucket(&self, bucket_name: &str, force: bool) -> Result<()> {
-        let bucket_path = self.get_bucket_path(bucket_name);
+    pub async fn get_bucket_versioning(&self, bucket_name: &str) -> Result<BucketVersioning> {
+        self.validate_bucket_name(bucket_name)?;
 
-        if !bucket_path.exists() {
-            return Err(StorageError::BucketNotFound(bucket_name.to_string()))
+        Ok(self.db().get_bucket(bucket_name).await?.versioning)
+    }
+
+
+    /// Enable or suspend versioning. Versions written so far are kept either way.
+    pub async fn put_bucket_versioning(&self, bucket_name: &str, versioning: BucketVersioning) -> Result<()> {
+        self.validate_bucket_name(bucket_name)?;
+
+        if versioning == BucketVersioning::Unversioned {
+            return Err(StorageError::InvalidArgument("Versioning can only be Enabled or Suspended".to_string()));
         }
 
-        if !force {
-            let mut entries = fs::read_dir(&bucket_path).await?;
+        let bucket = self.db().get_bucket(bucket_name).await?;
+        self.db().set_bucket_versioning(bucket.id, versioning).await?;
 
-            if entries.next_entry().await?.is_some() {
-                return Err(StorageError::BucketNotEmpty(bucket_name.to_string()))
-            }
+        Ok(())
+    }
+
+
+    /// delete a bucket - must be empty or force flag =true
+    pub async fn delete_bucket(&self, bucket_name: &str, force: bool) -> Result<()> {
+        self.validate_bucket_name(bucket_name)?;
+
+        let bucket = self.db().get_bucket(bucket_name).await?;
+
+        if !force && self.db().has_objects(bucket.id).await? {
+            return Err(StorageError::BucketNotEmpty(bucket_name.to_string()))
         }
 
         //delete all metadata files for the bucket
-        //to-do
-        //
+        for upload in self.all_multipart_uploads().await? {
+            if upload.bucket == bucket_name {
+                self.remove_upload(&upload.upload_id).await?;
+            }
+        }
 
-        fs::remove_dir_all(&bucket_path).await?;
+        // objects and their metadata go with the bucket row, blobs only once nothing else uses them
+        {
+            let _guard = self.blob_lock().lock().await;
+            let orphaned = self.db().delete_bucket(bucket_name).await?;
+            self.remove_orphans(&orphaned).await;
+        }
 
 
         Ok(())
@@ -57,26 +93,32 @@ impl Storage {
     /// list buckets
     pub async fn list_buckets(&self) -> Result<Vec<BucketInfo>> {
         let mut buckets = Vec::new();
-        let mut entries = fs::read_dir(&self.base_path).await?;
 
-        while let Some(entry)= entries.next_entry().await? {
-            if entry.file_type().await?.is_dir() {
-                let name = entry.file_name().to_string_lossy().to_string();
+        // already ordered by name
+        for record in self.db().list_buckets().await? {
+            buckets.push(bucket_info(record));
+        }
 
-                // get created_at from metadata
+        Ok(buckets)
+    }
 
-                let (object_count, total_size) = self.get_bucket_stats(&name).await?;
 
-                buckets.push(BucketInfo { name, created_at: Utc::now(), object_count, total_size })
-            }
-        }
+    /// A bucket with its object count and total size
+    pub async fn get_bucket_info(&self, bucket_name: &str) -> Result<BucketInfo> {
+        self.validate_bucket_name(bucket_name)?;
 
-        Ok(buckets)
+        Ok(bucket_info(self.db().get_bucket(bucket_name).await?))
     }
+}
 
 
-    async fn get_bucket_stats(&self, _bucket_name: &str) -> Result<(usize, u64)> {
-        //todo calculate object count and total size
-        Ok((0, 0))
+fn bucket_info(record: BucketRecord) -> BucketInfo {
+    BucketInfo {
+        name: record.name,
+        owner: record.owner,
+        created_at: record.created_at,
+        object_count: record.object_count as usize,
+        total_size: record.total_size as u64,
+        quota: record.quota,
     }
 }
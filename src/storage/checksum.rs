@@ -0,0 +1,275 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use crc::{CRC_32_ISCSI, CRC_32_ISO_HDLC, Crc};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use super::{ChecksumAlgorithm, Checksums, Result, StorageError};
+
+static CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+static CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+
+/// Running digest of one algorithm
+pub enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Crc32(crc::Digest<'static, u32>),
+    Crc32c(crc::Digest<'static, u32>),
+}
+
+
+impl Hasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Self::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            ChecksumAlgorithm::Crc32 => Self::Crc32(CRC32.digest()),
+            ChecksumAlgorithm::Crc32c => Self::Crc32c(CRC32C.digest()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(h) => h.update(data),
+            Self::Sha256(h) => h.update(data),
+            Self::Sha1(h) => h.update(data),
+            Self::Crc32(h) | Self::Crc32c(h) => h.update(data),
+        }
+    }
+
+    /// The digest hex encoded, like `Checksums` holds it
+    pub fn finish(self) -> String {
+        match self {
+            Self::Md5(h) => hex::encode(h.finalize()),
+            Self::Sha256(h) => hex::encode(h.finalize()),
+            Self::Sha1(h) => hex::encode(h.finalize()),
+            Self::Crc32(h) | Self::Crc32c(h) => hex::encode(h.finalize().to_be_bytes()),
+        }
+    }
+}
+
+
+/// Passes bytes through from `inner` while hashing them with every supported
+/// algorithm, so checksums are known as soon as the last byte has been written out.
+pub struct HashingReader<R> {
+    inner: R,
+    md5: Hasher,
+    sha256: Hasher,
+    sha1: Hasher,
+    crc32: Hasher,
+    crc32c: Hasher,
+    size: u64,
+}
+
+
+impl<R> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            md5: Hasher::new(ChecksumAlgorithm::Md5),
+            sha256: Hasher::new(ChecksumAlgorithm::Sha256),
+            sha1: Hasher::new(ChecksumAlgorithm::Sha1),
+            crc32: Hasher::new(ChecksumAlgorithm::Crc32),
+            crc32c: Hasher::new(ChecksumAlgorithm::Crc32c),
+            size: 0,
+        }
+    }
+
+    /// Bytes read so far and their checksums
+    pub fn finish(self) -> (u64, Checksums) {
+        let checksums = Checksums {
+            md5: self.md5.finish(),
+            sha256: self.sha256.finish(),
+            sha1: self.sha1.finish(),
+            crc32: self.crc32.finish(),
+            crc32c: self.crc32c.finish(),
+        };
+
+        (self.size, checksums)
+    }
+}
+
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        let chunk = &buf.filled()[before..];
+
+        for hasher in [&mut this.md5, &mut this.sha256, &mut this.sha1, &mut this.crc32, &mut this.crc32c] {
+            hasher.update(chunk);
+        }
+
+        this.size += chunk.len() as u64;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+impl Checksums {
+    pub fn get(&self, algorithm: ChecksumAlgorithm) -> &str {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => &self.md5,
+            ChecksumAlgorithm::Sha256 => &self.sha256,
+            ChecksumAlgorithm::Sha1 => &self.sha1,
+            ChecksumAlgorithm::Crc32 => &self.crc32,
+            ChecksumAlgorithm::Crc32c => &self.crc32c,
+        }
+    }
+
+    /// Check every expected digest, in order, the first one that differs is the error
+    pub fn verify(&self, expected: &[(ChecksumAlgorithm, String)]) -> Result<()> {
+        for (algorithm, digest) in expected {
+            let actual = self.get(*algorithm);
+
+            if !digest.eq_ignore_ascii_case(actual) {
+                return Err(StorageError::ChecksumMismatch {
+                    expected: digest.clone(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    use crate::storage::{PutObjectOptions, Storage};
+
+    const CHECK: &[u8] = b"123456789";
+
+    fn digest(algorithm: ChecksumAlgorithm, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(algorithm);
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn hashes_known_vectors() {
+        for (algorithm, expected) in [
+            (ChecksumAlgorithm::Crc32, "cbf43926"),
+            (ChecksumAlgorithm::Crc32c, "e3069283"),
+            (ChecksumAlgorithm::Md5, "25f9e794323b453885f5181f1b624d0b"),
+            (ChecksumAlgorithm::Sha1, "f7c3bc1d808e04732adf679965ccc34ca7ae3441"),
+            (ChecksumAlgorithm::Sha256, "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225"),
+        ] {
+            assert_eq!(digest(algorithm, CHECK), expected, "{algorithm:?}");
+        }
+
+        assert_eq!(digest(ChecksumAlgorithm::Crc32, b""), "00000000");
+        assert_eq!(digest(ChecksumAlgorithm::Md5, b""), "d41d8cd98f00b204e9800998ecf8427e");
+    }
+
+    #[test]
+    fn hashes_across_updates() {
+        let mut hasher = Hasher::new(ChecksumAlgorithm::Crc32c);
+        for chunk in CHECK.chunks(2) {
+            hasher.update(chunk);
+        }
+
+        assert_eq!(hasher.finish(), "e3069283");
+    }
+
+    #[tokio::test]
+    async fn hashes_what_it_reads() {
+        let mut reader = HashingReader::new(CHECK);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+
+        let (size, checksums) = reader.finish();
+        assert_eq!((data.as_slice(), size), (CHECK, 9));
+        assert_eq!(checksums.crc32, "cbf43926");
+        assert_eq!(checksums.crc32c, "e3069283");
+        assert_eq!(checksums.md5, "25f9e794323b453885f5181f1b624d0b");
+        assert_eq!(checksums.sha1, "f7c3bc1d808e04732adf679965ccc34ca7ae3441");
+        assert_eq!(checksums.sha256, "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225");
+    }
+
+    #[tokio::test]
+    async fn verifies_expected_digests() {
+        let mut reader = HashingReader::new(CHECK);
+        reader.read_to_end(&mut Vec::new()).await.unwrap();
+        let (_, checksums) = reader.finish();
+
+        // case doesn't matter
+        checksums.verify(&[
+            (ChecksumAlgorithm::Crc32, "CBF43926".to_string()),
+            (ChecksumAlgorithm::Md5, "25f9e794323b453885f5181f1b624d0b".to_string()),
+        ]).unwrap();
+        checksums.verify(&[]).unwrap();
+
+        let err = checksums
+            .verify(&[
+                (ChecksumAlgorithm::Crc32, "cbf43926".to_string()),
+                (ChecksumAlgorithm::Crc32c, "00000000".to_string()),
+            ])
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            StorageError::ChecksumMismatch { expected, actual } if expected == "00000000" && actual == "e3069283",
+        ));
+    }
+
+    #[tokio::test]
+    async fn mismatched_uploads_store_nothing() {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        for expected in [
+            (ChecksumAlgorithm::Md5, "00000000000000000000000000000000"),
+            (ChecksumAlgorithm::Crc32, "00000000"),
+            (ChecksumAlgorithm::Crc32c, "cbf43926"),
+            (ChecksumAlgorithm::Sha1, "0000000000000000000000000000000000000000"),
+            (ChecksumAlgorithm::Sha256, "0000000000000000000000000000000000000000000000000000000000000000"),
+        ] {
+            let options = PutObjectOptions {
+                expected_checksums: vec![(expected.0, expected.1.to_string())],
+                ..Default::default()
+            };
+
+            assert!(
+                matches!(
+                    storage.put_object("bucket", "key", CHECK, options).await,
+                    Err(StorageError::ChecksumMismatch { .. }),
+                ),
+                "{expected:?}",
+            );
+            assert!(matches!(
+                storage.head_object("bucket", "key", None, None).await,
+                Err(StorageError::ObjectNotFound(_)),
+            ));
+        }
+
+        assert!(storage.backend().list(".blobs/").await.unwrap().is_empty());
+
+        // the right digests store it
+        let options = PutObjectOptions {
+            expected_checksums: vec![
+                (ChecksumAlgorithm::Crc32c, "e3069283".to_string()),
+                (ChecksumAlgorithm::Md5, "25f9e794323b453885f5181f1b624d0b".to_string()),
+            ],
+            ..Default::default()
+        };
+        let metadata = storage.put_object("bucket", "key", CHECK, options).await.unwrap();
+        assert_eq!(metadata.checksums.crc32, "cbf43926");
+    }
+}
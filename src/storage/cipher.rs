@@ -0,0 +1,362 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce, aead::Aead};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use super::ObjectReader;
+
+/// Plaintext bytes encrypted together. Each chunk is sealed on its own so a
+/// range is read by decrypting only the chunks it touches.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes the GCM tag adds to every chunk
+const TAG_SIZE: usize = 16;
+
+
+/// The AES-256-GCM key encrypting one object body or staged part. Every
+/// body has its own key, so nonces only have to be unique within it: they
+/// are the chunk index, and a flag marking the final chunk so a truncated
+/// body fails to decrypt rather than reading short.
+#[derive(Clone)]
+pub struct DataKey {
+    cipher: Arc<Aes256Gcm>,
+}
+
+
+impl DataKey {
+    /// `None` unless `key` is 32 bytes
+    pub fn from_bytes(key: &[u8]) -> Option<Self> {
+        Aes256Gcm::new_from_slice(key).ok().map(|cipher| Self { cipher: Arc::new(cipher) })
+    }
+
+    fn nonce(index: u64, last: bool) -> Nonce<aes_gcm::aead::consts::U12> {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&index.to_be_bytes());
+        nonce[11] = last as u8;
+        nonce.into()
+    }
+}
+
+
+/// Chunks a body of `size` bytes is split into. An empty body is a single
+/// empty chunk, so even that is authenticated.
+fn chunk_count(size: u64) -> u64 {
+    size.div_ceil(CHUNK_SIZE as u64).max(1)
+}
+
+
+/// Plaintext bytes in chunk `index` of a body of `size` bytes
+fn chunk_len(size: u64, index: u64) -> usize {
+    (size - index * CHUNK_SIZE as u64).min(CHUNK_SIZE as u64) as usize
+}
+
+
+/// Encrypts `inner` chunk by chunk as it is read
+pub struct EncryptingReader<R> {
+    inner: R,
+    key: DataKey,
+    /// Plaintext read ahead, one byte more than a chunk to know whether a full chunk is the last
+    plain: Vec<u8>,
+    filled: usize,
+    sealed: Vec<u8>,
+    sealed_pos: usize,
+    index: u64,
+    eof: bool,
+    done: bool,
+}
+
+
+impl<R> EncryptingReader<R> {
+    pub fn new(inner: R, key: DataKey) -> Self {
+        Self {
+            inner,
+            key,
+            plain: vec![0; CHUNK_SIZE + 1],
+            filled: 0,
+            sealed: Vec::new(),
+            sealed_pos: 0,
+            index: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    fn seal(&mut self, len: usize, last: bool) {
+        self.sealed = self
+            .key
+            .cipher
+            .encrypt(&DataKey::nonce(self.index, last), &self.plain[..len])
+            .expect("AES-GCM encrypts any plaintext");
+        self.sealed_pos = 0;
+        self.index += 1;
+
+        self.plain.copy_within(len..self.filled, 0);
+        self.filled -= len;
+    }
+}
+
+
+impl<R: AsyncRead + Unpin> AsyncRead for EncryptingReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.sealed_pos < this.sealed.len() {
+                let n = buf.remaining().min(this.sealed.len() - this.sealed_pos);
+                buf.put_slice(&this.sealed[this.sealed_pos..this.sealed_pos + n]);
+                this.sealed_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.eof {
+                this.seal(this.filled, true);
+                this.done = true;
+                continue;
+            }
+
+            let mut read = ReadBuf::new(&mut this.plain[this.filled..]);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+
+            match read.filled().len() {
+                0 => this.eof = true,
+                n => this.filled += n,
+            }
+
+            if this.filled > CHUNK_SIZE {
+                this.seal(CHUNK_SIZE, false);
+            }
+        }
+    }
+}
+
+
+/// Decrypts a body of `size` plaintext bytes, or the inclusive range of it,
+/// from a reader of the encrypted chunks the range falls in
+pub struct DecryptingReader {
+    inner: ObjectReader,
+    key: DataKey,
+    size: u64,
+    /// Next chunk to read and the final chunk of the body
+    index: u64,
+    last: u64,
+    sealed: Vec<u8>,
+    plain: Vec<u8>,
+    plain_pos: usize,
+    /// Plaintext to drop from the first chunk read and to hand out after that
+    skip: usize,
+    remaining: u64,
+}
+
+
+impl DecryptingReader {
+
+    /// The inclusive byte range of the encrypted body to read for a plaintext
+    /// `range`, `None` for the whole body
+    pub fn encrypted_range(size: u64, range: Option<(u64, u64)>) -> Option<(u64, u64)> {
+        let (start, end) = range?;
+        let (first, last) = (start / CHUNK_SIZE as u64, end / CHUNK_SIZE as u64);
+        let sealed_chunk = (CHUNK_SIZE + TAG_SIZE) as u64;
+
+        Some((first * sealed_chunk, last * sealed_chunk + (chunk_len(size, last) + TAG_SIZE) as u64 - 1))
+    }
+
+
+    /// `inner` reads from where `encrypted_range` says for the same `range`
+    pub fn new(inner: ObjectReader, key: DataKey, size: u64, range: Option<(u64, u64)>) -> Self {
+        let (start, end) = range.unwrap_or((0, size.saturating_sub(1)));
+        let index = start / CHUNK_SIZE as u64;
+
+        Self {
+            inner,
+            key,
+            size,
+            index,
+            last: chunk_count(size) - 1,
+            sealed: Vec::with_capacity(CHUNK_SIZE + TAG_SIZE),
+            plain: Vec::new(),
+            plain_pos: 0,
+            skip: (start - index * CHUNK_SIZE as u64) as usize,
+            remaining: if size == 0 { 0 } else { end - start + 1 },
+        }
+    }
+}
+
+
+impl AsyncRead for DecryptingReader {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.plain_pos < this.plain.len() {
+                let n = buf.remaining().min(this.plain.len() - this.plain_pos);
+                buf.put_slice(&this.plain[this.plain_pos..this.plain_pos + n]);
+                this.plain_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.remaining == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let expected = chunk_len(this.size, this.index) + TAG_SIZE;
+
+            while this.sealed.len() < expected {
+                let filled = this.sealed.len();
+                this.sealed.resize(expected, 0);
+
+                let mut read = ReadBuf::new(&mut this.sealed[filled..]);
+                let polled = Pin::new(&mut this.inner).poll_read(cx, &mut read);
+                let n = read.filled().len();
+                this.sealed.truncate(filled + n);
+
+                ready!(polled)?;
+
+                if n == 0 {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::UnexpectedEof, "encrypted body is truncated")));
+                }
+            }
+
+            let mut plain = this
+                .key
+                .cipher
+                .decrypt(&DataKey::nonce(this.index, this.index == this.last), this.sealed.as_slice())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "encrypted body failed authentication"))?;
+
+            plain.drain(..this.skip.min(plain.len()));
+            plain.truncate(this.remaining.min(plain.len() as u64) as usize);
+
+            this.remaining -= plain.len() as u64;
+            this.plain = plain;
+            this.plain_pos = 0;
+            this.sealed.clear();
+            this.skip = 0;
+            this.index += 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    fn key() -> DataKey {
+        DataKey::from_bytes(&[7; 32]).unwrap()
+    }
+
+    fn body(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Hands out at most a few bytes per read, like a slow network body
+    struct Trickle<'a>(&'a [u8]);
+
+    impl AsyncRead for Trickle<'_> {
+        fn poll_read(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            let n = buf.remaining().min(self.0.len()).min(1000);
+            buf.put_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn encrypt(plain: &[u8]) -> Vec<u8> {
+        let mut sealed = Vec::new();
+        EncryptingReader::new(Trickle(plain), key()).read_to_end(&mut sealed).await.unwrap();
+        sealed
+    }
+
+    async fn decrypt(sealed: Vec<u8>, size: u64, range: Option<(u64, u64)>) -> io::Result<Vec<u8>> {
+        let mut plain = Vec::new();
+        DecryptingReader::new(Box::pin(Cursor::new(sealed)), key(), size, range).read_to_end(&mut plain).await?;
+        Ok(plain)
+    }
+
+    #[tokio::test]
+    async fn round_trips() {
+        for size in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE + 17] {
+            let plain = body(size);
+            let sealed = encrypt(&plain).await;
+
+            assert_eq!(sealed.len(), size + chunk_count(size as u64) as usize * TAG_SIZE, "size {size}");
+            assert_eq!(decrypt(sealed, size as u64, None).await.unwrap(), plain, "size {size}");
+        }
+    }
+
+    #[tokio::test]
+    async fn decrypts_ranges() {
+        let size = 3 * CHUNK_SIZE + 17;
+        let plain = body(size);
+        let sealed = encrypt(&plain).await;
+
+        let c = CHUNK_SIZE as u64;
+        let ranges = [
+            (0, 0),
+            (c - 1, c - 1),
+            (c - 1, c),
+            (c, c),
+            (c, 2 * c - 1),
+            (1, 2 * c + 5),
+            (3 * c, size as u64 - 1),
+            (size as u64 - 1, size as u64 - 1),
+            (0, size as u64 - 1),
+        ];
+
+        for (start, end) in ranges {
+            let (from, to) = DecryptingReader::encrypted_range(size as u64, Some((start, end))).unwrap();
+            let part = sealed[from as usize..=to as usize].to_vec();
+
+            assert_eq!(
+                decrypt(part, size as u64, Some((start, end))).await.unwrap(),
+                plain[start as usize..=end as usize],
+                "range {start}-{end}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn detects_truncation() {
+        let size = 2 * CHUNK_SIZE + 5;
+        let sealed = encrypt(&body(size)).await;
+        let boundary = 2 * (CHUNK_SIZE + TAG_SIZE);
+
+        // read short of the size the body was written with
+        let error = decrypt(sealed[..boundary].to_vec(), size as u64, None).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // even when the size is cut down with it, the chunk now last wasn't sealed as the final one
+        let error = decrypt(sealed[..boundary].to_vec(), 2 * CHUNK_SIZE as u64, None).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn detects_tampering() {
+        let size = 2 * CHUNK_SIZE + 5;
+        let sealed = encrypt(&body(size)).await;
+
+        let mut flipped = sealed.clone();
+        flipped[CHUNK_SIZE + TAG_SIZE + 10] ^= 1;
+        let error = decrypt(flipped, size as u64, None).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // chunks can't be swapped around either
+        let mut swapped = sealed[CHUNK_SIZE + TAG_SIZE..2 * (CHUNK_SIZE + TAG_SIZE)].to_vec();
+        swapped.extend_from_slice(&sealed[..CHUNK_SIZE + TAG_SIZE]);
+        swapped.extend_from_slice(&sealed[2 * (CHUNK_SIZE + TAG_SIZE)..]);
+        let error = decrypt(swapped, size as u64, None).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use tokio::{io::AsyncReadExt, sync::Mutex};
+
+use crate::{crypto::MasterKey, db::Database};
+
+use super::{Result, StorageError, backend::StorageBackend};
+
+
+/// Object data lives in a `StorageBackend`, everything known about it
+/// (buckets, checksums, metadata) in the database
+#[derive(Clone)]
+pub struct Storage {
+    backend: Arc<dyn StorageBackend>,
+    db: Database,
+    /// Serialises placing and removing blobs, see `blob.rs`
+    blob_lock: Arc<Mutex<()>>,
+    /// Wraps the data keys of encrypted objects, see `encryption.rs`
+    master_key: Option<MasterKey>,
+}
+
+
+impl Storage {
+
+    pub async fn new(backend: Arc<dyn StorageBackend>, database_url: &str, master_key: Option<MasterKey>) -> Result<Self> {
+        let db = Database::new(database_url).await?;
+
+        let storage = Self {
+            backend,
+            db,
+            blob_lock: Arc::new(Mutex::new(())),
+            master_key,
+        };
+
+        storage.clean_staging().await?;
+
+        Ok(storage)
+    }
+
+
+    /// Remove staging files of writes that were cut off and close the
+    /// database. Nothing may use the storage anymore afterwards.
+    pub async fn close(&self) -> Result<()> {
+        self.clean_staging().await?;
+        self.db.close().await;
+
+        Ok(())
+    }
+
+
+    pub(super) fn backend(&self) -> &Arc<dyn StorageBackend> {
+        &self.backend
+    }
+
+    pub(super) fn db(&self) -> &Database {
+        &self.db
+    }
+
+    pub(super) fn blob_lock(&self) -> &Mutex<()> {
+        &self.blob_lock
+    }
+
+    pub(super) fn master_key(&self) -> Result<&MasterKey> {
+        self.master_key.as_ref().ok_or(StorageError::NoMasterKey)
+    }
+
+    /// Read a whole file from the backend, `None` if it doesn't exist. Only
+    /// meant for the small bookkeeping files next to the data.
+    pub(super) async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let mut reader = match self.backend.get(path, None).await {
+            Ok(reader) => reader,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+
+        Ok(Some(data))
+    }
+
+}
+
+
+#[cfg(test)]
+impl Storage {
+
+    /// Storage on the memory backend and an in-memory database, for tests
+    pub(crate) async fn in_memory() -> Self {
+        Self::new(Arc::new(super::MemoryBackend::default()), "sqlite::memory:", None).await.unwrap()
+    }
+
+    /// `in_memory` with an empty private bucket named "bucket", owned by "owner"
+    pub(crate) async fn in_memory_with_bucket() -> Self {
+        let storage = Self::in_memory().await;
+        storage.create_bucket("bucket", Some("owner"), &super::Acl::private("owner")).await.unwrap();
+
+        storage
+    }
+}
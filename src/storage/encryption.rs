@@ -0,0 +1,328 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    crypto::random_bytes,
+    storage::{CustomerKey, ObjectReader, Result, ServerSideEncryption, Storage, StorageError},
+};
+
+use super::cipher::{DataKey, DecryptingReader};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Bytes of the random salt a customer key is hashed with
+const SALT_SIZE: usize = 16;
+
+
+/// The key a body being written is encrypted with, and what to store to
+/// find it again on reads
+pub(super) struct BodyKey {
+    pub encryption: ServerSideEncryption,
+    pub key: DataKey,
+    /// The key sealed with the master key
+    pub sealed_key: Option<Vec<u8>>,
+    /// Salted hash of the customer key the key is derived from
+    pub customer_key_hash: Option<Vec<u8>>,
+}
+
+
+/// Encrypted object bodies are stored in chunks sealed with a data key of
+/// their own, see `cipher.rs`. The data key is kept in the database sealed
+/// with the master key from the configuration, so neither the disks nor the
+/// database alone give away a body.
+///
+/// With SSE-C the data key is derived from the customer's key and the salt
+/// its hash is stored with instead, so it can't be had without the client.
+impl Storage {
+
+    pub async fn get_bucket_encryption(&self, bucket: &str) -> Result<ServerSideEncryption> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+
+        record.encryption.ok_or_else(|| StorageError::NoEncryptionConfiguration(bucket.to_string()))
+    }
+
+
+    /// Encrypt objects written to the bucket from now on unless a request
+    /// asks otherwise. Needs a master key.
+    pub async fn put_bucket_encryption(&self, bucket: &str, encryption: ServerSideEncryption) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+        self.master_key()?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_encryption(record.id, Some(encryption)).await?;
+
+        Ok(())
+    }
+
+
+    /// Stop encrypting new objects by default, those already encrypted stay so
+    pub async fn delete_bucket_encryption(&self, bucket: &str) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_encryption(record.id, None).await?;
+
+        Ok(())
+    }
+
+
+    /// The key to encrypt a new body with: derived from `customer_key` if
+    /// given, else a fresh one if `encryption` asks for it
+    pub(super) fn new_body_key(
+        &self,
+        encryption: Option<ServerSideEncryption>,
+        customer_key: Option<&CustomerKey>,
+    ) -> Result<Option<BodyKey>> {
+        if let Some(customer_key) = customer_key {
+            let hash = customer_key.salted_hash();
+
+            return Ok(Some(BodyKey {
+                encryption: ServerSideEncryption::CustomerKey,
+                key: customer_key.data_key(&hash),
+                sealed_key: None,
+                customer_key_hash: Some(hash),
+            }));
+        }
+
+        match encryption {
+            Some(ServerSideEncryption::Aes256) => {
+                let (key, sealed) = self.new_data_key()?;
+
+                Ok(Some(BodyKey {
+                    encryption: ServerSideEncryption::Aes256,
+                    key,
+                    sealed_key: Some(sealed),
+                    customer_key_hash: None,
+                }))
+            }
+            Some(ServerSideEncryption::CustomerKey) => Err(StorageError::CustomerKeyRequired),
+            None => Ok(None),
+        }
+    }
+
+
+    /// The key a stored body is encrypted with, from the data key sealed
+    /// with it or the customer key matching its hash, `None` for plaintext
+    pub(super) fn body_key(
+        &self,
+        sealed_key: Option<&[u8]>,
+        customer_key_hash: Option<&[u8]>,
+        customer_key: Option<&CustomerKey>,
+    ) -> Result<Option<DataKey>> {
+        check_customer_key(customer_key_hash, customer_key)?;
+
+        match (customer_key_hash, customer_key, sealed_key) {
+            (Some(hash), Some(customer_key), _) => Ok(Some(customer_key.data_key(hash))),
+            (_, _, Some(sealed)) => self.open_data_key(sealed).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+
+    /// A fresh data key and the same key sealed to be stored
+    pub(super) fn new_data_key(&self) -> Result<(DataKey, Vec<u8>)> {
+        let key = random_bytes(32);
+        let sealed = self.master_key()?.seal(&key);
+
+        Ok((DataKey::from_bytes(&key).expect("data keys are 32 bytes"), sealed))
+    }
+
+
+    pub(super) fn open_data_key(&self, sealed: &[u8]) -> Result<DataKey> {
+        self.master_key()?
+            .open(sealed)
+            .and_then(|key| DataKey::from_bytes(&key))
+            .ok_or_else(|| std::io::Error::other("cannot decrypt a data key, was the master key changed?").into())
+    }
+
+
+    /// Read a body of `size` bytes, or the inclusive range of it, decrypting
+    /// it with `key` if it is encrypted
+    pub(super) async fn open_body(
+        &self,
+        storage_path: &str,
+        key: Option<DataKey>,
+        size: u64,
+        range: Option<(u64, u64)>,
+    ) -> Result<ObjectReader> {
+        let Some(key) = key else {
+            return Ok(self.backend().get(storage_path, range).await?);
+        };
+
+        let reader = self.backend().get(storage_path, DecryptingReader::encrypted_range(size, range)).await?;
+
+        Ok(Box::pin(DecryptingReader::new(reader, key, size, range)))
+    }
+}
+
+
+/// The ETag of a body written with `body_key`: its md5, unless it is under a
+/// customer key. Like S3 those get an opaque one, an md5 of the plaintext
+/// would fingerprint it for anyone listing the bucket.
+pub(super) fn body_etag(body_key: Option<&BodyKey>, md5: &str) -> String {
+    match body_key {
+        Some(key) if key.encryption == ServerSideEncryption::CustomerKey => hex::encode(random_bytes(16)),
+        _ => md5.to_string(),
+    }
+}
+
+
+/// Check the customer key a request gives against the salted hash of the one
+/// the body is encrypted with. Keys have to be given exactly for SSE-C bodies.
+pub(super) fn check_customer_key(customer_key_hash: Option<&[u8]>, customer_key: Option<&CustomerKey>) -> Result<()> {
+    match (customer_key_hash, customer_key) {
+        (Some(hash), Some(customer_key)) if customer_key.matches(hash) => Ok(()),
+        (Some(_), Some(_)) => Err(StorageError::CustomerKeyMismatch),
+        (Some(_), None) => Err(StorageError::CustomerKeyRequired),
+        (None, Some(_)) => Err(StorageError::CustomerKeyNotApplicable),
+        (None, None) => Ok(()),
+    }
+}
+
+
+impl CustomerKey {
+    /// A random salt followed by the hash of the key under it
+    fn salted_hash(&self) -> Vec<u8> {
+        let salt = random_bytes(SALT_SIZE);
+        let hash = self.mac(&salt, b"hash").finalize().into_bytes();
+
+        [salt.as_slice(), &hash].concat()
+    }
+
+
+    /// Whether this is the key `salted_hash` was taken of, in constant time
+    fn matches(&self, salted_hash: &[u8]) -> bool {
+        salted_hash.len() > SALT_SIZE
+            && self.mac(&salted_hash[..SALT_SIZE], b"hash").verify_slice(&salted_hash[SALT_SIZE..]).is_ok()
+    }
+
+
+    /// The data key of the body stored with `salted_hash`. Deriving it with
+    /// the salt keeps bodies encrypted under the same customer key apart.
+    fn data_key(&self, salted_hash: &[u8]) -> DataKey {
+        let key = self.mac(&salted_hash[..SALT_SIZE], b"data key").finalize().into_bytes();
+
+        DataKey::from_bytes(&key).expect("HMAC-SHA256 gives 32 bytes")
+    }
+
+
+    fn mac(&self, salt: &[u8], purpose: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(purpose);
+        mac.update(salt);
+        mac
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    use crate::storage::{ListObjectsOptions, ObjectMetadata, PutObjectOptions};
+
+    const BODY: &[u8] = b"hello";
+    const BODY_MD5: &str = "5d41402abc4b2a76b9719d911017c592";
+
+    fn customer_key(byte: u8) -> CustomerKey {
+        CustomerKey::new(vec![byte; 32]).unwrap()
+    }
+
+    async fn put(storage: &Storage, key: &str, customer_key: Option<CustomerKey>) -> ObjectMetadata {
+        let options = PutObjectOptions { customer_key, ..Default::default() };
+        storage.put_object("bucket", key, BODY, options).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn customer_key_objects_get_opaque_etags() {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        assert_eq!(put(&storage, "plain", None).await.etag, BODY_MD5);
+
+        let first = put(&storage, "sse-c", Some(customer_key(1))).await;
+        assert_ne!(first.etag, BODY_MD5);
+        assert_eq!(first.etag.len(), 32);
+
+        // the same body under the same key doesn't give it away either
+        let second = put(&storage, "sse-c", Some(customer_key(1))).await;
+        assert_ne!(second.etag, first.etag);
+
+        let options = ListObjectsOptions {
+            prefix: String::new(),
+            delimiter: None,
+            from: None,
+            max_keys: 1000,
+            fetch_owner: false,
+        };
+        let listing = storage.list_objects("bucket", &options).await.unwrap();
+        let etags: Vec<_> = listing.objects.iter().map(|o| (o.key.as_str(), o.etag.as_str())).collect();
+        assert_eq!(etags, [("plain", BODY_MD5), ("sse-c", second.etag.as_str())]);
+    }
+
+    async fn read(storage: &Storage, key: &str, customer_key: Option<&CustomerKey>) -> Result<Vec<u8>> {
+        let mut content = storage.get_object("bucket", key, None, None, customer_key).await?;
+        let mut data = Vec::new();
+        content.reader.read_to_end(&mut data).await?;
+
+        Ok(data)
+    }
+
+    #[test]
+    fn checks_customer_keys_against_their_hash() {
+        let key = customer_key(1);
+        let hash = key.salted_hash();
+
+        assert!(key.matches(&hash));
+        assert!(!customer_key(2).matches(&hash));
+        // salted, the same key hashes differently every time
+        assert_ne!(key.salted_hash(), hash);
+        assert!(!key.matches(&hash[..SALT_SIZE]));
+
+        check_customer_key(Some(&hash), Some(&key)).unwrap();
+        check_customer_key(None, None).unwrap();
+        assert!(matches!(
+            check_customer_key(Some(&hash), Some(&customer_key(2))),
+            Err(StorageError::CustomerKeyMismatch),
+        ));
+        assert!(matches!(check_customer_key(Some(&hash), None), Err(StorageError::CustomerKeyRequired)));
+        assert!(matches!(check_customer_key(None, Some(&key)), Err(StorageError::CustomerKeyNotApplicable)));
+    }
+
+    #[tokio::test]
+    async fn reads_need_the_right_customer_key() {
+        let storage = Storage::in_memory_with_bucket().await;
+        let key = customer_key(1);
+
+        put(&storage, "sse-c", Some(key.clone())).await;
+        put(&storage, "plain", None).await;
+
+        assert_eq!(read(&storage, "sse-c", Some(&key)).await.unwrap(), BODY);
+        assert_eq!(read(&storage, "plain", None).await.unwrap(), BODY);
+
+        assert!(matches!(
+            read(&storage, "sse-c", Some(&customer_key(2))).await,
+            Err(StorageError::CustomerKeyMismatch),
+        ));
+        assert!(matches!(read(&storage, "sse-c", None).await, Err(StorageError::CustomerKeyRequired)));
+        assert!(matches!(read(&storage, "plain", Some(&key)).await, Err(StorageError::CustomerKeyNotApplicable)));
+
+        // HEAD is held to the same
+        assert!(storage.head_object("bucket", "sse-c", None, Some(&key)).await.is_ok());
+        assert!(matches!(
+            storage.head_object("bucket", "sse-c", None, Some(&customer_key(2))).await,
+            Err(StorageError::CustomerKeyMismatch),
+        ));
+        assert!(matches!(
+            storage.head_object("bucket", "sse-c", None, None).await,
+            Err(StorageError::CustomerKeyRequired),
+        ));
+        assert!(matches!(
+            storage.head_object("bucket", "plain", None, Some(&key)).await,
+            Err(StorageError::CustomerKeyNotApplicable),
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    crypto,
+    db::PolicyTarget,
+    storage::{GroupInfo, Result, Storage, StorageError, UserInfo},
+};
+
+/// Longest user or group name, as in IAM
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Longest policy name, as in IAM
+const MAX_POLICY_NAME_LENGTH: usize = 128;
+
+
+impl Storage {
+
+    pub async fn create_user(&self, name: &str) -> Result<UserInfo> {
+        validate_name("user", name, MAX_NAME_LENGTH)?;
+
+        // 64 hex digits like the canonical user ids of AWS
+        let canonical_id = hex::encode(crypto::random_bytes(32));
+        self.db().create_user(name, &canonical_id).await?;
+
+        Ok(self.db().get_user(name).await?)
+    }
+
+
+    /// Delete a user, its access keys stop working right away
+    pub async fn delete_user(&self, name: &str) -> Result<()> {
+        Ok(self.db().delete_user(name).await?)
+    }
+
+
+    pub async fn get_user(&self, name: &str) -> Result<UserInfo> {
+        Ok(self.db().get_user(name).await?)
+    }
+
+
+    pub async fn list_users(&self) -> Result<Vec<UserInfo>> {
+        Ok(self.db().list_users().await?)
+    }
+
+
+    pub async fn create_group(&self, name: &str) -> Result<GroupInfo> {
+        validate_name("group", name, MAX_NAME_LENGTH)?;
+
+        self.db().create_group(name).await?;
+
+        Ok(self.db().get_group(name).await?)
+    }
+
+
+    pub async fn delete_group(&self, name: &str) -> Result<()> {
+        Ok(self.db().delete_group(name).await?)
+    }
+
+
+    pub async fn get_group(&self, name: &str) -> Result<GroupInfo> {
+        Ok(self.db().get_group(name).await?)
+    }
+
+
+    pub async fn list_groups(&self) -> Result<Vec<GroupInfo>> {
+        Ok(self.db().list_groups().await?)
+    }
+
+
+    pub async fn add_group_member(&self, group: &str, user: &str) -> Result<()> {
+        Ok(self.db().add_group_member(group, user).await?)
+    }
+
+
+    pub async fn remove_group_member(&self, group: &str, user: &str) -> Result<()> {
+        Ok(self.db().remove_group_member(group, user).await?)
+    }
+
+
+    /// Attach a policy document to a user, replacing one of the same name.
+    /// The document is stored as given, callers check it parses.
+    pub async fn put_user_policy(&self, user: &str, name: &str, document: &str) -> Result<()> {
+        validate_name("policy", name, MAX_POLICY_NAME_LENGTH)?;
+
+        Ok(self.db().put_policy(PolicyTarget::User, user, name, document).await?)
+    }
+
+
+    pub async fn get_user_policy(&self, user: &str, name: &str) -> Result<String> {
+        Ok(self.db().get_policy(PolicyTarget::User, user, name).await?)
+    }
+
+
+    pub async fn delete_user_policy(&self, user: &str, name: &str) -> Result<()> {
+        Ok(self.db().delete_policy(PolicyTarget::User, user, name).await?)
+    }
+
+
+    /// Attach a policy document to a group, it applies to every member
+    pub async fn put_group_policy(&self, group: &str, name: &str, document: &str) -> Result<()> {
+        validate_name("policy", name, MAX_POLICY_NAME_LENGTH)?;
+
+        Ok(self.db().put_policy(PolicyTarget::Group, group, name, document).await?)
+    }
+
+
+    pub async fn get_group_policy(&self, group: &str, name: &str) -> Result<String> {
+        Ok(self.db().get_policy(PolicyTarget::Group, group, name).await?)
+    }
+
+
+    pub async fn delete_group_policy(&self, group: &str, name: &str) -> Result<()> {
+        Ok(self.db().delete_policy(PolicyTarget::Group, group, name).await?)
+    }
+
+
+    /// The policy documents of a user and of the groups it is in
+    pub async fn user_policy_documents(&self, user: &str) -> Result<Vec<String>> {
+        Ok(self.db().user_policy_documents(user).await?)
+    }
+
+
+    /// Give a user an access key, whose secret was sealed with the master key
+    pub async fn create_access_key(&self, user: &str, access_key_id: &str, sealed_secret: &[u8]) -> Result<()> {
+        Ok(self.db().create_access_key(user, access_key_id, sealed_secret).await?)
+    }
+
+
+    pub async fn delete_access_key(&self, access_key_id: &str) -> Result<()> {
+        Ok(self.db().delete_access_key(access_key_id).await?)
+    }
+
+
+    /// The name and canonical id of the user owning an access key and the
+    /// key's sealed secret, if it exists
+    pub async fn get_access_key(&self, access_key_id: &str) -> Result<Option<(String, String, Vec<u8>)>> {
+        Ok(self.db().get_access_key(access_key_id).await?)
+    }
+}
+
+
+/// Names are limited to the characters IAM allows: letters, digits and `+=,.@_-`
+fn validate_name(kind: &str, name: &str, max_length: usize) -> Result<()> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "+=,.@_-".contains(c);
+
+    if name.is_empty() || name.len() > max_length || !name.chars().all(valid_char) {
+        return Err(StorageError::InvalidArgument(format!(
+            "The {kind} name must be 1 to {max_length} letters, digits or any of +=,.@_-"
+        )));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::{Acl, Quota};
+
+    #[tokio::test]
+    async fn recreated_users_dont_inherit_what_the_old_one_owned() {
+        let storage = Storage::in_memory().await;
+
+        let old = storage.create_user("alice").await.unwrap();
+        assert_eq!(old.canonical_id.len(), 64);
+
+        storage.create_bucket("bucket", Some(&old.canonical_id), &Acl::private(&old.canonical_id)).await.unwrap();
+        storage.put_owner_quota(&old.canonical_id, &Quota { max_objects: Some(1), max_size: None }).await.unwrap();
+
+        storage.delete_user("alice").await.unwrap();
+        let new = storage.create_user("alice").await.unwrap();
+
+        assert_ne!(new.canonical_id, old.canonical_id);
+        assert_eq!(storage.get_bucket_info("bucket").await.unwrap().owner, Some(old.canonical_id.clone()));
+        assert_eq!(storage.get_bucket_acl("bucket").await.unwrap().owner, old.canonical_id);
+
+        // the quota went with the user
+        assert_eq!(storage.get_owner_usage(&old.canonical_id).await.unwrap().quota, Quota::default());
+    }
+}
@@ -0,0 +1,477 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    db::{BucketRecord, ObjectRecord},
+    error::DbError,
+    storage::{BucketVersioning, LifecycleConfiguration, LifecycleRule, MultipartUpload, Result, Storage, StorageError},
+};
+
+use super::object::new_version_id;
+
+/// Rules a bucket can have, same as S3
+const MAX_RULES: usize = 1000;
+
+const MAX_RULE_ID_LENGTH: usize = 255;
+
+/// Rows fetched from the database per query while applying a rule
+const LIFECYCLE_BATCH_SIZE: usize = 1000;
+
+
+impl Storage {
+
+    pub async fn get_bucket_lifecycle(&self, bucket: &str) -> Result<LifecycleConfiguration> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+
+        lifecycle_of(&record)?.ok_or_else(|| StorageError::NoLifecycleConfiguration(bucket.to_string()))
+    }
+
+
+    /// Replace the lifecycle rules of a bucket
+    pub async fn put_bucket_lifecycle(&self, bucket: &str, config: &LifecycleConfiguration) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+        validate_lifecycle(config)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_lifecycle(record.id, Some(&serde_json::to_string(config)?)).await?;
+
+        Ok(())
+    }
+
+
+    pub async fn delete_bucket_lifecycle(&self, bucket: &str) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_lifecycle(record.id, None).await?;
+
+        Ok(())
+    }
+
+
+    /// Start the background task applying lifecycle rules, once right away
+    /// and then every `interval`. It ends once `stop` is cancelled, after
+    /// finishing the rule it is applying.
+    pub fn spawn_lifecycle_worker(&self, interval: Duration, stop: CancellationToken) -> JoinHandle<()> {
+        let storage = self.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = stop.cancelled() => break,
+                }
+
+                if let Err(e) = storage.apply_lifecycle(&stop).await {
+                    tracing::error!("applying lifecycle rules failed: {}", e);
+                }
+            }
+        })
+    }
+
+
+    /// Apply the enabled lifecycle rules of every bucket once. A failing rule
+    /// is logged and the others still run, once `stop` is cancelled no
+    /// further rule is started.
+    pub async fn apply_lifecycle(&self, stop: &CancellationToken) -> Result<()> {
+        let now = Utc::now();
+
+        for bucket in self.db().list_buckets().await? {
+            let config = match lifecycle_of(&bucket) {
+                Ok(Some(config)) => config,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!("lifecycle configuration of bucket {} is unreadable: {}", bucket.name, e);
+                    continue;
+                }
+            };
+
+            let rules: Vec<&LifecycleRule> = config.rules.iter().filter(|r| r.enabled).collect();
+
+            // listed once for all rules, the ones a rule aborts are dropped from it
+            let mut uploads = if rules.iter().any(|r| r.abort_incomplete_upload_days.is_some()) {
+                match self.list_multipart_uploads(&bucket.name).await {
+                    Ok(uploads) => uploads,
+                    Err(e) => {
+                        tracing::error!("listing the uploads of bucket {} failed: {}", bucket.name, e);
+                        Vec::new()
+                    }
+                }
+            } else {
+                Vec::new()
+            };
+
+            for rule in rules {
+                if stop.is_cancelled() {
+                    return Ok(());
+                }
+
+                if let Err(e) = self.apply_rule(&bucket, rule, &mut uploads, now).await {
+                    tracing::error!("lifecycle rule {} of bucket {} failed: {}", rule.id, bucket.name, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// Apply one rule of `bucket` as of `now`, `uploads` being the in-progress
+    /// multipart uploads of the bucket
+    async fn apply_rule(
+        &self,
+        bucket: &BucketRecord,
+        rule: &LifecycleRule,
+        uploads: &mut Vec<MultipartUpload>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let (mut aborted, mut expired, mut removed) = (0, 0, 0);
+
+        if let Some(days) = rule.abort_incomplete_upload_days {
+            let cutoff = now - TimeDelta::days(days.into());
+
+            let mut i = 0;
+
+            while let Some(upload) = uploads.get(i) {
+                if upload.key.starts_with(&rule.prefix) && upload.initiated < cutoff {
+                    self.remove_upload(&upload.upload_id).await?;
+                    uploads.swap_remove(i);
+                    aborted += 1;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        if let Some(days) = rule.expiration_days {
+            let cutoff = now - TimeDelta::days(days.into());
+            let mut after = None;
+
+            loop {
+                let batch = self.db().expired_objects(bucket.id, &rule.prefix, cutoff, after, LIFECYCLE_BATCH_SIZE).await?;
+                let exhausted = batch.len() < LIFECYCLE_BATCH_SIZE;
+
+                for record in batch {
+                    after = Some((record.modified_at, record.id));
+
+                    if self.matches_tags(rule, &record).await? && self.expire_current(bucket, &record).await? {
+                        expired += 1;
+                    }
+                }
+
+                if exhausted {
+                    break;
+                }
+            }
+        }
+
+        if let Some(days) = rule.noncurrent_days {
+            let cutoff = now - TimeDelta::days(days.into());
+            let keep = rule.newer_noncurrent_versions.unwrap_or(0);
+            let mut after = (String::new(), 0);
+
+            loop {
+                let batch = self
+                    .db()
+                    .noncurrent_versions(bucket.id, &rule.prefix, cutoff, keep, (&after.0, after.1), LIFECYCLE_BATCH_SIZE)
+                    .await?;
+                let exhausted = batch.len() < LIFECYCLE_BATCH_SIZE;
+
+                for record in batch {
+                    after = (record.key.clone(), record.id);
+
+                    if self.matches_tags(rule, &record).await? && self.remove_version(bucket, &record).await? {
+                        removed += 1;
+                    }
+                }
+
+                if exhausted {
+                    break;
+                }
+            }
+        }
+
+        if rule.expired_object_delete_marker {
+            let mut after = (String::new(), 0);
+
+            loop {
+                let batch = self
+                    .db()
+                    .expired_delete_markers(bucket.id, &rule.prefix, (&after.0, after.1), LIFECYCLE_BATCH_SIZE)
+                    .await?;
+                let exhausted = batch.len() < LIFECYCLE_BATCH_SIZE;
+
+                for record in batch {
+                    after = (record.key.clone(), record.id);
+
+                    if self.remove_version(bucket, &record).await? {
+                        removed += 1;
+                    }
+                }
+
+                if exhausted {
+                    break;
+                }
+            }
+        }
+
+        if aborted + expired + removed > 0 {
+            tracing::info!(
+                "lifecycle rule {} of bucket {}: {} objects expired, {} versions removed, {} uploads aborted",
+                rule.id, bucket.name, expired, removed, aborted,
+            );
+        }
+
+        Ok(())
+    }
+
+
+    async fn matches_tags(&self, rule: &LifecycleRule, record: &ObjectRecord) -> Result<bool> {
+        if rule.tags.is_empty() {
+            return Ok(true);
+        }
+
+        let tags = self.db().get_object_tags(record.id).await?;
+
+        Ok(rule.tags.iter().all(|(k, v)| tags.get(k) == Some(v)))
+    }
+
+
+    /// Expire the current version of an object like a DeleteObject without a
+    /// version id would, unless it was replaced since it was picked. Returns
+    /// whether it was expired.
+    async fn expire_current(&self, bucket: &BucketRecord, record: &ObjectRecord) -> Result<bool> {
+        let _guard = self.blob_lock().lock().await;
+
+        match self.db().get_object(bucket.id, &record.key, None).await {
+            Ok(latest) if latest.id == record.id => {}
+            Ok(_) | Err(DbError::ObjectNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        let orphaned = match bucket.versioning {
+            BucketVersioning::Unversioned => {
+                let (_, orphaned) = self.db().delete_object(bucket.id, &record.key, &record.version_id).await?;
+                Vec::from_iter(orphaned)
+            }
+            versioning => {
+                let version_id = new_version_id(versioning);
+                let (_, orphaned) = self.db().create_delete_marker(bucket.id, &record.key, &version_id).await?;
+                orphaned
+            }
+        };
+
+        self.remove_orphans(&orphaned).await;
+
+        Ok(true)
+    }
+
+
+    /// Permanently remove a version, unless it became or stopped being the
+    /// current one since it was picked. Returns whether it was removed.
+    async fn remove_version(&self, bucket: &BucketRecord, record: &ObjectRecord) -> Result<bool> {
+        let _guard = self.blob_lock().lock().await;
+
+        match self.db().get_object(bucket.id, &record.key, Some(&record.version_id)).await {
+            Ok(current) if current.id == record.id && current.is_latest == record.is_latest => {}
+            Ok(_) | Err(DbError::ObjectNotFound(_)) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        let (_, orphaned) = self.db().delete_object(bucket.id, &record.key, &record.version_id).await?;
+        self.remove_orphans(Vec::from_iter(orphaned).as_slice()).await;
+
+        Ok(true)
+    }
+}
+
+
+fn lifecycle_of(bucket: &BucketRecord) -> Result<Option<LifecycleConfiguration>> {
+    match &bucket.lifecycle {
+        Some(json) => Ok(Some(serde_json::from_str(json)?)),
+        None => Ok(None),
+    }
+}
+
+
+fn validate_lifecycle(config: &LifecycleConfiguration) -> Result<()> {
+    let invalid = |message: &str| Err(StorageError::InvalidArgument(message.to_string()));
+
+    if config.rules.is_empty() || config.rules.len() > MAX_RULES {
+        return invalid("A lifecycle configuration must have between 1 and 1000 rules");
+    }
+
+    let mut ids = HashSet::new();
+
+    for rule in &config.rules {
+        if rule.id.len() > MAX_RULE_ID_LENGTH {
+            return invalid("ID length should not exceed allowed limit of 255");
+        }
+
+        if !ids.insert(rule.id.as_str()) {
+            return invalid("Rule ID must be unique. Found same ID for more than one rule");
+        }
+
+        let days = [rule.expiration_days, rule.noncurrent_days, rule.abort_incomplete_upload_days];
+
+        if days.iter().all(Option::is_none) && !rule.expired_object_delete_marker {
+            return invalid("At least one action needs to be specified in a rule");
+        }
+
+        if days.contains(&Some(0)) {
+            return invalid("Days in a lifecycle action must be a positive integer");
+        }
+
+        if rule.expiration_days.is_some() && rule.expired_object_delete_marker {
+            return invalid("ExpiredObjectDeleteMarker cannot be specified with Days or Date in a Lifecycle Expiration Policy");
+        }
+
+        if rule.newer_noncurrent_versions.is_some() && rule.noncurrent_days.is_none() {
+            return invalid("NewerNoncurrentVersions requires NoncurrentDays");
+        }
+
+        if !rule.tags.is_empty() && (rule.expired_object_delete_marker || rule.abort_incomplete_upload_days.is_some()) {
+            return invalid("Tag filters cannot be used with ExpiredObjectDeleteMarker or AbortIncompleteMultipartUpload");
+        }
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::{ListVersionsOptions, PutObjectOptions};
+
+    fn enabled_rule(id: &str) -> LifecycleRule {
+        LifecycleRule { id: id.to_string(), enabled: true, ..Default::default() }
+    }
+
+    async fn storage_with_bucket(versioning: Option<BucketVersioning>) -> (Storage, BucketRecord) {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        if let Some(versioning) = versioning {
+            storage.put_bucket_versioning("bucket", versioning).await.unwrap();
+        }
+
+        let bucket = storage.db().get_bucket("bucket").await.unwrap();
+
+        (storage, bucket)
+    }
+
+    async fn put(storage: &Storage, key: &str) {
+        storage.put_object("bucket", key, &b"data"[..], PutObjectOptions::default()).await.unwrap();
+    }
+
+    async fn versions(storage: &Storage) -> Vec<(String, bool)> {
+        let options = ListVersionsOptions { max_keys: 1000, ..Default::default() };
+        let listing = storage.list_object_versions("bucket", &options).await.unwrap();
+
+        listing.versions.into_iter().map(|v| (v.key, v.is_delete_marker)).collect()
+    }
+
+    #[test]
+    fn validates_rules() {
+        let config = |rules| LifecycleConfiguration { rules };
+        let expiring = |id: &str| LifecycleRule { expiration_days: Some(30), ..enabled_rule(id) };
+
+        assert!(validate_lifecycle(&config(vec![expiring("a"), expiring("b")])).is_ok());
+
+        for rules in [
+            vec![],
+            vec![enabled_rule("no action")],
+            vec![expiring("twice"), expiring("twice")],
+            vec![expiring(&"x".repeat(256))],
+            vec![LifecycleRule { expiration_days: Some(0), ..enabled_rule("zero days") }],
+            vec![LifecycleRule { expired_object_delete_marker: true, ..expiring("both") }],
+            vec![LifecycleRule { newer_noncurrent_versions: Some(2), ..expiring("no noncurrent days") }],
+            vec![LifecycleRule {
+                tags: vec![("k".to_string(), "v".to_string())],
+                abort_incomplete_upload_days: Some(1),
+                ..enabled_rule("tagged abort")
+            }],
+        ] {
+            assert!(matches!(validate_lifecycle(&config(rules)), Err(StorageError::InvalidArgument(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn expires_current_versions_under_the_prefix() {
+        let (storage, bucket) = storage_with_bucket(None).await;
+        put(&storage, "logs/a").await;
+        put(&storage, "keep").await;
+
+        let rule = LifecycleRule { prefix: "logs/".to_string(), expiration_days: Some(2), ..enabled_rule("logs") };
+
+        // not old enough yet
+        storage.apply_rule(&bucket, &rule, &mut Vec::new(), Utc::now() + TimeDelta::days(1)).await.unwrap();
+        assert_eq!(versions(&storage).await.len(), 2);
+
+        storage.apply_rule(&bucket, &rule, &mut Vec::new(), Utc::now() + TimeDelta::days(3)).await.unwrap();
+        assert_eq!(versions(&storage).await, vec![("keep".to_string(), false)]);
+    }
+
+    #[tokio::test]
+    async fn expiring_a_versioned_object_leaves_a_delete_marker() {
+        let (storage, bucket) = storage_with_bucket(Some(BucketVersioning::Enabled)).await;
+        put(&storage, "a").await;
+
+        let rule = LifecycleRule { expiration_days: Some(1), ..enabled_rule("expire") };
+        storage.apply_rule(&bucket, &rule, &mut Vec::new(), Utc::now() + TimeDelta::days(2)).await.unwrap();
+
+        assert_eq!(versions(&storage).await, vec![("a".to_string(), true), ("a".to_string(), false)]);
+
+        // with the version gone as well the marker is expired too
+        let rule = LifecycleRule { noncurrent_days: Some(1), ..enabled_rule("noncurrent") };
+        storage.apply_rule(&bucket, &rule, &mut Vec::new(), Utc::now() + TimeDelta::days(2)).await.unwrap();
+        assert_eq!(versions(&storage).await, vec![("a".to_string(), true)]);
+
+        let rule = LifecycleRule { expired_object_delete_marker: true, ..enabled_rule("markers") };
+        storage.apply_rule(&bucket, &rule, &mut Vec::new(), Utc::now()).await.unwrap();
+        assert!(versions(&storage).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_newest_noncurrent_versions() {
+        let (storage, bucket) = storage_with_bucket(Some(BucketVersioning::Enabled)).await;
+
+        for _ in 0..4 {
+            put(&storage, "a").await;
+        }
+
+        let rule = LifecycleRule { noncurrent_days: Some(1), newer_noncurrent_versions: Some(1), ..enabled_rule("noncurrent") };
+        storage.apply_rule(&bucket, &rule, &mut Vec::new(), Utc::now() + TimeDelta::days(2)).await.unwrap();
+
+        // the current version and the newest noncurrent one
+        assert_eq!(versions(&storage).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn aborts_old_uploads_once() {
+        let (storage, bucket) = storage_with_bucket(None).await;
+        storage.create_multipart_upload("bucket", "tmp/a", PutObjectOptions::default()).await.unwrap();
+        storage.create_multipart_upload("bucket", "b", PutObjectOptions::default()).await.unwrap();
+
+        let mut uploads = storage.list_multipart_uploads("bucket").await.unwrap();
+        let rule = LifecycleRule { prefix: "tmp/".to_string(), abort_incomplete_upload_days: Some(7), ..enabled_rule("uploads") };
+
+        storage.apply_rule(&bucket, &rule, &mut uploads, Utc::now() + TimeDelta::days(1)).await.unwrap();
+        assert_eq!(uploads.len(), 2);
+
+        storage.apply_rule(&bucket, &rule, &mut uploads, Utc::now() + TimeDelta::days(8)).await.unwrap();
+        assert_eq!(uploads.iter().map(|u| u.key.as_str()).collect::<Vec<_>>(), ["b"]);
+
+        let left = storage.list_multipart_uploads("bucket").await.unwrap();
+        assert_eq!(left.iter().map(|u| u.key.as_str()).collect::<Vec<_>>(), ["b"]);
+    }
+}
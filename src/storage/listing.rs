@@ -0,0 +1,361 @@
+use crate::{
+    db::successor,
+    error::DbError,
+    storage::{
+        ListObjectsOptions, ListVersionsOptions, ObjectListing, ObjectSummary, ObjectVersion, Result, Storage, StorageError,
+        VersionListing,
+        acl::object_acl,
+    },
+};
+
+/// Rows fetched from the database per query while filling a page
+const LIST_BATCH_SIZE: usize = 1000;
+
+
+impl Storage {
+
+    /// One page of a bucket's objects in key order, with keys containing the
+    /// delimiter after the prefix folded into common prefixes. A common prefix
+    /// counts against `max_keys` like an object does.
+    pub async fn list_objects(&self, bucket: &str, options: &ListObjectsOptions) -> Result<ObjectListing> {
+        self.validate_bucket_name(bucket)?;
+
+        let bucket_record = self.db().get_bucket(bucket).await?;
+        let prefix = options.prefix.as_str();
+        let delimiter = options.delimiter.as_deref().filter(|d| !d.is_empty());
+
+        let mut listing = ObjectListing::default();
+        let mut from = match &options.from {
+            Some(from) if from.as_str() > prefix => from.clone(),
+            _ => prefix.to_string(),
+        };
+
+        if options.max_keys == 0 {
+            return Ok(listing);
+        }
+
+        'pages: loop {
+            let batch = self.db().list_objects(bucket_record.id, prefix, &from, LIST_BATCH_SIZE).await?;
+            let exhausted = batch.len() < LIST_BATCH_SIZE;
+
+            for record in batch {
+                // the rest of the keys under the last common prefix
+                if listing.common_prefixes.last().is_some_and(|p| record.key.starts_with(p.as_str())) {
+                    continue;
+                }
+
+                if listing.objects.len() + listing.common_prefixes.len() == options.max_keys {
+                    listing.next = Some(from);
+                    break 'pages;
+                }
+
+                let common_prefix = delimiter.and_then(|d| {
+                    record.key[prefix.len()..].find(d).map(|i| &record.key[..prefix.len() + i + d.len()])
+                });
+
+                if let Some(common_prefix) = common_prefix {
+                    listing.common_prefixes.push(common_prefix.to_string());
+
+                    // a batch running out within the common prefix goes on after it
+                    match successor(common_prefix) {
+                        Some(next) => from = next,
+                        None => break 'pages,
+                    }
+
+                    continue;
+                }
+
+                // keys can't contain NUL, so this is the smallest string after the key
+                from = format!("{}\0", record.key);
+
+                listing.objects.push(ObjectSummary {
+                    owner: if options.fetch_owner { Some(object_acl(&bucket_record, &record)?.owner) } else { None },
+                    key: record.key,
+                    size: record.size as u64,
+                    etag: record.etag,
+                    modified_at: record.modified_at,
+                });
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(listing)
+    }
+
+    /// One page of every version in a bucket, delete markers included, in key
+    /// order and newest first within a key. Keys are folded into common
+    /// prefixes like `list_objects` does, which count against `max_keys`.
+    pub async fn list_object_versions(&self, bucket: &str, options: &ListVersionsOptions) -> Result<VersionListing> {
+        self.validate_bucket_name(bucket)?;
+
+        let bucket_record = self.db().get_bucket(bucket).await?;
+        let prefix = options.prefix.as_str();
+        let delimiter = options.delimiter.as_deref().filter(|d| !d.is_empty());
+        let key_marker = options.key_marker.as_deref().filter(|k| !k.is_empty());
+        let version_id_marker = options.version_id_marker.as_deref().filter(|v| !v.is_empty());
+
+        // resume right after the marker version, or after the marker key and
+        // everything folded into the same common prefix as it
+        let (mut from, mut before_id) = match (key_marker, version_id_marker) {
+            (None, Some(_)) => {
+                return Err(StorageError::InvalidArgument(
+                    "A version-id marker cannot be specified without a key marker.".to_string(),
+                ));
+            }
+            (Some(key), Some(version_id)) => {
+                self.validate_version_id(version_id)?;
+
+                match self.db().get_object(bucket_record.id, key, Some(version_id)).await {
+                    Ok(record) => (record.key, record.id),
+                    Err(DbError::ObjectNotFound(_)) => {
+                        return Err(StorageError::InvalidArgument("Invalid version id marker specified".to_string()));
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            (Some(key), None) => {
+                let folded = delimiter.and_then(|d| {
+                    key.strip_prefix(prefix)?.find(d).map(|i| &key[..prefix.len() + i + d.len()])
+                });
+
+                match folded {
+                    Some(common_prefix) => match successor(common_prefix) {
+                        Some(next) => (next, i64::MAX),
+                        None => return Ok(VersionListing::default()),
+                    },
+                    // keys can't contain NUL, so this is the smallest string after the key
+                    None => (format!("{key}\0"), i64::MAX),
+                }
+            }
+            (None, None) => (prefix.to_string(), i64::MAX),
+        };
+
+        if from.as_str() < prefix {
+            (from, before_id) = (prefix.to_string(), i64::MAX);
+        }
+
+        let mut listing = VersionListing::default();
+        let mut last = None;
+
+        if options.max_keys == 0 {
+            return Ok(listing);
+        }
+
+        'pages: loop {
+            let batch = self.db().list_object_versions(bucket_record.id, prefix, &from, before_id, LIST_BATCH_SIZE).await?;
+            let exhausted = batch.len() < LIST_BATCH_SIZE;
+
+            for record in batch {
+                // the rest of the versions under the last common prefix
+                if listing.common_prefixes.last().is_some_and(|p| record.key.starts_with(p.as_str())) {
+                    continue;
+                }
+
+                if listing.versions.len() + listing.common_prefixes.len() == options.max_keys {
+                    listing.next = last;
+                    break 'pages;
+                }
+
+                let common_prefix = delimiter.and_then(|d| {
+                    record.key[prefix.len()..].find(d).map(|i| &record.key[..prefix.len() + i + d.len()])
+                });
+
+                if let Some(common_prefix) = common_prefix {
+                    listing.common_prefixes.push(common_prefix.to_string());
+                    last = Some((common_prefix.to_string(), String::new()));
+
+                    // a batch running out within the common prefix goes on after it
+                    match successor(common_prefix) {
+                        Some(next) => (from, before_id) = (next, i64::MAX),
+                        None => break 'pages,
+                    }
+
+                    continue;
+                }
+
+                (from, before_id) = (record.key.clone(), record.id);
+                last = Some((record.key.clone(), record.version_id.clone()));
+
+                listing.versions.push(ObjectVersion {
+                    owner: object_acl(&bucket_record, &record)?.owner,
+                    key: record.key,
+                    version_id: record.version_id,
+                    is_latest: record.is_latest,
+                    is_delete_marker: record.is_delete_marker,
+                    size: record.size as u64,
+                    etag: record.etag,
+                    modified_at: record.modified_at,
+                });
+            }
+
+            if exhausted {
+                break;
+            }
+        }
+
+        Ok(listing)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::{BucketVersioning, PutObjectOptions};
+
+    async fn storage_with(keys: &[&str]) -> Storage {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        for key in keys {
+            storage.put_object("bucket", key, &b"data"[..], PutObjectOptions::default()).await.unwrap();
+        }
+
+        storage
+    }
+
+    fn objects(prefix: &str, delimiter: Option<&str>, from: Option<&str>, max_keys: usize) -> ListObjectsOptions {
+        ListObjectsOptions {
+            prefix: prefix.to_string(),
+            delimiter: delimiter.map(str::to_string),
+            from: from.map(str::to_string),
+            max_keys,
+            fetch_owner: false,
+        }
+    }
+
+    fn keys(listing: &ObjectListing) -> Vec<&str> {
+        listing.objects.iter().map(|o| o.key.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn folds_keys_into_common_prefixes() {
+        let storage = storage_with(&["a", "dir/x", "dir/y", "dir/sub/z", "e", "photos/2024/1", "photos/2025/1"]).await;
+
+        let listing = storage.list_objects("bucket", &objects("", Some("/"), None, 1000)).await.unwrap();
+        assert_eq!(keys(&listing), ["a", "e"]);
+        assert_eq!(listing.common_prefixes, ["dir/", "photos/"]);
+        assert_eq!(listing.next, None);
+
+        let listing = storage.list_objects("bucket", &objects("photos/", Some("/"), None, 1000)).await.unwrap();
+        assert!(listing.objects.is_empty());
+        assert_eq!(listing.common_prefixes, ["photos/2024/", "photos/2025/"]);
+
+        // no owner unless asked for
+        assert!(listing.objects.iter().all(|o| o.owner.is_none()));
+        let options = ListObjectsOptions { fetch_owner: true, ..objects("", None, None, 1000) };
+        let listing = storage.list_objects("bucket", &options).await.unwrap();
+        assert!(listing.objects.iter().all(|o| o.owner.as_deref() == Some("owner")));
+    }
+
+    #[tokio::test]
+    async fn folds_across_batches() {
+        let mut names: Vec<String> = (0..LIST_BATCH_SIZE + 10).map(|i| format!("dir/{i:05}")).collect();
+        names.push("last".to_string());
+
+        let storage = storage_with(&names.iter().map(String::as_str).collect::<Vec<_>>()).await;
+
+        let listing = storage.list_objects("bucket", &objects("", Some("/"), None, 1000)).await.unwrap();
+        assert_eq!(keys(&listing), ["last"]);
+        assert_eq!(listing.common_prefixes, ["dir/"]);
+    }
+
+    #[tokio::test]
+    async fn pages_resume_where_they_stopped() {
+        let storage = storage_with(&["a", "b", "dir/1", "dir/2", "e"]).await;
+
+        let page = storage.list_objects("bucket", &objects("", Some("/"), None, 3)).await.unwrap();
+        assert_eq!(keys(&page), ["a", "b"]);
+        assert_eq!(page.common_prefixes, ["dir/"]);
+
+        let from = page.next.as_deref();
+        assert!(from.is_some());
+
+        let page = storage.list_objects("bucket", &objects("", Some("/"), from, 3)).await.unwrap();
+        assert_eq!(keys(&page), ["e"]);
+        assert!(page.common_prefixes.is_empty());
+        assert_eq!(page.next, None);
+
+        // start-after as the handler turns it into a lower bound
+        let page = storage.list_objects("bucket", &objects("", None, Some("dir/1\0"), 1000)).await.unwrap();
+        assert_eq!(keys(&page), ["dir/2", "e"]);
+
+        // a bound before the prefix starts at the prefix
+        let page = storage.list_objects("bucket", &objects("dir/", None, Some("a"), 1000)).await.unwrap();
+        assert_eq!(keys(&page), ["dir/1", "dir/2"]);
+
+        assert!(storage.list_objects("bucket", &objects("", None, None, 0)).await.unwrap().objects.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_end_of_the_prefix() {
+        let storage = storage_with(&["dir", "dir/a", "dir/b", "dir0", "dis", "\u{10FFFF}", "\u{10FFFF}x"]).await;
+
+        let listing = storage.list_objects("bucket", &objects("dir/", None, None, 1000)).await.unwrap();
+        assert_eq!(keys(&listing), ["dir/a", "dir/b"]);
+
+        let listing = storage.list_objects("bucket", &objects("di", None, None, 1000)).await.unwrap();
+        assert_eq!(keys(&listing), ["dir", "dir/a", "dir/b", "dir0", "dis"]);
+
+        // nothing sorts after this prefix, there is no upper bound
+        let listing = storage.list_objects("bucket", &objects("\u{10FFFF}", None, None, 1000)).await.unwrap();
+        assert_eq!(keys(&listing), ["\u{10FFFF}", "\u{10FFFF}x"]);
+
+        assert_eq!(successor("dir/").as_deref(), Some("dir0"));
+        assert_eq!(successor("a\u{D7FF}").as_deref(), Some("a\u{E000}"));
+        assert_eq!(successor("a\u{10FFFF}").as_deref(), Some("b"));
+        assert_eq!(successor(""), None);
+    }
+
+    #[tokio::test]
+    async fn lists_versions_with_markers() {
+        let storage = storage_with(&[]).await;
+        storage.put_bucket_versioning("bucket", BucketVersioning::Enabled).await.unwrap();
+
+        for key in ["a", "a", "dir/x", "dir/y", "z"] {
+            storage.put_object("bucket", key, &b"data"[..], PutObjectOptions::default()).await.unwrap();
+        }
+        storage.delete_object("bucket", "z", None).await.unwrap();
+
+        let options = |key_marker: Option<&str>, version_id_marker: Option<&str>, max_keys| ListVersionsOptions {
+            prefix: String::new(),
+            delimiter: Some("/".to_string()),
+            key_marker: key_marker.map(str::to_string),
+            version_id_marker: version_id_marker.map(str::to_string),
+            max_keys,
+        };
+
+        let all = storage.list_object_versions("bucket", &options(None, None, 1000)).await.unwrap();
+        let entries: Vec<_> = all.versions.iter().map(|v| (v.key.as_str(), v.is_latest, v.is_delete_marker)).collect();
+        assert_eq!(entries, [("a", true, false), ("a", false, false), ("z", true, true), ("z", false, false)]);
+        assert_eq!(all.common_prefixes, ["dir/"]);
+
+        // stopping between the two versions of a
+        let page = storage.list_object_versions("bucket", &options(None, None, 1)).await.unwrap();
+        let (key, version_id) = page.next.unwrap();
+        assert_eq!((key.as_str(), version_id.as_str()), ("a", all.versions[0].version_id.as_str()));
+
+        let page = storage.list_object_versions("bucket", &options(Some(&key), Some(&version_id), 2)).await.unwrap();
+        assert_eq!(page.versions.len(), 1);
+        assert_eq!(page.versions[0].version_id, all.versions[1].version_id);
+        assert_eq!(page.common_prefixes, ["dir/"]);
+        assert_eq!(page.next, Some(("dir/".to_string(), String::new())));
+
+        // a key marker within a common prefix skips all of it
+        let page = storage.list_object_versions("bucket", &options(Some("dir/x"), None, 1000)).await.unwrap();
+        assert!(page.common_prefixes.is_empty());
+        assert!(page.versions.iter().all(|v| v.key == "z"));
+
+        assert!(matches!(
+            storage.list_object_versions("bucket", &options(None, Some(&version_id), 1000)).await,
+            Err(StorageError::InvalidArgument(_)),
+        ));
+        assert!(matches!(
+            storage.list_object_versions("bucket", &options(Some("a"), Some("nope"), 1000)).await,
+            Err(StorageError::InvalidArgument(_)),
+        ));
+    }
+}
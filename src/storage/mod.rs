@@ -3,4 +3,22 @@ mod types;
 mod validation;
 mod bucket;
 mod object;
+mod multipart;
+mod listing;
+mod blob;
+mod staging;
+mod backend;
 mod checksum;
+mod lifecycle;
+mod quota;
+mod iam;
+mod policy;
+mod acl;
+mod cipher;
+mod encryption;
+
+pub use self::core::Storage;
+pub use backend::{FilesystemBackend, MemoryBackend, StorageBackend};
+pub use checksum::Hasher;
+pub use types::*;
+pub use crate::error::{Result, StorageError};
@@ -0,0 +1,422 @@
+use chrono::Utc;
+use futures_util::{FutureExt, StreamExt, TryStreamExt, future::BoxFuture, stream};
+use md5::{Digest, Md5};
+use tokio::io::AsyncRead;
+use tokio_util::io::{ReaderStream, StreamReader};
+use uuid::Uuid;
+
+use crate::storage::{
+    CompletedPart, CustomerKey, MultipartUpload, ObjectMetadata, ObjectReader, PartInfo, PutObjectOptions, Result, Storage,
+    StorageError, UploadPartOptions,
+    cipher::DecryptingReader,
+    encryption::{body_etag, check_customer_key},
+};
+
+/// Part numbers S3 accepts
+pub const MAX_PART_NUMBER: u32 = 10_000;
+
+/// Every part but the last has to be at least this big
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// In-progress uploads are staged here, one directory per upload holding
+/// `upload.json` and `part-NNNNN` with a `part-NNNNN.json` next to each
+const MULTIPART_DIR: &str = ".metadata/multipart";
+
+const UPLOAD_FILE: &str = "upload.json";
+
+
+impl Storage {
+
+    /// Start a multipart upload for `bucket/key`. The content type, custom
+    /// metadata, tags, ACL and encryption in `options` are applied to the
+    /// object once the upload completes, its expected checksums are not used.
+    ///
+    /// Parts of an upload that ends up encrypted are encrypted as well. With
+    /// a customer key every part and the completion need the same key.
+    pub async fn create_multipart_upload(&self, bucket: &str, key: &str, options: PutObjectOptions) -> Result<MultipartUpload> {
+        self.validate_bucket_name(bucket)?;
+        self.validate_object_key(key)?;
+
+        let bucket_record = self.db().get_bucket(bucket).await?;
+
+        // this checks there is a master key up front, the parts get keys of their own
+        let body_key =
+            self.new_body_key(options.encryption.or(bucket_record.encryption), options.customer_key.as_ref())?;
+
+        let upload = MultipartUpload {
+            upload_id: Uuid::new_v4().simple().to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            initiated: Utc::now(),
+            content_type: options.content_type,
+            custom_metadata: options.custom_metadata,
+            tags: options.tags,
+            acl: options.acl,
+            encryption: body_key.as_ref().map(|k| k.encryption),
+            customer_key_hash: body_key.and_then(|k| k.customer_key_hash),
+        };
+
+        self.write_atomic(&upload_file_path(&upload.upload_id), &serde_json::to_vec(&upload)?).await?;
+
+        Ok(upload)
+    }
+
+
+    /// Stage one part, replacing any earlier upload of the same part number
+    pub async fn upload_part<R>(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        data: R,
+        options: UploadPartOptions,
+    ) -> Result<PartInfo>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        validate_part_number(part_number)?;
+        let upload = self.get_upload(bucket, key, upload_id).await?;
+        // a fresh key for every part written, so no two share nonces
+        check_customer_key(upload.customer_key_hash.as_deref(), options.customer_key.as_ref())?;
+        let part_key = self.new_body_key(upload.encryption, options.customer_key.as_ref())?;
+
+        let part_path = part_path(upload_id, part_number);
+        // a re-upload of the part only replaces the earlier one once it is complete
+        let staged = self.staging_path();
+        let (size, checksums) = self.write_file(&staged, data, &options.expected_checksums, part_key.as_ref().map(|k| &k.key)).await?;
+
+        self.commit_staged(&staged, &part_path).await?;
+
+        let part = PartInfo {
+            part_number,
+            etag: body_etag(part_key.as_ref(), &checksums.md5),
+            size,
+            last_modified: Utc::now(),
+            sealed_key: part_key.as_ref().and_then(|k| k.sealed_key.clone()),
+            customer_key_hash: part_key.and_then(|k| k.customer_key_hash),
+        };
+
+        self.write_atomic(&format!("{part_path}.json"), &serde_json::to_vec(&part)?).await?;
+
+        Ok(part)
+    }
+
+
+    /// The upload and its parts ordered by part number
+    pub async fn list_parts(&self, bucket: &str, key: &str, upload_id: &str) -> Result<(MultipartUpload, Vec<PartInfo>)> {
+        let upload = self.get_upload(bucket, key, upload_id).await?;
+
+        let mut parts = Vec::new();
+
+        for path in self.backend().list(&format!("{}/part-", upload_path(upload_id))).await? {
+            if path.ends_with(".json")
+                && let Some(data) = self.read_file(&path).await?
+            {
+                parts.push(serde_json::from_slice::<PartInfo>(&data)?);
+            }
+        }
+
+        parts.sort_by_key(|p| p.part_number);
+
+        Ok((upload, parts))
+    }
+
+
+    /// Assemble the named parts into the final object. Its ETag is the
+    /// composite md5 of the concatenated part md5s suffixed with the part count.
+    pub async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+        customer_key: Option<&CustomerKey>,
+    ) -> Result<ObjectMetadata> {
+        let (upload, uploaded) = self.list_parts(bucket, key, upload_id).await?;
+
+        if parts.is_empty() {
+            return Err(StorageError::InvalidPart("You must specify at least one part".to_string()));
+        }
+
+        if parts.windows(2).any(|w| w[0].part_number >= w[1].part_number) {
+            return Err(StorageError::InvalidPartOrder);
+        }
+
+        check_customer_key(upload.customer_key_hash.as_deref(), customer_key)?;
+
+        let mut composite = Md5::new();
+        let mut paths = Vec::with_capacity(parts.len());
+        let mut size = 0;
+
+        for (i, part) in parts.iter().enumerate() {
+            let info = uploaded
+                .iter()
+                .find(|p| p.part_number == part.part_number)
+                .ok_or_else(|| StorageError::InvalidPart(format!("Part {} was not uploaded", part.part_number)))?;
+
+            if !info.etag.eq_ignore_ascii_case(part.etag.trim_matches('"')) {
+                return Err(StorageError::InvalidPart(format!("ETag of part {} does not match", part.part_number)));
+            }
+
+            if info.size < MIN_PART_SIZE && i + 1 < parts.len() {
+                return Err(StorageError::PartTooSmall(part.part_number));
+            }
+
+            composite.update(hex::decode(&info.etag).map_err(|_| StorageError::InvalidPart(info.etag.clone()))?);
+            let part_key = self.body_key(info.sealed_key.as_deref(), info.customer_key_hash.as_deref(), customer_key)?;
+            paths.push((part_path(upload_id, part.part_number), info.size, part_key));
+            size += info.size;
+        }
+
+        // stream the parts one after the other into the final object. Each part is
+        // opened once the one before it is done; the futures are boxed up front as a
+        // closure in the stream trips up the Send check of the handlers up the stack.
+        let opens: Vec<BoxFuture<'static, std::io::Result<ReaderStream<ObjectReader>>>> = paths
+            .into_iter()
+            .map(|(path, size, part_key)| {
+                let backend = self.backend().clone();
+
+                async move {
+                    let reader = backend.get(&path, None).await?;
+
+                    Ok(ReaderStream::new(match part_key {
+                        Some(key) => Box::pin(DecryptingReader::new(reader, key, size, None)) as ObjectReader,
+                        None => reader,
+                    }))
+                }
+                .boxed()
+            })
+            .collect();
+
+        let body = stream::iter(opens).buffered(1).try_flatten();
+
+        let options = PutObjectOptions {
+            content_type: upload.content_type,
+            custom_metadata: upload.custom_metadata,
+            tags: upload.tags,
+            expected_checksums: Vec::new(),
+            acl: upload.acl,
+            encryption: upload.encryption,
+            customer_key: customer_key.cloned(),
+            content_length: Some(size),
+        };
+        let etag = format!("{}-{}", hex::encode(composite.finalize()), parts.len());
+
+        let metadata = self.store_object(bucket, key, StreamReader::new(Box::pin(body)), options, Some(etag)).await?;
+
+        // the object is already stored, failing here would report a completed upload as lost
+        if let Err(e) = self.remove_upload(upload_id).await {
+            tracing::warn!("failed to remove completed upload {upload_id}: {e}");
+        }
+
+        Ok(metadata)
+    }
+
+
+    /// Drop an upload and all of its staged parts
+    pub async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<()> {
+        self.get_upload(bucket, key, upload_id).await?;
+
+        self.remove_upload(upload_id).await
+    }
+
+
+    /// In-progress uploads of a bucket, ordered by key and then initiation time
+    pub async fn list_multipart_uploads(&self, bucket: &str) -> Result<Vec<MultipartUpload>> {
+        self.head_bucket(bucket).await?;
+
+        let mut uploads: Vec<MultipartUpload> = self
+            .all_multipart_uploads()
+            .await?
+            .into_iter()
+            .filter(|u| u.bucket == bucket)
+            .collect();
+
+        uploads.sort_by(|a, b| a.key.cmp(&b.key).then(a.initiated.cmp(&b.initiated)));
+
+        Ok(uploads)
+    }
+
+
+    pub(super) async fn all_multipart_uploads(&self) -> Result<Vec<MultipartUpload>> {
+        let mut uploads = Vec::new();
+
+        for path in self.backend().list(&format!("{MULTIPART_DIR}/")).await? {
+            if path.ends_with(&format!("/{UPLOAD_FILE}"))
+                && let Some(data) = self.read_file(&path).await?
+            {
+                uploads.push(serde_json::from_slice(&data)?);
+            }
+        }
+
+        Ok(uploads)
+    }
+
+
+    async fn get_upload(&self, bucket: &str, key: &str, upload_id: &str) -> Result<MultipartUpload> {
+        self.validate_bucket_name(bucket)?;
+
+        // upload ids end up in paths, only accept what we hand out
+        if upload_id.is_empty() || !upload_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(StorageError::UploadNotFound(upload_id.to_string()));
+        }
+
+        let data = self
+            .read_file(&upload_file_path(upload_id))
+            .await?
+            .ok_or_else(|| StorageError::UploadNotFound(upload_id.to_string()))?;
+
+        let upload: MultipartUpload = serde_json::from_slice(&data)?;
+
+        if upload.bucket != bucket || upload.key != key {
+            return Err(StorageError::UploadNotFound(upload_id.to_string()));
+        }
+
+        Ok(upload)
+    }
+
+
+    /// Remove an upload's bookkeeping file and all of its parts
+    pub(super) async fn remove_upload(&self, upload_id: &str) -> Result<()> {
+        for path in self.backend().list(&format!("{}/", upload_path(upload_id))).await? {
+            self.backend().delete(&path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+
+fn upload_path(upload_id: &str) -> String {
+    format!("{MULTIPART_DIR}/{upload_id}")
+}
+
+fn upload_file_path(upload_id: &str) -> String {
+    format!("{}/{UPLOAD_FILE}", upload_path(upload_id))
+}
+
+fn part_path(upload_id: &str, part_number: u32) -> String {
+    format!("{}/part-{part_number:05}", upload_path(upload_id))
+}
+
+
+fn validate_part_number(part_number: u32) -> Result<()> {
+    if !(1..=MAX_PART_NUMBER).contains(&part_number) {
+        return Err(StorageError::InvalidArgument(format!(
+            "Part number must be an integer between 1 and {MAX_PART_NUMBER}, inclusive"
+        )));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    async fn storage_with_upload(key: &str) -> (Storage, MultipartUpload) {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        let upload = storage.create_multipart_upload("bucket", key, PutObjectOptions::default()).await.unwrap();
+
+        (storage, upload)
+    }
+
+    async fn upload_part(storage: &Storage, upload: &MultipartUpload, part_number: u32, body: &[u8]) -> CompletedPart {
+        let part = storage
+            .upload_part("bucket", &upload.key, &upload.upload_id, part_number, body, UploadPartOptions::default())
+            .await
+            .unwrap();
+
+        CompletedPart { part_number, etag: format!("\"{}\"", part.etag) }
+    }
+
+    #[tokio::test]
+    async fn completes_from_parts() {
+        let (storage, upload) = storage_with_upload("big").await;
+        let first = vec![b'a'; MIN_PART_SIZE as usize];
+
+        let part1 = upload_part(&storage, &upload, 1, &first).await;
+        // a re-upload replaces the part
+        upload_part(&storage, &upload, 2, b"stale").await;
+        let part2 = upload_part(&storage, &upload, 2, b"tail").await;
+
+        let (_, parts) = storage.list_parts("bucket", "big", &upload.upload_id).await.unwrap();
+        assert_eq!(parts.iter().map(|p| (p.part_number, p.size)).collect::<Vec<_>>(), [(1, MIN_PART_SIZE), (2, 4)]);
+
+        let metadata = storage
+            .complete_multipart_upload("bucket", "big", &upload.upload_id, &[part1, part2], None)
+            .await
+            .unwrap();
+        assert_eq!(metadata.size, MIN_PART_SIZE + 4);
+        assert!(metadata.etag.ends_with("-2"));
+
+        let mut content = storage.get_object("bucket", "big", None, None, None).await.unwrap();
+        let mut body = Vec::new();
+        content.reader.read_to_end(&mut body).await.unwrap();
+        assert_eq!(body.len() as u64, MIN_PART_SIZE + 4);
+        assert!(body.ends_with(b"atail"));
+
+        // the upload is gone with its parts
+        assert!(storage.list_multipart_uploads("bucket").await.unwrap().is_empty());
+        assert!(storage.backend().list(&format!("{MULTIPART_DIR}/")).await.unwrap().is_empty());
+        assert!(matches!(
+            storage.list_parts("bucket", "big", &upload.upload_id).await,
+            Err(StorageError::UploadNotFound(_)),
+        ));
+    }
+
+    #[tokio::test]
+    async fn refuses_parts_that_do_not_fit() {
+        let (storage, upload) = storage_with_upload("key").await;
+        let part1 = upload_part(&storage, &upload, 1, b"small").await;
+        let part2 = upload_part(&storage, &upload, 2, b"small").await;
+
+        let complete = |parts: Vec<CompletedPart>| {
+            let storage = storage.clone();
+            let upload_id = upload.upload_id.clone();
+
+            async move { storage.complete_multipart_upload("bucket", "key", &upload_id, &parts, None).await }
+        };
+
+        assert!(matches!(complete(vec![]).await, Err(StorageError::InvalidPart(_))));
+        assert!(matches!(complete(vec![part2.clone(), part1.clone()]).await, Err(StorageError::InvalidPartOrder)));
+        assert!(matches!(complete(vec![part1.clone(), part2.clone()]).await, Err(StorageError::PartTooSmall(1))));
+
+        let wrong_etag = CompletedPart { part_number: 1, etag: "\"0123\"".to_string() };
+        assert!(matches!(complete(vec![wrong_etag]).await, Err(StorageError::InvalidPart(_))));
+
+        let missing = CompletedPart { part_number: 3, etag: part1.etag.clone() };
+        assert!(matches!(complete(vec![missing]).await, Err(StorageError::InvalidPart(_))));
+
+        // the last part may be small
+        complete(vec![part2]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn abort_removes_the_parts() {
+        let (storage, upload) = storage_with_upload("key").await;
+        upload_part(&storage, &upload, 1, b"data").await;
+
+        assert!(matches!(
+            storage.abort_multipart_upload("bucket", "other", &upload.upload_id).await,
+            Err(StorageError::UploadNotFound(_)),
+        ));
+
+        storage.abort_multipart_upload("bucket", "key", &upload.upload_id).await.unwrap();
+
+        assert!(storage.backend().list(&format!("{MULTIPART_DIR}/")).await.unwrap().is_empty());
+        assert!(matches!(
+            storage.abort_multipart_upload("bucket", "key", &upload.upload_id).await,
+            Err(StorageError::UploadNotFound(_)),
+        ));
+        assert!(matches!(
+            storage.upload_part("bucket", "key", &upload.upload_id, 1, &b"late"[..], UploadPartOptions::default()).await,
+            Err(StorageError::UploadNotFound(_)),
+        ));
+    }
+}
@@ -1,49 +1,448 @@
-use std::str::Bytes;
+use tokio::io::AsyncRead;
 
-use tokio::{fs, io::AsyncWriteExt};
+use chrono::Utc;
+use uuid::Uuid;
 
-use crate::storage::{Storage, Result, StorageError};
+use crate::{
+    error::DbError,
+    storage::{
+        BucketVersioning, ByteRange, ChecksumAlgorithm, Checksums, CustomerKey, DeletedObject, NULL_VERSION_ID,
+        ObjectContent, ObjectMetadata, PutObjectOptions, Storage, Result, StorageError,
+        checksum::HashingReader,
+        cipher::{DataKey, EncryptingReader},
+        encryption::{body_etag, check_customer_key},
+    },
+};
 
 impl Storage {
-    /// Put an object into storage
-    pub async fn put_object(&self, bucket: &str, key:&str, data: Bytes) -> Result<()> {
+    /// Put an object into storage, streaming `data` to the backend.
+    ///
+    /// If the body does not hash to one of `options.expected_checksums` the
+    /// object is removed again and `ChecksumMismatch` returned.
+    pub async fn put_object<R>(&self, bucket: &str, key:&str, data: R, options: PutObjectOptions) -> Result<ObjectMetadata>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        self.store_object(bucket, key, data, options, None).await
+    }
+
+
+    /// Write an object and its metadata. `etag` overrides the md5 of the
+    /// body, which multipart uploads use for their composite ETag. Bodies
+    /// under a customer key get a random ETag instead of their md5.
+    ///
+    /// With versioning enabled this adds a version, otherwise it replaces the
+    /// `null` version of the key.
+    ///
+    /// The body is encrypted with `options.customer_key`, or as
+    /// `options.encryption` or else the bucket's default encryption says.
+    /// Encrypted bodies are stored as they are, they can't be shared with
+    /// identical content.
+    pub(super) async fn store_object<R>(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: R,
+        options: PutObjectOptions,
+        etag: Option<String>,
+    ) -> Result<ObjectMetadata>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
         self.validate_bucket_name(bucket)?;
         self.validate_object_key(key)?;
 
-        let bucket_path = self.get_bucket_path(bucket);
+        let bucket_record = self.db().get_bucket(bucket).await?;
+        let version_id = new_version_id(bucket_record.versioning);
 
-        if !bucket_path.exists() {
-            return Err(StorageError::BucketNotFound(bucket.to_string()));
+        if let Some(size) = options.content_length {
+            self.check_quota_room(&bucket_record, key, &version_id, size).await?;
         }
 
-        let object_path = self.get_object_path(bucket, key);
+        let body_key =
+            self.new_body_key(options.encryption.or(bucket_record.encryption), options.customer_key.as_ref())?;
+
+        // stream to a staging file first, the blob it becomes is only known once it is hashed
+        let staged = self.staging_path();
+        let (size, checksums) =
+            self.write_file(&staged, data, &options.expected_checksums, body_key.as_ref().map(|k| &k.key)).await?;
+
+        let content_type = options.content_type.unwrap_or_else(|| {
+            mime_guess::from_path(key).first_or_octet_stream().to_string()
+        });
+
+        let acl = options.acl.as_ref().map(serde_json::to_string).transpose()?;
+        let now = Utc::now();
+
+        let metadata = ObjectMetadata {
+            key: key.to_string(),
+            version_id,
+            size,
+            content_type,
+            etag: etag.unwrap_or_else(|| body_etag(body_key.as_ref(), &checksums.md5)),
+            checksums,
+            created_at: now,
+            modified_at: now,
+            custom_metadata: options.custom_metadata,
+            tags: options.tags,
+            encryption: body_key.as_ref().map(|k| k.encryption),
+        };
+
+        let _guard = self.blob_lock().lock().await;
+
+        let placed = match body_key {
+            Some(_) => self.place_unique_blob(&staged).await.map(|path| (path, true)),
+            None => self.place_blob(&staged, &metadata.checksums.sha256).await,
+        };
+
+        let (storage_path, created) = match placed {
+            Ok(placed) => placed,
+            Err(e) => {
+                let _ = self.backend().delete(&staged).await;
+                return Err(e);
+            }
+        };
+
+        // the object only exists once it is recorded, a blob the database doesn't know of is removed
+        let sealed_key = body_key.as_ref().and_then(|k| k.sealed_key.as_deref());
+        let customer_key_hash = body_key.as_ref().and_then(|k| k.customer_key_hash.as_deref());
+
+        let (_, orphaned) = match self
+            .db()
+            .create_object(bucket_record.id, &metadata, &storage_path, acl.as_deref(), sealed_key, customer_key_hash)
+            .await
+        {
+            Ok(created) => created,
+            Err(e) => {
+                if created {
+                    self.remove_orphans(&[storage_path]).await;
+                }
+                return Err(e.into());
+            }
+        };
+
+        // the blob of a replaced null version may not be needed anymore
+        self.remove_orphans(&orphaned).await;
+
+        Ok(metadata)
+    }
+
+
+    /// Open an object for streaming, nothing is read until the reader is polled.
+    ///
+    /// With a `range` only that region of the object is read. Without a
+    /// `version_id` the latest version is read. Objects encrypted with a
+    /// customer key need that key.
+    pub async fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        range: Option<ByteRange>,
+        customer_key: Option<&CustomerKey>,
+    ) -> Result<ObjectContent> {
+        let stored = self.existing_object(bucket, key, version_id).await?;
+        let body_key =
+            self.body_key(stored.data_key.as_deref(), stored.customer_key_hash.as_deref(), customer_key)?;
+        let metadata = stored.metadata;
+
+        let range = match range {
+            Some(range) => Some(range.resolve(metadata.size).ok_or(StorageError::InvalidRange(metadata.size))?),
+            None => None,
+        };
+
+        let reader = self.open_body(&stored.storage_path, body_key, metadata.size, range).await?;
+
+        Ok(ObjectContent { metadata, range, reader })
+    }
+
 
-        if let Some(parent) = object_path.parent(){
-            fs::create_dir_all(parent).await?;
+    /// Metadata of an object, without reading it. Objects encrypted with a
+    /// customer key need that key, like for `get_object`.
+    pub async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        customer_key: Option<&CustomerKey>,
+    ) -> Result<ObjectMetadata> {
+        let stored = self.existing_object(bucket, key, version_id).await?;
+        check_customer_key(stored.customer_key_hash.as_deref(), customer_key)?;
+
+        Ok(stored.metadata)
+    }
+
+
+    /// Delete an object. Deleting a missing key is not an error, matching S3.
+    ///
+    /// A `version_id` removes that version for good. Without one a versioned
+    /// bucket gets a delete marker as the new latest version, while an
+    /// unversioned bucket drops the object.
+    pub async fn delete_object(&self, bucket: &str, key: &str, version_id: Option<&str>) -> Result<DeletedObject> {
+        self.validate_bucket_name(bucket)?;
+        self.validate_object_key(key)?;
+
+        if let Some(version_id) = version_id {
+            self.validate_version_id(version_id)?;
         }
 
-        //write file
-        let mut file = fs::File::create(&object_path).await?;
-        file.write_all(&data).await?;
-        file.flush().await?;
+        let bucket_record = self.db().get_bucket(bucket).await?;
+
+        let _guard = self.blob_lock().lock().await;
+
+        let (deleted, orphaned) = match (version_id, bucket_record.versioning) {
+            (None, BucketVersioning::Enabled | BucketVersioning::Suspended) => {
+                let version_id = new_version_id(bucket_record.versioning);
+                let (marker, orphaned) = self.db().create_delete_marker(bucket_record.id, key, &version_id).await?;
+
+                (DeletedObject { version_id: Some(marker.version_id), delete_marker: true }, orphaned)
+            }
+            (version_id, _) => {
+                match self.db().delete_object(bucket_record.id, key, version_id.unwrap_or(NULL_VERSION_ID)).await {
+                    Ok((record, orphaned)) => (
+                        DeletedObject {
+                            version_id: version_id.map(|_| record.version_id),
+                            delete_marker: record.is_delete_marker,
+                        },
+                        Vec::from_iter(orphaned),
+                    ),
+                    Err(DbError::ObjectNotFound(_)) => (
+                        DeletedObject { version_id: version_id.map(str::to_string), delete_marker: false },
+                        Vec::new(),
+                    ),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        };
+
+        self.remove_orphans(&orphaned).await;
 
-        // Determine content type
-        //todo
+        Ok(deleted)
     }
 
 
-    pub async fn get_object(&self, bucket: &str, key: &str) -> Result<(Bytes)> {
+    /// Stream `data` to `path` in the backend, hashing on the way through and
+    /// encrypting with `key` if given. Sizes and checksums are of the plaintext.
+    /// The file is removed again if writing fails or a digest in `expected`
+    /// doesn't match.
+    pub(super) async fn write_file<R>(
+        &self,
+        path: &str,
+        data: R,
+        expected: &[(ChecksumAlgorithm, String)],
+        key: Option<&DataKey>,
+    ) -> Result<(u64, Checksums)>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut reader = HashingReader::new(data);
+
+        match key {
+            Some(key) => self.backend().put(path, &mut EncryptingReader::new(&mut reader, key.clone())).await?,
+            None => self.backend().put(path, &mut reader).await?,
+        };
+
+        let (size, checksums) = reader.finish();
+
+        if let Err(e) = checksums.verify(expected) {
+            let _ = self.backend().delete(path).await;
+            return Err(e);
+        }
+
+        Ok((size, checksums))
+    }
+
+
+    /// A version of an object, the latest one without a `version_id`
+    async fn existing_object(&self, bucket: &str, key: &str, version_id: Option<&str>) -> Result<StoredObject> {
         self.validate_bucket_name(bucket)?;
         self.validate_object_key(key)?;
 
-        let object_path = self.get_object_path(bucket, key);
+        if let Some(version_id) = version_id {
+            self.validate_version_id(version_id)?;
+        }
+
+        let bucket_record = self.db().get_bucket(bucket).await?;
+
+        let record = self.db().get_object(bucket_record.id, key, version_id).await.map_err(|e| match (e, version_id) {
+            (DbError::ObjectNotFound(_), Some(version_id)) => StorageError::VersionNotFound(version_id.to_string()),
+            (e, _) => e.into(),
+        })?;
 
-        if !object_path.exists() {
-            return Err(StorageError::ObjectNotFound(key.to_string()));
+        if record.is_delete_marker {
+            return Err(StorageError::DeleteMarker { version_id: record.version_id, requested: version_id.is_some() });
         }
 
-       let data = fs::read(&object_path).await?;
+        let custom_metadata = self.db().get_object_metadata(record.id).await?;
+        let tags = self.db().get_object_tags(record.id).await?;
+
+        let metadata = ObjectMetadata {
+            key: record.key,
+            version_id: record.version_id,
+            size: record.size as u64,
+            content_type: record.content_type,
+            etag: record.etag,
+            checksums: Checksums {
+                md5: record.md5_checksum,
+                sha256: record.sha256_checksum,
+                sha1: record.sha1_checksum,
+                crc32: record.crc32_checksum,
+                crc32c: record.crc32c_checksum,
+            },
+            created_at: record.created_at,
+            modified_at: record.modified_at,
+            custom_metadata,
+            tags,
+            encryption: record.encryption,
+        };
+
+        Ok(StoredObject {
+            storage_path: record.storage_path,
+            data_key: record.data_key,
+            customer_key_hash: record.customer_key_hash,
+            metadata,
+        })
+    }
+}
+
+
+/// Where a version of an object is stored and what its key is found with
+struct StoredObject {
+    storage_path: String,
+    data_key: Option<Vec<u8>>,
+    customer_key_hash: Option<Vec<u8>>,
+    metadata: ObjectMetadata,
+}
+
+
+/// Id for a version written now: a fresh one while versioning is enabled,
+/// the `null` version otherwise
+pub(super) fn new_version_id(versioning: BucketVersioning) -> String {
+    match versioning {
+        BucketVersioning::Enabled => Uuid::new_v4().simple().to_string(),
+        BucketVersioning::Unversioned | BucketVersioning::Suspended => NULL_VERSION_ID.to_string(),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    async fn put(storage: &Storage, key: &str, body: &[u8]) -> ObjectMetadata {
+        storage.put_object("bucket", key, body, PutObjectOptions::default()).await.unwrap()
+    }
+
+    async fn read(storage: &Storage, key: &str, version_id: Option<&str>, range: Option<ByteRange>) -> Result<Vec<u8>> {
+        let mut content = storage.get_object("bucket", key, version_id, range, None).await?;
+
+        let mut body = Vec::new();
+        content.reader.read_to_end(&mut body).await?;
+
+        Ok(body)
+    }
+
+    #[tokio::test]
+    async fn puts_gets_and_deletes() {
+        let storage = Storage::in_memory_with_bucket().await;
+
+        let metadata = put(&storage, "dir/hello.txt", b"hello world").await;
+        assert_eq!(metadata.size, 11);
+        assert_eq!(metadata.etag, "5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(metadata.content_type, "text/plain");
+        assert_eq!(metadata.version_id, NULL_VERSION_ID);
+
+        assert_eq!(read(&storage, "dir/hello.txt", None, None).await.unwrap(), b"hello world");
+        assert_eq!(read(&storage, "dir/hello.txt", None, Some(ByteRange::FromTo(6, None))).await.unwrap(), b"world");
+        assert!(matches!(
+            read(&storage, "dir/hello.txt", None, Some(ByteRange::FromTo(11, None))).await,
+            Err(StorageError::InvalidRange(11)),
+        ));
+
+        let head = storage.head_object("bucket", "dir/hello.txt", None, None).await.unwrap();
+        assert_eq!(head.etag, metadata.etag);
+
+        // an overwrite replaces the object
+        put(&storage, "dir/hello.txt", b"bye").await;
+        assert_eq!(read(&storage, "dir/hello.txt", None, None).await.unwrap(), b"bye");
+
+        let deleted = storage.delete_object("bucket", "dir/hello.txt", None).await.unwrap();
+        assert!(!deleted.delete_marker);
+        assert!(matches!(read(&storage, "dir/hello.txt", None, None).await, Err(StorageError::ObjectNotFound(_))));
+
+        // deleting what isn't there succeeds like on S3
+        storage.delete_object("bucket", "dir/hello.txt", None).await.unwrap();
+
+        assert!(matches!(
+            storage.put_object("missing", "key", &b""[..], PutObjectOptions::default()).await,
+            Err(StorageError::BucketNotFound(_)),
+        ));
+    }
+
+    #[tokio::test]
+    async fn keeps_versions_behind_delete_markers() {
+        let storage = Storage::in_memory_with_bucket().await;
+        storage.put_bucket_versioning("bucket", BucketVersioning::Enabled).await.unwrap();
+
+        let first = put(&storage, "key", b"first").await;
+        let second = put(&storage, "key", b"second").await;
+        assert_ne!(first.version_id, second.version_id);
+
+        assert_eq!(read(&storage, "key", None, None).await.unwrap(), b"second");
+        assert_eq!(read(&storage, "key", Some(&first.version_id), None).await.unwrap(), b"first");
+
+        let marker = storage.delete_object("bucket", "key", None).await.unwrap();
+        assert!(marker.delete_marker);
+        let marker_id = marker.version_id.unwrap();
+
+        assert!(matches!(
+            read(&storage, "key", None, None).await,
+            Err(StorageError::DeleteMarker { requested: false, .. }),
+        ));
+        assert!(matches!(
+            read(&storage, "key", Some(&marker_id), None).await,
+            Err(StorageError::DeleteMarker { requested: true, .. }),
+        ));
+        assert_eq!(read(&storage, "key", Some(&second.version_id), None).await.unwrap(), b"second");
+
+        // removing the marker brings the object back
+        let removed = storage.delete_object("bucket", "key", Some(&marker_id)).await.unwrap();
+        assert!(removed.delete_marker);
+        assert_eq!(read(&storage, "key", None, None).await.unwrap(), b"second");
+
+        storage.delete_object("bucket", "key", Some(&second.version_id)).await.unwrap();
+        assert_eq!(read(&storage, "key", None, None).await.unwrap(), b"first");
+
+        assert!(matches!(
+            read(&storage, "key", Some(&second.version_id), None).await,
+            Err(StorageError::VersionNotFound(_)),
+        ));
+    }
+
+    #[tokio::test]
+    async fn suspended_versioning_replaces_the_null_version() {
+        let storage = Storage::in_memory_with_bucket().await;
+        put(&storage, "key", b"unversioned").await;
+
+        storage.put_bucket_versioning("bucket", BucketVersioning::Enabled).await.unwrap();
+        let versioned = put(&storage, "key", b"versioned").await;
+
+        storage.put_bucket_versioning("bucket", BucketVersioning::Suspended).await.unwrap();
+        let null = put(&storage, "key", b"suspended").await;
+        assert_eq!(null.version_id, NULL_VERSION_ID);
+
+        // the version written before versioning was enabled is gone
+        assert_eq!(read(&storage, "key", Some(NULL_VERSION_ID), None).await.unwrap(), b"suspended");
+        assert_eq!(read(&storage, "key", Some(&versioned.version_id), None).await.unwrap(), b"versioned");
 
-       Ok((Bytes::from(data)))
+        let marker = storage.delete_object("bucket", "key", None).await.unwrap();
+        assert_eq!(marker.version_id.as_deref(), Some(NULL_VERSION_ID));
+        assert!(matches!(
+            read(&storage, "key", Some(NULL_VERSION_ID), None).await,
+            Err(StorageError::DeleteMarker { requested: true, .. }),
+        ));
     }
 }
@@ -0,0 +1,49 @@
+use crate::{
+    error::DbError,
+    storage::{Result, Storage, StorageError},
+};
+
+
+impl Storage {
+
+    pub async fn get_bucket_policy(&self, bucket: &str) -> Result<String> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+
+        record.policy.ok_or_else(|| StorageError::NoBucketPolicy(bucket.to_string()))
+    }
+
+
+    /// Replace the policy of a bucket. The document is stored as given,
+    /// callers check it parses.
+    pub async fn put_bucket_policy(&self, bucket: &str, policy: &str) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_policy(record.id, Some(policy)).await?;
+
+        Ok(())
+    }
+
+
+    pub async fn delete_bucket_policy(&self, bucket: &str) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_policy(record.id, None).await?;
+
+        Ok(())
+    }
+
+
+    /// The policy of a bucket for authorizing a request, `None` when the
+    /// bucket has none or doesn't exist, which the handler reports
+    pub async fn bucket_policy(&self, bucket: &str) -> Result<Option<String>> {
+        match self.db().get_bucket(bucket).await {
+            Ok(record) => Ok(record.policy),
+            Err(DbError::BucketNotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    db::BucketRecord,
+    error::DbError,
+    storage::{NULL_VERSION_ID, OwnerUsage, Quota, Result, Storage, StorageError},
+};
+
+
+impl Storage {
+
+    /// Limit what a bucket may hold. Objects already stored are kept even if
+    /// the bucket is over the new quota, only further growth is refused.
+    pub async fn put_bucket_quota(&self, bucket: &str, quota: &Quota) -> Result<()> {
+        self.validate_bucket_name(bucket)?;
+        validate_quota(quota)?;
+
+        let record = self.db().get_bucket(bucket).await?;
+        self.db().set_bucket_quota(record.id, quota).await?;
+
+        Ok(())
+    }
+
+
+    /// Refuse a write of `size` bytes as `version_id` of `key` when the quota
+    /// already can't take it, a `null` version counting only what it adds
+    /// over the one it replaces
+    pub(super) async fn check_quota_room(&self, bucket: &BucketRecord, key: &str, version_id: &str, size: u64) -> Result<()> {
+        let replaced = match version_id {
+            NULL_VERSION_ID => match self.db().get_object(bucket.id, key, Some(NULL_VERSION_ID)).await {
+                Ok(record) => Some(record).filter(|r| !r.is_delete_marker),
+                Err(DbError::ObjectNotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            },
+            _ => None,
+        };
+
+        let (objects, bytes) = match replaced {
+            Some(record) => (0, size as i64 - record.size),
+            None => (1, size as i64),
+        };
+
+        Ok(self.db().check_quota_room(bucket.id, objects, bytes).await?)
+    }
+
+
+    /// What the buckets of `owner`, a canonical id, hold together, and their quota
+    pub async fn get_owner_usage(&self, owner: &str) -> Result<OwnerUsage> {
+        Ok(self.db().owner_usage(owner).await?)
+    }
+
+
+    /// Limit what the buckets of `owner` may hold together, on top of the
+    /// quota each of them has
+    pub async fn put_owner_quota(&self, owner: &str, quota: &Quota) -> Result<()> {
+        validate_quota(quota)?;
+
+        self.db().set_owner_quota(owner, quota).await?;
+
+        Ok(())
+    }
+}
+
+
+fn validate_quota(quota: &Quota) -> Result<()> {
+    // limits are stored as sqlite integers
+    if [quota.max_objects, quota.max_size].iter().flatten().any(|&n| n > i64::MAX as u64) {
+        return Err(StorageError::InvalidArgument(format!("Quota limits must not exceed {}", i64::MAX)));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::{AsyncRead, ReadBuf};
+
+    use super::*;
+
+    use crate::storage::{Acl, CompletedPart, PutObjectOptions, UploadPartOptions};
+
+    /// A body that fails if anything tries to read it
+    struct Unread;
+
+    impl AsyncRead for Unread {
+        fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, _: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Err(io::Error::other("the body was read")))
+        }
+    }
+
+    async fn put(storage: &Storage, bucket: &str, key: &str, body: &[u8]) -> Result<()> {
+        storage.put_object(bucket, key, body, PutObjectOptions::default()).await.map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn limits_the_objects_of_a_bucket() {
+        let storage = Storage::in_memory_with_bucket().await;
+        storage.put_bucket_quota("bucket", &Quota { max_objects: Some(2), max_size: None }).await.unwrap();
+
+        put(&storage, "bucket", "a", b"1").await.unwrap();
+        put(&storage, "bucket", "b", b"2").await.unwrap();
+        assert!(matches!(put(&storage, "bucket", "c", b"3").await, Err(StorageError::QuotaExceeded(_))));
+
+        // replacing an object adds none
+        put(&storage, "bucket", "a", b"again").await.unwrap();
+
+        storage.delete_object("bucket", "b", None).await.unwrap();
+        put(&storage, "bucket", "c", b"3").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn limits_the_size_of_a_bucket() {
+        let storage = Storage::in_memory_with_bucket().await;
+        storage.put_bucket_quota("bucket", &Quota { max_objects: None, max_size: Some(10) }).await.unwrap();
+
+        put(&storage, "bucket", "a", b"123456").await.unwrap();
+        assert!(matches!(put(&storage, "bucket", "b", b"123456").await, Err(StorageError::QuotaExceeded(_))));
+
+        // only what a replacement adds over the replaced object counts
+        put(&storage, "bucket", "a", b"1234567890").await.unwrap();
+        assert!(matches!(put(&storage, "bucket", "a", b"12345678901").await, Err(StorageError::QuotaExceeded(_))));
+
+        // shrinking is fine even over quota
+        storage.put_bucket_quota("bucket", &Quota { max_objects: None, max_size: Some(5) }).await.unwrap();
+        put(&storage, "bucket", "a", b"123456").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn limits_the_buckets_of_an_owner_together() {
+        let storage = Storage::in_memory().await;
+
+        for (bucket, owner) in [("one", "alice"), ("two", "alice"), ("other", "bob")] {
+            storage.create_bucket(bucket, Some(owner), &Acl::private(owner)).await.unwrap();
+        }
+
+        storage.put_owner_quota("alice", &Quota { max_objects: Some(3), max_size: Some(10) }).await.unwrap();
+
+        put(&storage, "one", "a", b"123456").await.unwrap();
+        assert!(matches!(put(&storage, "two", "a", b"123456").await, Err(StorageError::QuotaExceeded(_))));
+
+        put(&storage, "two", "a", b"1").await.unwrap();
+        put(&storage, "two", "b", b"1").await.unwrap();
+        assert!(matches!(put(&storage, "one", "b", b"1").await, Err(StorageError::QuotaExceeded(_))));
+
+        // other owners are not affected
+        put(&storage, "other", "a", b"12345678901").await.unwrap();
+
+        let usage = storage.get_owner_usage("alice").await.unwrap();
+        assert_eq!((usage.object_count, usage.total_size), (3, 8));
+    }
+
+    #[tokio::test]
+    async fn refuses_by_content_length_before_reading() {
+        let storage = Storage::in_memory_with_bucket().await;
+        storage.put_bucket_quota("bucket", &Quota { max_objects: None, max_size: Some(10) }).await.unwrap();
+
+        let options = PutObjectOptions { content_length: Some(11), ..Default::default() };
+        assert!(matches!(
+            storage.put_object("bucket", "key", Unread, options).await,
+            Err(StorageError::QuotaExceeded(_)),
+        ));
+
+        // with room the body is read
+        let options = PutObjectOptions { content_length: Some(10), ..Default::default() };
+        assert!(matches!(storage.put_object("bucket", "key", Unread, options).await, Err(StorageError::IoError(_))));
+    }
+
+    #[tokio::test]
+    async fn multipart_completion_counts_against_the_quota() {
+        let storage = Storage::in_memory_with_bucket().await;
+        let part_size = 5 * 1024 * 1024;
+        storage.put_bucket_quota("bucket", &Quota { max_objects: None, max_size: Some(part_size) }).await.unwrap();
+
+        let upload = storage.create_multipart_upload("bucket", "key", PutObjectOptions::default()).await.unwrap();
+        let mut parts = Vec::new();
+
+        for (part_number, body) in [(1, vec![0; part_size as usize]), (2, b"over".to_vec())] {
+            let part = storage
+                .upload_part("bucket", "key", &upload.upload_id, part_number, &body[..], UploadPartOptions::default())
+                .await
+                .unwrap();
+            parts.push(CompletedPart { part_number, etag: part.etag });
+        }
+
+        assert!(matches!(
+            storage.complete_multipart_upload("bucket", "key", &upload.upload_id, &parts, None).await,
+            Err(StorageError::QuotaExceeded(_)),
+        ));
+
+        // the upload is kept, completing it works once there is room
+        storage.put_bucket_quota("bucket", &Quota::default()).await.unwrap();
+        storage.complete_multipart_upload("bucket", "key", &upload.upload_id, &parts, None).await.unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use uuid::Uuid;
+
+use crate::storage::{Result, Storage};
+
+/// Where writes go before they are moved into place
+const STAGING_DIR: &str = ".metadata/staging";
+
+
+/// Everything is written to a staging file first and renamed into place once
+/// it is complete and durable, so a crash or a concurrent reader never sees a
+/// partial file and an overwrite never destroys what it replaces mid-write.
+///
+/// Whatever is left in staging was cut off by a crash or a shutdown and is
+/// removed on startup and shutdown.
+impl Storage {
+
+    /// Fresh path to stream a file to before it is renamed into place
+    pub(super) fn staging_path(&self) -> String {
+        format!("{STAGING_DIR}/{}", Uuid::new_v4().simple())
+    }
+
+
+    /// Remove staging files left behind by interrupted writes
+    pub(super) async fn clean_staging(&self) -> Result<()> {
+        let orphaned = self.backend().list(&format!("{STAGING_DIR}/")).await?;
+
+        for path in &orphaned {
+            self.backend().delete(path).await?;
+        }
+
+        if !orphaned.is_empty() {
+            tracing::info!("removed {} orphaned staging files", orphaned.len());
+        }
+
+        Ok(())
+    }
+
+
+    /// Move a staged file into place, the staged file is removed if that fails
+    pub(super) async fn commit_staged(&self, staged: &str, path: &str) -> Result<()> {
+        if let Err(e) = self.backend().rename(staged, path).await {
+            let _ = self.backend().delete(staged).await;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+
+    /// Replace a small file so readers see either the old or the new content
+    pub(super) async fn write_atomic(&self, path: &str, mut data: &[u8]) -> Result<()> {
+        let staged = self.staging_path();
+
+        self.backend().put(&staged, &mut data).await?;
+        self.commit_staged(&staged, path).await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::{ChecksumAlgorithm, PutObjectOptions, StorageError};
+
+    async fn staged(storage: &Storage) -> Vec<String> {
+        storage.backend().list(&format!("{STAGING_DIR}/")).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn removes_leftover_staging_files() {
+        let storage = Storage::in_memory().await;
+
+        for _ in 0..2 {
+            storage.backend().put(&storage.staging_path(), &mut &b"partial"[..]).await.unwrap();
+        }
+        assert_eq!(staged(&storage).await.len(), 2);
+
+        storage.clean_staging().await.unwrap();
+        assert!(staged(&storage).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_writes_leave_nothing_behind() {
+        let storage = Storage::in_memory_with_bucket().await;
+        storage.put_object("bucket", "key", &b"kept"[..], PutObjectOptions::default()).await.unwrap();
+
+        let options = PutObjectOptions {
+            expected_checksums: vec![(ChecksumAlgorithm::Md5, "00000000000000000000000000000000".to_string())],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            storage.put_object("bucket", "key", &b"replacement"[..], options).await,
+            Err(StorageError::ChecksumMismatch { .. }),
+        ));
+
+        // the object it was to replace is untouched
+        assert!(staged(&storage).await.is_empty());
+        assert_eq!(storage.backend().list(".blobs/").await.unwrap().len(), 1);
+        assert_eq!(storage.head_object("bucket", "key", None, None).await.unwrap().size, 4);
+    }
+
+    #[tokio::test]
+    async fn replaces_files_whole() {
+        let storage = Storage::in_memory().await;
+
+        storage.write_atomic("dir/file", b"old").await.unwrap();
+        storage.write_atomic("dir/file", b"new").await.unwrap();
+
+        assert_eq!(storage.read_file("dir/file").await.unwrap().as_deref(), Some(&b"new"[..]));
+        assert!(staged(&storage).await.is_empty());
+    }
+}
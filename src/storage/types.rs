@@ -1,26 +1,689 @@
+use std::{collections::HashMap, fmt, pin::Pin};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, Utc};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
+
+
+/// Hex digests of an object's body in every algorithm S3 supports. The CRCs
+/// are the big-endian bytes of the checksum.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Checksums {
     pub md5: String,
     pub sha256: String,
+    pub sha1: String,
+    pub crc32: String,
+    pub crc32c: String,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+    Sha1,
+    Crc32,
+    Crc32c,
+}
+
+
+/// Versioning state of a bucket. Once enabled it can only be suspended,
+/// never turned off again, same as S3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BucketVersioning {
+    /// Never configured, every key has just the `null` version
+    #[default]
+    Unversioned,
+    /// Every write adds a version
+    Enabled,
+    /// Writes replace the `null` version, earlier versions are kept
+    Suspended,
+}
+
+impl BucketVersioning {
+    /// How the state is stored and shown in `VersioningConfiguration`, empty when unversioned
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BucketVersioning::Unversioned => "",
+            BucketVersioning::Enabled => "Enabled",
+            BucketVersioning::Suspended => "Suspended",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "" => Some(BucketVersioning::Unversioned),
+            "Enabled" => Some(BucketVersioning::Enabled),
+            "Suspended" => Some(BucketVersioning::Suspended),
+            _ => None,
+        }
+    }
 }
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BucketInfo {
     pub name: String,
+    /// Canonical id of who created the bucket
+    pub owner: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// Every stored version counts, delete markers don't
+    pub object_count: usize,
+    /// Bytes of all stored versions
+    pub total_size: u64,
+    pub quota: Quota,
+}
+
+
+/// Limits on the object count and total size of a bucket, or of all buckets
+/// of an owner together, counted like `BucketInfo` counts them. `None` is no limit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    pub max_objects: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+
+/// What the buckets of one owner hold together, and their limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnerUsage {
+    pub owner: String,
     pub object_count: usize,
     pub total_size: u64,
+    pub quota: Quota,
+}
+
+
+/// A user of the IAM API, with the names of what is attached to it
+#[derive(Debug, Clone, Serialize)]
+pub struct UserInfo {
+    pub name: String,
+    /// What the user owns buckets and is granted ACLs by
+    pub canonical_id: String,
+    pub created_at: DateTime<Utc>,
+    pub groups: Vec<String>,
+    pub access_keys: Vec<String>,
+    pub policies: Vec<String>,
+}
+
+
+/// A group of users sharing the policies attached to the group
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupInfo {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub members: Vec<String>,
+    pub policies: Vec<String>,
+}
+
+
+/// How an object body is encrypted at rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServerSideEncryption {
+    /// AES-256-GCM under a key of its own, which is kept wrapped by the
+    /// master key. `AES256` in `x-amz-server-side-encryption`.
+    Aes256,
+    /// AES-256-GCM under a key derived from one the client gives with every
+    /// request, SSE-C. Only a salted hash of the client's key is kept.
+    CustomerKey,
+}
+
+impl ServerSideEncryption {
+    /// How it is stored, and for `Aes256` named in headers and bucket
+    /// encryption configurations
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServerSideEncryption::Aes256 => "AES256",
+            ServerSideEncryption::CustomerKey => "SSE-C",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "AES256" => Some(ServerSideEncryption::Aes256),
+            "SSE-C" => Some(ServerSideEncryption::CustomerKey),
+            _ => None,
+        }
+    }
+}
+
+
+/// An AES-256 key a client encrypts its object with, given in
+/// `x-amz-server-side-encryption-customer-key`. It is only held while
+/// serving the request.
+#[derive(Clone)]
+pub struct CustomerKey {
+    pub(super) key: Vec<u8>,
+}
+
+impl CustomerKey {
+    /// `None` unless `key` is 32 bytes
+    pub fn new(key: Vec<u8>) -> Option<Self> {
+        (key.len() == 32).then_some(Self { key })
+    }
+
+    /// Base64 md5 of the key, what `x-amz-server-side-encryption-customer-key-MD5` holds
+    pub fn md5(&self) -> String {
+        BASE64.encode(Md5::digest(&self.key))
+    }
+}
+
+/// Never print the key
+impl fmt::Debug for CustomerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomerKey(..)")
+    }
+}
+
+
+/// Access control list of a bucket or object version, kept for tools that
+/// rely on ACLs rather than policies. Owners are canonical ids: the generated
+/// id of a user, `root:` and the access key id for the root credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Acl {
+    pub owner: String,
+    pub grants: Vec<Grant>,
+}
+
+
+/// Canonical id owning what was created without credentials, with authentication off
+pub const DEFAULT_OWNER: &str = "filia";
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    pub grantee: Grantee,
+    pub permission: Permission,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Grantee {
+    /// A principal by canonical id
+    User(String),
+    /// Anyone, anonymous requests included
+    AllUsers,
+    /// Anyone signing requests with a valid access key
+    AuthenticatedUsers,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Permission {
+    Read,
+    Write,
+    ReadAcp,
+    WriteAcp,
+    FullControl,
+}
+
+
+impl Acl {
+
+    /// Only the owner has access, with full control
+    pub fn private(owner: &str) -> Self {
+        Self::canned(CannedAcl::Private, owner, None)
+    }
+
+
+    /// A canned ACL owned by `owner`. `bucket_owner` is the owner of the
+    /// bucket an object is written to, `None` for the ACL of a bucket.
+    pub fn canned(canned: CannedAcl, owner: &str, bucket_owner: Option<&str>) -> Self {
+        let grant = |grantee, permission| Grant { grantee, permission };
+        let mut grants = vec![grant(Grantee::User(owner.to_string()), Permission::FullControl)];
+
+        match canned {
+            CannedAcl::Private => {}
+            CannedAcl::PublicRead => grants.push(grant(Grantee::AllUsers, Permission::Read)),
+            CannedAcl::PublicReadWrite => {
+                grants.push(grant(Grantee::AllUsers, Permission::Read));
+                grants.push(grant(Grantee::AllUsers, Permission::Write));
+            }
+            CannedAcl::AuthenticatedRead => grants.push(grant(Grantee::AuthenticatedUsers, Permission::Read)),
+            CannedAcl::BucketOwnerFullControl => {
+                if let Some(bucket_owner) = bucket_owner.filter(|b| *b != owner) {
+                    grants.push(grant(Grantee::User(bucket_owner.to_string()), Permission::FullControl));
+                }
+            }
+        }
+
+        Self { owner: owner.to_string(), grants }
+    }
+
+
+    /// Whether the ACL grants `permission` to the principal with canonical id
+    /// `grantee`, `None` for anonymous requests. Owners may always read and
+    /// change the ACL, whatever it grants them.
+    pub fn allows(&self, permission: Permission, grantee: Option<&str>) -> bool {
+        if grantee == Some(self.owner.as_str()) && matches!(permission, Permission::ReadAcp | Permission::WriteAcp) {
+            return true;
+        }
+
+        self.grants.iter().any(|grant| {
+            let covers = grant.permission == permission || grant.permission == Permission::FullControl;
+
+            covers
+                && match &grant.grantee {
+                    Grantee::User(id) => grantee == Some(id.as_str()),
+                    Grantee::AllUsers => true,
+                    Grantee::AuthenticatedUsers => grantee.is_some(),
+                }
+        })
+    }
+}
+
+
+impl Permission {
+    /// How the permission is named in ACL XML and `x-amz-grant-*` headers
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Read => "READ",
+            Permission::Write => "WRITE",
+            Permission::ReadAcp => "READ_ACP",
+            Permission::WriteAcp => "WRITE_ACP",
+            Permission::FullControl => "FULL_CONTROL",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "READ" => Some(Permission::Read),
+            "WRITE" => Some(Permission::Write),
+            "READ_ACP" => Some(Permission::ReadAcp),
+            "WRITE_ACP" => Some(Permission::WriteAcp),
+            "FULL_CONTROL" => Some(Permission::FullControl),
+            _ => None,
+        }
+    }
+}
+
+
+/// The predefined ACLs of `x-amz-acl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CannedAcl {
+    Private,
+    PublicRead,
+    PublicReadWrite,
+    AuthenticatedRead,
+    /// For objects written to someone else's bucket, like `Private` on buckets
+    BucketOwnerFullControl,
+}
+
+
+impl CannedAcl {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "private" => Some(CannedAcl::Private),
+            "public-read" => Some(CannedAcl::PublicRead),
+            "public-read-write" => Some(CannedAcl::PublicReadWrite),
+            "authenticated-read" => Some(CannedAcl::AuthenticatedRead),
+            "bucket-owner-full-control" => Some(CannedAcl::BucketOwnerFullControl),
+            _ => None,
+        }
+    }
 }
 
 
+/// Version id of objects written while a bucket wasn't versioning them. There
+/// is at most one such version per key, each such write replaces it.
+pub const NULL_VERSION_ID: &str = "null";
+
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectMetadata {
     pub key: String,
+    /// `null` for objects written while the bucket wasn't versioning them
+    pub version_id: String,
     pub size: u64,
     pub content_type: String,
+    /// md5 of the body, or the composite md5 for multipart uploads. Random
+    /// for bodies under a customer key.
+    pub etag: String,
     pub checksums: Checksums,
     pub created_at: DateTime<Utc>,
     pub modified_at: DateTime<Utc>,
     pub custom_metadata: HashMap<String, String>,
+    pub tags: HashMap<String, String>,
+    /// `None` when stored in plaintext
+    #[serde(default)]
+    pub encryption: Option<ServerSideEncryption>,
+}
+
+
+/// What a client can set on an object when writing it
+#[derive(Debug, Clone, Default)]
+pub struct PutObjectOptions {
+    /// Guessed from the key's extension when not given
+    pub content_type: Option<String>,
+    /// `x-amz-meta-*` values, keyed without the prefix
+    pub custom_metadata: HashMap<String, String>,
+    /// `x-amz-tagging` key/value pairs
+    pub tags: HashMap<String, String>,
+    /// Hex digests the body has to hash to
+    pub expected_checksums: Vec<(ChecksumAlgorithm, String)>,
+    /// From `x-amz-acl` or `x-amz-grant-*`, the default when not given
+    pub acl: Option<Acl>,
+    /// From `x-amz-server-side-encryption`, the bucket default when not given
+    pub encryption: Option<ServerSideEncryption>,
+    /// From the `x-amz-server-side-encryption-customer-*` headers, takes
+    /// precedence over `encryption`
+    pub customer_key: Option<CustomerKey>,
+    /// Size of the body when known up front, lets an upload that would go
+    /// over quota be refused before it is read
+    pub content_length: Option<u64>,
+}
+
+
+/// What a client can give with a part of a multipart upload
+#[derive(Debug, Clone, Default)]
+pub struct UploadPartOptions {
+    /// Hex digests the part has to hash to
+    pub expected_checksums: Vec<(ChecksumAlgorithm, String)>,
+    /// The customer key of an upload encrypted with one
+    pub customer_key: Option<CustomerKey>,
+}
+
+
+/// What to list from a bucket, see `Storage::list_objects`
+#[derive(Debug, Clone, Default)]
+pub struct ListObjectsOptions {
+    pub prefix: String,
+    /// Keys with this after the prefix are folded into a common prefix
+    pub delimiter: Option<String>,
+    /// Inclusive lower bound to resume from, as handed out in `ObjectListing::next`
+    pub from: Option<String>,
+    pub max_keys: usize,
+    /// Look up the owner of every object listed
+    pub fetch_owner: bool,
+}
+
+/// One page of a bucket listing
+#[derive(Debug, Clone, Default)]
+pub struct ObjectListing {
+    pub objects: Vec<ObjectSummary>,
+    pub common_prefixes: Vec<String>,
+    /// Where the next page starts, `None` when this was the last one
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectSummary {
+    pub key: String,
+    pub size: u64,
+    pub etag: String,
+    pub modified_at: DateTime<Utc>,
+    /// Canonical id of the owner, from the object's ACL. Only looked up when
+    /// `ListObjectsOptions::fetch_owner` is set.
+    pub owner: Option<String>,
+}
+
+
+/// What to list from a bucket, see `Storage::list_object_versions`
+#[derive(Debug, Clone, Default)]
+pub struct ListVersionsOptions {
+    pub prefix: String,
+    /// Keys with this after the prefix are folded into a common prefix
+    pub delimiter: Option<String>,
+    /// Resume after this key, or after `version_id_marker` within it
+    pub key_marker: Option<String>,
+    pub version_id_marker: Option<String>,
+    pub max_keys: usize,
+}
+
+/// One page of the versions in a bucket, keys in order and the versions of
+/// each key newest first
+#[derive(Debug, Clone, Default)]
+pub struct VersionListing {
+    pub versions: Vec<ObjectVersion>,
+    pub common_prefixes: Vec<String>,
+    /// Key and version id of the last entry when there is another page
+    pub next: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectVersion {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub is_delete_marker: bool,
+    pub size: u64,
+    pub etag: String,
+    pub modified_at: DateTime<Utc>,
+    /// Canonical id of the owner, from the version's ACL
+    pub owner: String,
+}
+
+
+/// Outcome of a DeleteObject
+#[derive(Debug, Clone, Default)]
+pub struct DeletedObject {
+    /// Version that was removed, or of the delete marker that was written
+    pub version_id: Option<String>,
+    /// Whether that version is a delete marker
+    pub delete_marker: bool,
+}
+
+
+pub type ObjectReader = Pin<Box<dyn AsyncRead + Send>>;
+
+/// Body of an object, read from disk as the caller consumes it
+pub struct ObjectContent {
+    pub metadata: ObjectMetadata,
+    /// Inclusive first and last byte served when a range was asked for
+    pub range: Option<(u64, u64)>,
+    pub reader: ObjectReader,
+}
+
+
+/// A single byte range as in `Range: bytes=..`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    /// `bytes=start-` or `bytes=start-end`, end inclusive
+    FromTo(u64, Option<u64>),
+    /// `bytes=-n`, the last n bytes
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Inclusive bounds of the range within an object of `size` bytes, `None`
+    /// when no byte of the object falls inside it
+    pub fn resolve(&self, size: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRange::FromTo(start, _) if start >= size => None,
+            // not produced by parsing a Range header, which ignores such ranges
+            ByteRange::FromTo(start, Some(end)) if end < start => None,
+            ByteRange::FromTo(start, end) => Some((start, end.unwrap_or(u64::MAX).min(size - 1))),
+            ByteRange::Suffix(0) => None,
+            ByteRange::Suffix(_) if size == 0 => None,
+            ByteRange::Suffix(n) => Some((size.saturating_sub(n), size - 1)),
+        }
+    }
+}
+
+
+/// An in-progress multipart upload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub upload_id: String,
+    pub bucket: String,
+    pub key: String,
+    pub initiated: DateTime<Utc>,
+    /// Metadata given at initiation, applied to the completed object
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub custom_metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub acl: Option<Acl>,
+    /// Encryption of the completed object. Its parts are encrypted too while
+    /// staged, each with a key of its own.
+    #[serde(default)]
+    pub encryption: Option<ServerSideEncryption>,
+    /// Salted hash of the customer key every part and the completion need for SSE-C
+    #[serde(default)]
+    pub customer_key_hash: Option<Vec<u8>>,
+}
+
+
+/// A part uploaded to a multipart upload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartInfo {
+    pub part_number: u32,
+    pub etag: String,
+    pub size: u64,
+    pub last_modified: DateTime<Utc>,
+    /// Key of an encrypted part sealed with the master key, or for SSE-C the
+    /// salted hash of the customer key it is derived from
+    #[serde(default)]
+    pub sealed_key: Option<Vec<u8>>,
+    #[serde(default)]
+    pub customer_key_hash: Option<Vec<u8>>,
+}
+
+
+/// A part as named by the client when completing an upload
+#[derive(Debug, Clone)]
+pub struct CompletedPart {
+    pub part_number: u32,
+    pub etag: String,
+}
+
+
+/// Lifecycle rules of a bucket, applied by the expiration worker
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LifecycleConfiguration {
+    pub rules: Vec<LifecycleRule>,
+}
+
+/// One lifecycle rule. It applies to objects under `prefix` carrying all of
+/// `tags`, and takes every action that is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LifecycleRule {
+    pub id: String,
+    pub enabled: bool,
+    pub prefix: String,
+    pub tags: Vec<(String, String)>,
+    /// Expire current versions this many days after they were written
+    pub expiration_days: Option<u32>,
+    /// Remove delete markers that no other version of their key is left behind
+    pub expired_object_delete_marker: bool,
+    /// Remove versions this many days after they stopped being current...
+    pub noncurrent_days: Option<u32>,
+    /// ...except for this many of the newest ones
+    pub newer_noncurrent_versions: Option<u32>,
+    /// Abort multipart uploads this many days after they were started
+    pub abort_incomplete_upload_days: Option<u32>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_ranges_within_the_object() {
+        assert_eq!(ByteRange::FromTo(0, Some(9)).resolve(100), Some((0, 9)));
+        assert_eq!(ByteRange::FromTo(90, None).resolve(100), Some((90, 99)));
+        // an end past the object is cut to its last byte
+        assert_eq!(ByteRange::FromTo(90, Some(500)).resolve(100), Some((90, 99)));
+        assert_eq!(ByteRange::Suffix(10).resolve(100), Some((90, 99)));
+        assert_eq!(ByteRange::Suffix(500).resolve(100), Some((0, 99)));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(ByteRange::FromTo(100, None).resolve(100), None);
+        assert_eq!(ByteRange::FromTo(150, Some(200)).resolve(100), None);
+        assert_eq!(ByteRange::Suffix(0).resolve(100), None);
+
+        // nothing of an empty object can be served
+        assert_eq!(ByteRange::FromTo(0, None).resolve(0), None);
+        assert_eq!(ByteRange::FromTo(0, Some(10)).resolve(0), None);
+        assert_eq!(ByteRange::Suffix(10).resolve(0), None);
+    }
+
+    #[test]
+    fn canned_acls_grant_what_they_say() {
+        let private = Acl::private("alice");
+        assert_eq!(private.grants, [Grant { grantee: Grantee::User("alice".to_string()), permission: Permission::FullControl }]);
+
+        for permission in [Permission::Read, Permission::Write, Permission::ReadAcp, Permission::WriteAcp] {
+            assert!(private.allows(permission, Some("alice")), "{permission:?}");
+            assert!(!private.allows(permission, Some("bob")), "{permission:?}");
+            assert!(!private.allows(permission, None), "{permission:?}");
+        }
+
+        // anyone may read, only the owner write
+        let public_read = Acl::canned(CannedAcl::PublicRead, "alice", None);
+        assert!(public_read.allows(Permission::Read, None));
+        assert!(public_read.allows(Permission::Read, Some("bob")));
+        assert!(!public_read.allows(Permission::Write, None));
+        assert!(!public_read.allows(Permission::Write, Some("bob")));
+        assert!(!public_read.allows(Permission::ReadAcp, None));
+        assert!(public_read.allows(Permission::Write, Some("alice")));
+
+        let public_read_write = Acl::canned(CannedAcl::PublicReadWrite, "alice", None);
+        assert!(public_read_write.allows(Permission::Write, None));
+        assert!(!public_read_write.allows(Permission::WriteAcp, None));
+
+        let authenticated_read = Acl::canned(CannedAcl::AuthenticatedRead, "alice", None);
+        assert!(authenticated_read.allows(Permission::Read, Some("bob")));
+        assert!(!authenticated_read.allows(Permission::Read, None));
+        assert!(!authenticated_read.allows(Permission::Write, Some("bob")));
+    }
+
+    #[test]
+    fn bucket_owner_full_control_grants_the_bucket_owner() {
+        let acl = Acl::canned(CannedAcl::BucketOwnerFullControl, "bob", Some("alice"));
+        assert_eq!(acl.owner, "bob");
+
+        for permission in [Permission::Read, Permission::Write, Permission::ReadAcp, Permission::WriteAcp] {
+            assert!(acl.allows(permission, Some("alice")), "{permission:?}");
+            assert!(acl.allows(permission, Some("bob")), "{permission:?}");
+            assert!(!acl.allows(permission, Some("carol")), "{permission:?}");
+            assert!(!acl.allows(permission, None), "{permission:?}");
+        }
+
+        // the bucket owner writing to their own bucket, and bucket ACLs, get no second grant
+        assert_eq!(Acl::canned(CannedAcl::BucketOwnerFullControl, "alice", Some("alice")), Acl::private("alice"));
+        assert_eq!(Acl::canned(CannedAcl::BucketOwnerFullControl, "alice", None), Acl::private("alice"));
+    }
+
+    #[test]
+    fn owners_keep_access_to_the_acl() {
+        let acl = Acl { owner: "alice".to_string(), grants: vec![] };
+
+        assert!(acl.allows(Permission::ReadAcp, Some("alice")));
+        assert!(acl.allows(Permission::WriteAcp, Some("alice")));
+        assert!(!acl.allows(Permission::Read, Some("alice")));
+        assert!(!acl.allows(Permission::Write, Some("alice")));
+
+        let acl = Acl {
+            owner: "alice".to_string(),
+            grants: vec![Grant { grantee: Grantee::User("bob".to_string()), permission: Permission::Read }],
+        };
+        assert!(acl.allows(Permission::Read, Some("bob")));
+        assert!(!acl.allows(Permission::Write, Some("bob")));
+        assert!(!acl.allows(Permission::ReadAcp, Some("bob")));
+    }
+
+    #[test]
+    fn parses_acl_names() {
+        for name in ["private", "public-read", "public-read-write", "authenticated-read", "bucket-owner-full-control"] {
+            assert!(CannedAcl::parse(name).is_some(), "{name}");
+        }
+        assert_eq!(CannedAcl::parse("log-delivery-write"), None);
+        assert_eq!(CannedAcl::parse("Public-Read"), None);
+
+        for permission in [Permission::Read, Permission::Write, Permission::ReadAcp, Permission::WriteAcp, Permission::FullControl] {
+            assert_eq!(Permission::parse(permission.as_str()), Some(permission));
+        }
+        assert_eq!(Permission::parse("read"), None);
+    }
 }
@@ -1,7 +1,44 @@
-pub struct Validator;
+use super::{NULL_VERSION_ID, Result, Storage, StorageError};
 
 
+impl Storage {
 
-impl Validator {
+    pub(super) fn validate_bucket_name(&self, name: &str)-> Result<()> {
+        if name.len() < 3 || name.len() > 30 {
+            return Err(StorageError::InvalidBucketName("Bucket name must be between 3 and 30 characters".to_string()))
+        }
+
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(StorageError::InvalidBucketName(
+                "Bucket name can only contain alphanumeric characters, hyphens, and underscores".to_string()
+            ));
+        }
+
+        Ok(())
+    }
+
+
+    pub(super) fn validate_object_key(&self, key: &str)-> Result<()> {
+        if key.is_empty() || key.len() > 1024 {
+            return Err(StorageError::InvalidObjectKey("Object key must be between 1 and 1024 characters".to_string()))
+        }
+
+        // bodies are stored by content, a key is only a name, but XML listings can't carry NUL
+        if key.contains('\0') {
+            return Err(StorageError::InvalidObjectKey("Object key cannot contain NUL characters".to_string()));
+        }
+
+        Ok(())
+    }
+
+
+    /// Version ids are either `null` or one we handed out
+    pub(super) fn validate_version_id(&self, version_id: &str) -> Result<()> {
+        if version_id == NULL_VERSION_ID || (version_id.len() == 32 && version_id.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Ok(());
+        }
+
+        Err(StorageError::InvalidArgument("Invalid version id specified".to_string()))
+    }
 
 }